@@ -0,0 +1,56 @@
+//! Checks the consistency of a database file.
+//!
+//! Usage: `sdms-fsck <database> [--repair]`
+//!
+//! With `--repair`, damaged allocator metadata is rebuilt. Exit codes:
+//!
+//! - 0: the file is clean
+//! - 1: problems were found and repaired
+//! - 4: problems remain
+//! - 8: the check itself failed
+
+use sdms_lab_0::disk::fsck::{check, repair};
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut stdout = io::stdout();
+    let mut stderr = io::stderr();
+    let (path, fix) = match &args[..] {
+        [path] => (path, false),
+        [path, flag] if flag == "--repair" => (path, true),
+        _ => {
+            let _ = writeln!(stderr, "usage: sdms-fsck <database> [--repair]");
+            return ExitCode::from(8);
+        }
+    };
+
+    let result = if fix {
+        repair(path).map(|repaired| {
+            for action in &repaired.actions {
+                let _ = writeln!(stdout, "repaired: {action}");
+            }
+            (repaired.report, !repaired.actions.is_empty())
+        })
+    } else {
+        check(path).map(|report| (report, false))
+    };
+
+    match result {
+        Ok((report, repaired)) => {
+            let _ = write!(stdout, "{report}");
+            if !report.is_clean() {
+                ExitCode::from(4)
+            } else if repaired {
+                ExitCode::from(1)
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+        Err(err) => {
+            let _ = writeln!(stderr, "sdms-fsck: {err}");
+            ExitCode::from(8)
+        }
+    }
+}
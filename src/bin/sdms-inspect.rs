@@ -0,0 +1,199 @@
+//! Inspects a database file without modifying it.
+//!
+//! Usage: `sdms-inspect <database> [--json] <command>`
+//!
+//! Commands:
+//!
+//! - `dump <page>`: hex dump of the raw page
+//! - `header <page>`: decoded materialized page header
+//! - `free-list`: `next_free` and the free list
+//! - `diff <page> <page>`: byte ranges that differ between two pages
+
+use sdms_lab_0::PageID;
+use sdms_lab_0::disk::DiskManagerError;
+use sdms_lab_0::disk::inspect::{ByteRange, Inspector, PageHeader};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: sdms-inspect <database> [--json] (dump <page> | header <page> | free-list | diff <page> <page>)";
+
+/// Bytes per line of a hex dump.
+const BYTES_PER_LINE: usize = 16;
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let json = match args.iter().position(|arg| arg == "--json") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let mut stderr = io::stderr();
+    let Some((path, command)) = args.split_first() else {
+        let _ = writeln!(stderr, "{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let output = Inspector::open(path).and_then(|mut inspector| run(&mut inspector, command, json));
+    match output {
+        Ok(Some(output)) => {
+            let _ = write!(io::stdout(), "{output}");
+            ExitCode::SUCCESS
+        }
+        Ok(None) => {
+            let _ = writeln!(stderr, "{USAGE}");
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            let _ = writeln!(stderr, "sdms-inspect: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `command` and returns its output, `None` if the command is not valid.
+fn run(
+    inspector: &mut Inspector,
+    command: &[String],
+    json: bool,
+) -> Result<Option<String>, DiskManagerError> {
+    let page_id = |arg: &String| arg.parse().ok().map(PageID);
+    let output = match command {
+        [cmd, page] if cmd == "dump" => {
+            let Some(page_id) = page_id(page) else {
+                return Ok(None);
+            };
+            let page = inspector.page(page_id)?;
+            if json {
+                let hex: String = page.iter().map(|byte| format!("{byte:02x}")).collect();
+                format!("{{\"page_id\":{page_id},\"hex\":\"{hex}\"}}\n")
+            } else {
+                hex_dump(&page)
+            }
+        }
+        [cmd, page] if cmd == "header" => {
+            let Some(page_id) = page_id(page) else {
+                return Ok(None);
+            };
+            let header = inspector.header(page_id)?;
+            if json {
+                header_json(&header)
+            } else {
+                header_text(&header)
+            }
+        }
+        [cmd] if cmd == "free-list" => {
+            let next_free = inspector.next_free();
+            let free_list = inspector.free_list();
+            if json {
+                let ids: Vec<String> = free_list.iter().map(PageID::to_string).collect();
+                format!(
+                    "{{\"next_free\":{next_free},\"free_list\":[{}]}}\n",
+                    ids.join(",")
+                )
+            } else {
+                let mut out = format!("next_free: {next_free}\nfree pages: {}\n", free_list.len());
+                for page_id in free_list {
+                    let _ = writeln!(out, "  {page_id}");
+                }
+                out
+            }
+        }
+        [cmd, a, b] if cmd == "diff" => {
+            let (Some(a), Some(b)) = (page_id(a), page_id(b)) else {
+                return Ok(None);
+            };
+            let ranges = inspector.diff(a, b)?;
+            if json {
+                diff_json(a, b, &ranges)
+            } else {
+                let pages = (inspector.page(a)?, inspector.page(b)?);
+                diff_text(a, b, &ranges, &pages.0, &pages.1)
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(output))
+}
+
+/// Formats `bytes` as offset, hex bytes and printable ASCII, [`BYTES_PER_LINE`] bytes per line.
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(out, "{:08x} ", line * BYTES_PER_LINE);
+        for byte in chunk {
+            let _ = write!(out, " {byte:02x}");
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let _ = writeln!(out, "  |{ascii}|");
+    }
+    out
+}
+
+fn header_text(header: &PageHeader) -> String {
+    let checksum = match (header.checksum, header.checksum_matches) {
+        (Some(checksum), Some(true)) => format!("{checksum:08x} (ok)"),
+        (Some(checksum), _) => format!("{checksum:08x} (MISMATCH)"),
+        (None, _) => "none".to_string(),
+    };
+    format!(
+        "page:           {}\nstate:          {:?}\nstored page id: {}\nused bytes:     {}\nchecksum:       {checksum}\n",
+        header.page_id, header.state, header.stored_page_id, header.used_bytes
+    )
+}
+
+fn header_json(header: &PageHeader) -> String {
+    let checksum = header
+        .checksum
+        .map_or("null".to_string(), |checksum| checksum.to_string());
+    let matches = header
+        .checksum_matches
+        .map_or("null".to_string(), |matches| matches.to_string());
+    format!(
+        "{{\"page_id\":{},\"state\":\"{:?}\",\"stored_page_id\":{},\"used_bytes\":{},\"checksum\":{checksum},\"checksum_matches\":{matches}}}\n",
+        header.page_id, header.state, header.stored_page_id, header.used_bytes
+    )
+}
+
+fn diff_text(a: PageID, b: PageID, ranges: &[ByteRange], page_a: &[u8], page_b: &[u8]) -> String {
+    let differing: usize = ranges.iter().map(|range| range.len).sum();
+    let mut out = format!(
+        "pages {a} and {b}: {differing} bytes differ in {} ranges\n",
+        ranges.len()
+    );
+    for range in ranges {
+        let bytes = range.offset..range.offset + range.len;
+        let hex = |page: &[u8]| -> String {
+            page[bytes.clone()]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect()
+        };
+        let _ = writeln!(out, "@{:#06x} +{}", range.offset, range.len);
+        let _ = writeln!(out, "  {a}: {}", hex(page_a));
+        let _ = writeln!(out, "  {b}: {}", hex(page_b));
+    }
+    out
+}
+
+fn diff_json(a: PageID, b: PageID, ranges: &[ByteRange]) -> String {
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|range| format!("{{\"offset\":{},\"len\":{}}}", range.offset, range.len))
+        .collect();
+    format!(
+        "{{\"a\":{a},\"b\":{b},\"ranges\":[{}]}}\n",
+        ranges.join(",")
+    )
+}
@@ -0,0 +1,39 @@
+//! Restores a database file from a full backup and a chain of incremental backups.
+//!
+//! Usage: `sdms-restore <target> <full-backup> [incremental-backup...]`
+//!
+//! The incremental backups are applied in the given order.
+
+use sdms_lab_0::disk::incremental::restore;
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut stderr = io::stderr();
+    if args.len() < 2 {
+        let _ = writeln!(
+            stderr,
+            "usage: sdms-restore <target> <full-backup> [incremental-backup...]"
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let incrementals: Vec<&str> = args[2..].iter().map(String::as_str).collect();
+    match restore(&args[0], &args[1], &incrementals) {
+        Ok(dm) => {
+            let _ = writeln!(
+                io::stdout(),
+                "restored {} ({} pages, backup epoch {})",
+                args[0],
+                dm.next_free().0 - 1,
+                dm.backup_epoch()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            let _ = writeln!(stderr, "sdms-restore: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
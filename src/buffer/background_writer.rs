@@ -0,0 +1,142 @@
+//! Background writer
+//!
+//! A miss in [`ConcurrentBufferManager::pin_shared`] or
+//! [`ConcurrentBufferManager::pin_exclusive`] that evicts a dirty page has to write it before
+//! reading the new one. The [`BackgroundWriter`] writes unpinned dirty pages ahead of time from its
+//! own thread, starting with those the replacement strategy will pick next, see
+//! [`SharedReplacementStrategyTrait::eviction_order`]. Written pages stay in the pool, clean.
+//!
+//! Every [`BackgroundWriterConfig::interval`], the writer checks the share of dirty frames. Once
+//! it reaches [`BackgroundWriterConfig::dirty_ratio`], it writes up to
+//! [`BackgroundWriterConfig::max_pages`] pages.
+
+use crate::buffer::concurrent::{ConcurrentBufferManager, SharedReplacementStrategyTrait};
+use crate::buffer::{BufferManagerError, DiskManagerTrait};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Settings of a [`BackgroundWriter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackgroundWriterConfig {
+    /// Time between two rounds.
+    pub interval: Duration,
+    /// Maximum number of pages written per round.
+    pub max_pages: usize,
+    /// Share of dirty frames, between 0 and 1, from which on the writer writes pages.
+    pub dirty_ratio: f64,
+}
+
+impl Default for BackgroundWriterConfig {
+    fn default() -> Self {
+        BackgroundWriterConfig {
+            interval: Duration::from_millis(100),
+            max_pages: 64,
+            dirty_ratio: 0.1,
+        }
+    }
+}
+
+/// Handle of a running background writer thread. Stops and joins the thread on drop.
+#[derive(Debug)]
+pub struct BackgroundWriter {
+    /// Dropping the sender wakes up and stops the thread.
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    /// Number of pages written so far.
+    pages_written: Arc<AtomicU64>,
+    /// Number of failed writes so far.
+    write_errors: Arc<AtomicU64>,
+    /// The most recent failed write, until it is taken.
+    last_error: Arc<Mutex<Option<BufferManagerError>>>,
+}
+
+impl BackgroundWriter {
+    /// Number of pages the writer has written so far.
+    pub fn pages_written(&self) -> u64 {
+        self.pages_written.load(Ordering::Acquire)
+    }
+
+    /// Number of writes that have failed so far. The pages stay dirty.
+    pub fn write_errors(&self) -> u64 {
+        self.write_errors.load(Ordering::Acquire)
+    }
+
+    /// Takes the error of the most recent failed write, if any, see
+    /// [`BackgroundWriter::write_errors`].
+    pub fn take_last_error(&self) -> Option<BufferManagerError> {
+        self.last_error.lock().unwrap().take()
+    }
+
+    /// Stops the writer and waits until its thread has finished, which dropping it does.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        self.stop = None;
+        if let Some(thread) = self.thread.take() {
+            // A panic of the writer thread has already been reported.
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<D, S> ConcurrentBufferManager<D, S>
+where
+    D: DiskManagerTrait + Send + 'static,
+    S: SharedReplacementStrategyTrait + 'static,
+{
+    /// Starts a thread that writes dirty pages ahead of eviction according to `config`.
+    ///
+    /// The thread keeps the buffer manager alive until the returned handle is dropped. Failed
+    /// writes are recorded in the handle, see [`BackgroundWriter::write_errors`], and the pages
+    /// stay dirty, so eviction writes them again.
+    pub fn start_background_writer(
+        self: &Arc<Self>,
+        config: BackgroundWriterConfig,
+    ) -> BackgroundWriter {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let pages_written = Arc::new(AtomicU64::new(0));
+        let write_errors = Arc::new(AtomicU64::new(0));
+        let last_error = Arc::new(Mutex::new(None));
+        let buffer_manager = Arc::clone(self);
+        let counter = Arc::clone(&pages_written);
+        let error_counter = Arc::clone(&write_errors);
+        let error_slot = Arc::clone(&last_error);
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(config.interval) {
+                if buffer_manager.dirty_ratio() < config.dirty_ratio {
+                    continue;
+                }
+                let mut written = 0;
+                for frame in buffer_manager.dirty_eviction_candidates() {
+                    if written >= config.max_pages {
+                        break;
+                    }
+                    match buffer_manager.clean_frame(frame) {
+                        Ok(true) => written += 1,
+                        Ok(false) => {}
+                        Err(err) => {
+                            error_counter.fetch_add(1, Ordering::AcqRel);
+                            *error_slot.lock().unwrap() = Some(err);
+                        }
+                    }
+                }
+                counter.fetch_add(written as u64, Ordering::AcqRel);
+            }
+        });
+
+        BackgroundWriter {
+            stop: Some(stop),
+            thread: Some(thread),
+            pages_written,
+            write_errors,
+            last_error,
+        }
+    }
+}
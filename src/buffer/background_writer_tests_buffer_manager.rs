@@ -0,0 +1,144 @@
+// Tests for the background writer
+
+#[cfg(test)]
+mod background_writer {
+    use crate::buffer::background_writer::*;
+    use crate::buffer::concurrent::*;
+    use crate::buffer::*;
+    use crate::{BUFFER_POOL_SIZE, PageID};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    type Manager = ConcurrentBufferManager<RecordingDisk, SharedLRUReplacementStrategy>;
+
+    fn buffer_manager(pages: usize) -> (Arc<Mutex<RecordingDisk>>, Arc<Manager>) {
+        let disk = Arc::new(Mutex::new(RecordingDisk::new(pages)));
+        let buffer_manager = ConcurrentBufferManager::new(disk.clone(), Default::default());
+        (disk, Arc::new(buffer_manager))
+    }
+
+    /// Pins the pages in the given order and dirties them, with their PageID as contents.
+    fn dirty(buffer_manager: &Manager, pages: &[usize]) {
+        for &i in pages {
+            buffer_manager.pin_exclusive(PageID(i)).unwrap().1[0] = i as u8;
+        }
+    }
+
+    fn config(dirty_ratio: f64, max_pages: usize) -> BackgroundWriterConfig {
+        BackgroundWriterConfig {
+            interval: Duration::from_millis(5),
+            max_pages,
+            dirty_ratio,
+        }
+    }
+
+    /// Waits until the writer has written `pages` pages.
+    fn wait_for(writer: &BackgroundWriter, pages: u64) {
+        let start = Instant::now();
+        while writer.pages_written() < pages {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "background writer did not write {pages} pages"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn writes_unpinned_dirty_pages() {
+        let (disk, buffer_manager) = buffer_manager(5);
+        dirty(&buffer_manager, &[1, 2, 3, 4]);
+
+        let writer = buffer_manager.start_background_writer(config(0.0, 64));
+        wait_for(&writer, 4);
+        writer.stop();
+
+        assert_eq!(buffer_manager.dirty_ratio(), 0.0);
+        let disk = disk.lock().unwrap();
+        for i in 1..=4 {
+            assert_eq!(disk.inner.pages[i].1[0], i as u8);
+        }
+        // Written pages stay in the pool.
+        assert!((1..=4).all(|i| buffer_manager.frame_of(PageID(i)).is_some()));
+    }
+
+    #[test]
+    fn waits_for_dirty_ratio() {
+        let (disk, buffer_manager) = buffer_manager(3);
+        dirty(&buffer_manager, &[1, 2]);
+        let threshold = 3.0 / BUFFER_POOL_SIZE as f64;
+
+        let writer = buffer_manager.start_background_writer(config(threshold, 64));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(writer.pages_written(), 0);
+        assert!(disk.lock().unwrap().writes.is_empty());
+        assert!(buffer_manager.frame_of(PageID(1)).unwrap().is_dirty());
+    }
+
+    #[test]
+    fn skips_pinned_pages() {
+        let (disk, buffer_manager) = buffer_manager(3);
+        dirty(&buffer_manager, &[1, 2]);
+        let pinned = buffer_manager.pin_shared(PageID(1)).unwrap();
+
+        let writer = buffer_manager.start_background_writer(config(0.0, 64));
+        wait_for(&writer, 1);
+        thread::sleep(Duration::from_millis(20));
+        writer.stop();
+
+        assert_eq!(disk.lock().unwrap().writes, vec![PageID(2)]);
+        assert!(buffer_manager.frame_of(PageID(1)).unwrap().is_dirty());
+        drop(pinned);
+    }
+
+    #[test]
+    fn writes_in_eviction_order() {
+        let (disk, buffer_manager) = buffer_manager(4);
+        dirty(&buffer_manager, &[1, 2, 3]);
+        // Page 1 becomes the most recently used one.
+        drop(buffer_manager.pin_shared(PageID(1)).unwrap());
+
+        let writer = buffer_manager.start_background_writer(config(0.0, 1));
+        wait_for(&writer, 3);
+        writer.stop();
+
+        assert_eq!(
+            disk.lock().unwrap().writes,
+            vec![PageID(2), PageID(3), PageID(1)]
+        );
+    }
+
+    #[test]
+    fn records_failed_writes() {
+        let (disk, buffer_manager) = buffer_manager(3);
+        disk.lock().unwrap().failing = Some(PageID(1));
+        dirty(&buffer_manager, &[1, 2]);
+
+        let writer = buffer_manager.start_background_writer(config(0.0, 64));
+        wait_for(&writer, 1);
+        let start = Instant::now();
+        while writer.write_errors() == 0 {
+            assert!(start.elapsed() < Duration::from_secs(10), "no failed write");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(matches!(
+            writer.take_last_error(),
+            Some(BufferManagerError::IOError(_))
+        ));
+        writer.stop();
+        assert_eq!(disk.lock().unwrap().writes, vec![PageID(2)]);
+        assert!(buffer_manager.frame_of(PageID(1)).unwrap().is_dirty());
+    }
+
+    #[test]
+    fn drop_stops_the_thread() {
+        let (_disk, buffer_manager) = buffer_manager(2);
+        let writer = buffer_manager.start_background_writer(BackgroundWriterConfig::default());
+        assert_eq!(Arc::strong_count(&buffer_manager), 2);
+
+        drop(writer);
+        assert_eq!(Arc::strong_count(&buffer_manager), 1);
+    }
+}
@@ -1,6 +1,8 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::collections::{HashMap, VecDeque};
+use std::{cell::RefCell, mem, rc::Rc};
 
 use crate::buffer::frame_pool::FramePool;
+use crate::buffer::guard::PageWriteGuard;
 use crate::buffer::*;
 use crate::{BUFFER_POOL_SIZE, FrameID, PageID};
 
@@ -18,13 +20,20 @@ pub struct FrameDescriptor {
     /// [BufferManager::unpin].
     pub dirty: bool,
     // Add new members here, but do not remove the members above.
+    /// Logical time of the last pin, used by [`LRUReplacementStrategy`].
+    pub last_used: u64,
+    /// Set on pin and cleared by the clock hand, used by [`ClockReplacementStrategy`].
+    pub referenced: bool,
+    /// Logical times of the last uncorrelated pins, most recent first, used by
+    /// [`LRUKReplacementStrategy`].
+    pub history: VecDeque<u64>,
 }
 
 /// Abstracts storage from higher database operations and caches pages from persistent storage.
 ///
-/// Caches Pages from the `DiskManager` in a pool of [`BUFFER_POOL_SIZE`] frames. To lookup pages
-/// that are loaded in the buffer manager, the [`BufferManager::page_table`] maps [`PageID`]s to
-/// frames.
+/// Caches Pages from the `DiskManager` in a pool of [`BUFFER_POOL_SIZE`] frames, or as many as
+/// given to [`BufferManager::with_capacity`]. To lookup pages that are loaded in the buffer
+/// manager, the [`BufferManager::page_table`] maps [`PageID`]s to frames.
 ///
 /// The metadata for each frame is stored in a [`FrameDescriptor`] instance. This is mainly used
 /// for the [`ReplacementStrategy`] to evict pages, but also to store the dirty bit and the pin
@@ -63,6 +72,9 @@ pub struct BufferManager<
     #[allow(unused)]
     pub last_evict: PageID,
     // Add new members here, but do not remove the ones above.
+    /// Frames emptied by [`BufferManager::delete_page`], reused before the replacement strategy
+    /// is asked.
+    free_frames: Vec<FrameID>,
 }
 
 /// Struct that can hold state required for LRU replacement.
@@ -70,6 +82,8 @@ pub struct BufferManager<
 #[derive(Default)]
 pub struct LRUReplacementStrategy {
     // you can add members here
+    /// Logical clock, incremented on every pin.
+    time: u64,
 }
 
 /// Implement the LRU replacement strategy in [LRUReplacementStrategy::replace].
@@ -86,11 +100,16 @@ impl ReplacementStrategyTrait for LRUReplacementStrategy {
         &mut self,
         fds: &mut FramePool<FrameDescriptor>,
     ) -> Result<PageID, BufferManagerError> {
-        todo!()
+        fds.iter()
+            .filter(|fd| fd.pin_count == 0)
+            .min_by_key(|fd| fd.last_used)
+            .map(|fd| fd.page_id)
+            .ok_or(BufferManagerError::AllPagesPinned)
     }
 
     fn on_pin(&mut self, frame_descriptor: &mut FrameDescriptor) {
-        todo!()
+        self.time += 1;
+        frame_descriptor.last_used = self.time;
     }
 }
 
@@ -99,6 +118,8 @@ impl ReplacementStrategyTrait for LRUReplacementStrategy {
 #[derive(Default)]
 pub struct ClockReplacementStrategy {
     // you can add members here
+    /// Frame the clock hand points to.
+    hand: usize,
 }
 
 /// Implement the CLOCK replacement strategy in [ClockReplacementStrategy::replace].
@@ -115,11 +136,106 @@ impl ReplacementStrategyTrait for ClockReplacementStrategy {
         &mut self,
         fds: &mut FramePool<FrameDescriptor>,
     ) -> Result<PageID, BufferManagerError> {
-        todo!()
+        // The first round clears reference bits, so the second round finds a victim unless all
+        // pages are pinned.
+        let frames = fds.len();
+        for _ in 0..2 * frames {
+            let frame = self.hand % frames;
+            self.hand = (frame + 1) % frames;
+            let fd = &mut fds[FrameID(frame)];
+            if fd.pin_count > 0 {
+                continue;
+            }
+            if !fd.referenced {
+                return Ok(fd.page_id);
+            }
+            fd.referenced = false;
+        }
+        Err(BufferManagerError::AllPagesPinned)
     }
 
     fn on_pin(&mut self, frame_descriptor: &mut FrameDescriptor) {
-        todo!()
+        frame_descriptor.referenced = true;
+    }
+}
+
+/// LRU-K replacement (O'Neil, O'Neil and Weikum, 1993).
+///
+/// Evicts the page with the largest backward K-distance, the time since its K-th most recent
+/// pin. Pages pinned fewer than K times have an infinite distance and go first, in LRU order, so
+/// pages touched once by a scan do not push out frequently used ones.
+///
+/// Pins within the correlated reference period after the previous pin of a page, e.g. a read
+/// followed by an update, count as a single reference. Pages in that period are only evicted if
+/// no other page is unpinned.
+pub struct LRUKReplacementStrategy {
+    /// Number of pins remembered per page.
+    k: usize,
+    /// Maximum distance in pins between correlated pins of a page.
+    correlated_period: u64,
+    /// Logical clock, incremented on every pin.
+    time: u64,
+}
+
+impl LRUKReplacementStrategy {
+    /// Creates an LRU-`k` strategy with a correlated reference period of `correlated_period`
+    /// pins.
+    ///
+    /// # Panics
+    /// Panics if `k` is zero.
+    pub fn new(k: usize, correlated_period: u64) -> Self {
+        assert!(k > 0, "LRU-K needs at least one pin per page");
+        LRUKReplacementStrategy {
+            k,
+            correlated_period,
+            time: 0,
+        }
+    }
+}
+
+/// LRU-2 without a correlated reference period.
+impl Default for LRUKReplacementStrategy {
+    fn default() -> Self {
+        Self::new(2, 0)
+    }
+}
+
+impl ReplacementStrategyTrait for LRUKReplacementStrategy {
+    fn replace(
+        &mut self,
+        fds: &mut FramePool<FrameDescriptor>,
+    ) -> Result<PageID, BufferManagerError> {
+        // The K-th most recent pin, 0 for pages pinned fewer than K times, then the last pin.
+        let age = |fd: &&FrameDescriptor| {
+            let kth = fd.history.get(self.k - 1).copied().unwrap_or(0);
+            (kth, fd.last_used)
+        };
+        let unpinned = || fds.iter().filter(|fd| fd.pin_count == 0);
+        // The pin that needs the frame happens at `self.time + 1`.
+        unpinned()
+            .filter(|fd| self.time + 1 - fd.last_used > self.correlated_period)
+            .min_by_key(age)
+            .or_else(|| unpinned().min_by_key(age))
+            .map(|fd| fd.page_id)
+            .ok_or(BufferManagerError::AllPagesPinned)
+    }
+
+    fn on_pin(&mut self, frame_descriptor: &mut FrameDescriptor) {
+        self.time += 1;
+        let history = &mut frame_descriptor.history;
+        let correlated =
+            !history.is_empty() && self.time - frame_descriptor.last_used <= self.correlated_period;
+        if !correlated {
+            if let Some(&first) = history.front() {
+                // The correlated pins since `first` count as one, so older pins move forward by
+                // the length of that period.
+                let period = frame_descriptor.last_used - first;
+                history.iter_mut().for_each(|time| *time += period);
+            }
+            history.push_front(self.time);
+            history.truncate(self.k);
+        }
+        frame_descriptor.last_used = self.time;
     }
 }
 
@@ -135,7 +251,325 @@ impl<DiskManager: DiskManagerTrait, ReplacementStrategy: ReplacementStrategyTrai
         disk_manager: Rc<RefCell<DiskManager>>,
         replacement_strat: ReplacementStrategy,
     ) -> Self {
-        todo!()
+        Self::with_capacity(disk_manager, replacement_strat, BUFFER_POOL_SIZE)
+    }
+
+    /// Instantiates a new [`BufferManager`] like [`BufferManager::new`], with `frames` frames
+    /// instead of [`BUFFER_POOL_SIZE`].
+    ///
+    /// # Panics
+    /// Panics if `frames` is zero.
+    pub fn with_capacity(
+        disk_manager: Rc<RefCell<DiskManager>>,
+        replacement_strat: ReplacementStrategy,
+        frames: usize,
+    ) -> Self {
+        assert!(frames > 0, "a buffer pool needs at least one frame");
+        let frame_descriptors = (0..frames)
+            .map(|_| FrameDescriptor::default())
+            .collect::<Vec<_>>();
+        let pool = (0..frames)
+            .map(|_| MaterializedPage::default())
+            .collect::<Vec<_>>();
+
+        BufferManager {
+            disk_manager,
+            replacement_strat,
+            buffer_count: 0,
+            page_table: HashMap::new(),
+            frame_descriptors: FramePool::new(frame_descriptors.into_boxed_slice()),
+            pool: FramePool::new(pool.into_boxed_slice()),
+            last_evict: PageID(0),
+            free_frames: Vec::new(),
+        }
+    }
+
+    /// Pins `page_id` and returns the frame holding it, loading the page if needed.
+    ///
+    /// # Errors
+    /// See [`BufferManager::pin`].
+    pub(crate) fn pin_frame(&mut self, page_id: PageID) -> Result<FrameID, BufferManagerError> {
+        if page_id.0 == 0 {
+            return Err(BufferManagerError::InvalidPageID(page_id));
+        }
+        let frame = match self.page_table.get(&page_id) {
+            Some(&frame) => frame,
+            None => self.load(page_id)?,
+        };
+        let frame_descriptor = &mut self.frame_descriptors[frame];
+        frame_descriptor.pin_count += 1;
+        self.replacement_strat.on_pin(frame_descriptor);
+        Ok(frame)
+    }
+
+    /// Reads `page_id` into an empty frame, or into the frame of a page picked by the replacement
+    /// strategy. The returned frame is not pinned yet.
+    ///
+    /// The evicted page is written back first if it is dirty. It stays loaded if reading
+    /// `page_id` fails.
+    fn load(&mut self, page_id: PageID) -> Result<FrameID, BufferManagerError> {
+        let frame = self.claim_frame()?;
+        let mut page = MaterializedPage::new(page_id);
+        self.disk_manager.borrow_mut().read(page_id, &mut page)?;
+        self.install(frame, page_id, page, false);
+        Ok(frame)
+    }
+
+    /// Allocates a new page on disk and returns it pinned, zeroed and dirty, so it is written on
+    /// eviction even if the caller does not change it.
+    ///
+    /// # Errors
+    /// - Propagates errors of [`DiskManagerTrait::allocate`].
+    /// - Returns [`BufferManagerError::AllPagesPinned`] if all pages are pinned. The page is
+    ///   freed again then.
+    pub fn new_page(
+        &mut self,
+    ) -> Result<PageWriteGuard<'_, DiskManager, ReplacementStrategy>, BufferManagerError> {
+        let page_id = self.disk_manager.borrow_mut().allocate()?;
+        let frame = match self.claim_frame() {
+            Ok(frame) => frame,
+            Err(err) => {
+                self.disk_manager.borrow_mut().free(page_id)?;
+                return Err(err);
+            }
+        };
+        self.install(frame, page_id, MaterializedPage::new(page_id), true);
+        self.pin_write(page_id)
+    }
+
+    /// Drops `page_id` from the buffer pool without writing it back and frees it on disk.
+    ///
+    /// # Errors
+    /// - Returns [`BufferManagerError::PagePinned`] if the page is pinned.
+    /// - Propagates errors of [`DiskManagerTrait::free`]. The page stays loaded then.
+    pub fn delete_page(&mut self, page_id: PageID) -> Result<(), BufferManagerError> {
+        let frame = self.page_table.get(&page_id).copied();
+        if let Some(frame) = frame
+            && self.frame_descriptors[frame].pin_count > 0
+        {
+            return Err(BufferManagerError::PagePinned(page_id));
+        }
+        self.disk_manager.borrow_mut().free(page_id)?;
+
+        if let Some(frame) = frame {
+            self.page_table.remove(&page_id);
+            self.frame_descriptors[frame] = FrameDescriptor::default();
+            self.pool[frame] = MaterializedPage::default();
+            self.free_frames.push(frame);
+            self.buffer_count -= 1;
+        }
+        Ok(())
+    }
+
+    /// Grows or shrinks the pool to `frames` frames.
+    ///
+    /// Shrinking moves pinned pages, and as many unpinned pages as fit, from the removed frames
+    /// into empty frames. If pinned pages do not fit, pages picked by the replacement strategy
+    /// make room for them. Remaining unpinned pages of the removed frames are evicted. Evicted
+    /// pages are written back if they are dirty.
+    ///
+    /// # Errors
+    /// The pool keeps its size if an error is returned.
+    /// - Returns [`BufferManagerError::AllPagesPinned`] if more than `frames` pages are pinned.
+    /// - Propagates errors of [`DiskManagerTrait::write`]. Pages written so far stay loaded,
+    ///   but clean.
+    ///
+    /// # Panics
+    /// Panics if `frames` is zero.
+    pub fn resize(&mut self, frames: usize) -> Result<(), BufferManagerError> {
+        assert!(frames > 0, "a buffer pool needs at least one frame");
+        if frames < self.pool.len() {
+            self.evacuate(frames)?;
+        }
+
+        let mut frame_descriptors: Vec<_> =
+            self.frame_descriptors.iter_mut().map(mem::take).collect();
+        let mut pool: Vec<_> = self.pool.iter_mut().map(mem::take).collect();
+        frame_descriptors.resize_with(frames, FrameDescriptor::default);
+        pool.resize_with(frames, MaterializedPage::default);
+
+        // Empty frames are handed out lowest first.
+        self.free_frames = (0..frames)
+            .rev()
+            .filter(|&frame| frame_descriptors[frame].page_id == PageID(0))
+            .map(FrameID)
+            .collect();
+        self.buffer_count = frames - self.free_frames.len();
+        self.frame_descriptors = FramePool::new(frame_descriptors.into_boxed_slice());
+        self.pool = FramePool::new(pool.into_boxed_slice());
+        Ok(())
+    }
+
+    /// Empties the frames from `frames` on, see [`BufferManager::resize`].
+    fn evacuate(&mut self, frames: usize) -> Result<(), BufferManagerError> {
+        let occupied = |fd: &FrameDescriptor| fd.page_id != PageID(0);
+        let (pinned, unpinned): (Vec<FrameID>, Vec<FrameID>) = (frames..self.pool.len())
+            .map(FrameID)
+            .filter(|&frame| occupied(&self.frame_descriptors[frame]))
+            .partition(|&frame| self.frame_descriptors[frame].pin_count > 0);
+        let mut targets: Vec<FrameID> = (0..frames)
+            .map(FrameID)
+            .filter(|&frame| !occupied(&self.frame_descriptors[frame]))
+            .collect();
+
+        let missing = pinned.len().saturating_sub(targets.len());
+        if missing > 0 {
+            let evictable = (0..frames)
+                .map(FrameID)
+                .filter(|&frame| {
+                    let fd = &self.frame_descriptors[frame];
+                    occupied(fd) && fd.pin_count == 0
+                })
+                .count();
+            if evictable < missing {
+                return Err(BufferManagerError::AllPagesPinned);
+            }
+            let victims = self.pick_victims(frames, &targets, missing);
+            targets.extend(victims);
+        }
+
+        // Write back everything that is evicted before changing the pool, so errors leave it
+        // intact.
+        let moved = targets.len().saturating_sub(pinned.len());
+        let evicted: Vec<FrameID> = unpinned.iter().skip(moved).copied().collect();
+        let victims = &targets[targets.len() - missing..];
+        for &frame in victims.iter().chain(&evicted) {
+            self.write_back(frame)?;
+        }
+
+        for &frame in victims.iter().chain(&evicted) {
+            let page_id = mem::take(&mut self.frame_descriptors[frame]).page_id;
+            self.page_table.remove(&page_id);
+            self.last_evict = page_id;
+        }
+        for (&from, &to) in pinned.iter().chain(&unpinned).zip(&targets) {
+            self.frame_descriptors[to] = mem::take(&mut self.frame_descriptors[from]);
+            self.pool[to] = mem::take(&mut self.pool[from]);
+            self.page_table
+                .insert(self.frame_descriptors[to].page_id, to);
+        }
+        Ok(())
+    }
+
+    /// Asks the replacement strategy for `count` occupied frames below `frames` to evict. The
+    /// frames from `frames` on and the `empty` ones are hidden from it by pinning them
+    /// temporarily.
+    fn pick_victims(&mut self, frames: usize, empty: &[FrameID], count: usize) -> Vec<FrameID> {
+        let mut hidden: Vec<FrameID> = (frames..self.pool.len()).map(FrameID).collect();
+        hidden.extend(empty);
+        for &frame in &hidden {
+            self.frame_descriptors[frame].pin_count += 1;
+        }
+        let mut victims = Vec::with_capacity(count);
+        while victims.len() < count {
+            // The caller checked that enough unpinned frames are left.
+            let victim = self
+                .replacement_strat
+                .replace(&mut self.frame_descriptors)
+                .expect("enough unpinned frames");
+            let frame = self.page_table[&victim];
+            self.frame_descriptors[frame].pin_count += 1;
+            victims.push(frame);
+        }
+        for &frame in hidden.iter().chain(&victims) {
+            self.frame_descriptors[frame].pin_count -= 1;
+        }
+        victims
+    }
+
+    /// Picks the frame for a new page: an empty one, or the frame of a page picked by the
+    /// replacement strategy after writing it back. The evicted page stays in the page table until
+    /// [`BufferManager::install`] replaces it.
+    fn claim_frame(&mut self) -> Result<FrameID, BufferManagerError> {
+        if let Some(&frame) = self.free_frames.last() {
+            return Ok(frame);
+        }
+        if self.buffer_count < self.pool.len() {
+            return Ok(FrameID(self.buffer_count));
+        }
+        let victim = self
+            .replacement_strat
+            .replace(&mut self.frame_descriptors)?;
+        let frame = self.page_table[&victim];
+        self.write_back(frame)?;
+        Ok(frame)
+    }
+
+    /// Puts `page_id` with contents `page` into `frame` returned by
+    /// [`BufferManager::claim_frame`], evicting the page it held. The page is not pinned yet.
+    fn install(&mut self, frame: FrameID, page_id: PageID, page: MaterializedPage, dirty: bool) {
+        if self.free_frames.last() == Some(&frame) {
+            self.free_frames.pop();
+            self.buffer_count += 1;
+        } else if frame.0 == self.buffer_count {
+            self.buffer_count += 1;
+        } else {
+            let victim = self.frame_descriptors[frame].page_id;
+            self.page_table.remove(&victim);
+            self.last_evict = victim;
+        }
+        self.pool[frame] = page;
+        self.frame_descriptors[frame] = FrameDescriptor {
+            page_id,
+            dirty,
+            ..FrameDescriptor::default()
+        };
+        self.page_table.insert(page_id, frame);
+    }
+
+    /// Writes `page_id` to disk if it is loaded and dirty, and clears its dirty bit.
+    ///
+    /// Pinned pages are written as well, with their current contents.
+    ///
+    /// # Errors
+    /// Propagates errors of [`DiskManagerTrait::write`]. The page stays dirty then.
+    pub fn flush_page(&mut self, page_id: PageID) -> Result<(), BufferManagerError> {
+        match self.page_table.get(&page_id) {
+            Some(&frame) => self.write_back(frame),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes all dirty pages to disk in ascending [`PageID`] order, so write-back is sequential,
+    /// and clears their dirty bits.
+    ///
+    /// # Errors
+    /// Stops at the first error of [`DiskManagerTrait::write`] and returns it. The remaining
+    /// pages stay dirty.
+    pub fn flush_all(&mut self) -> Result<(), BufferManagerError> {
+        let mut dirty: Vec<(PageID, FrameID)> = self
+            .page_table
+            .iter()
+            .filter(|&(_, &frame)| self.frame_descriptors[frame].dirty)
+            .map(|(&page_id, &frame)| (page_id, frame))
+            .collect();
+        dirty.sort_unstable();
+        for (_, frame) in dirty {
+            self.write_back(frame)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes all dirty pages and drops the buffer manager.
+    ///
+    /// Unlike dropping it, which flushes as well but ignores errors, this reports them.
+    ///
+    /// # Errors
+    /// See [`BufferManager::flush_all`].
+    pub fn shutdown(mut self) -> Result<(), BufferManagerError> {
+        self.flush_all()
+    }
+
+    /// Writes the page in `frame` to disk if it is dirty and clears the dirty bit.
+    fn write_back(&mut self, frame: FrameID) -> Result<(), BufferManagerError> {
+        let frame_descriptor = &mut self.frame_descriptors[frame];
+        if frame_descriptor.dirty {
+            self.disk_manager
+                .borrow_mut()
+                .write(frame_descriptor.page_id, &self.pool[frame])?;
+            frame_descriptor.dirty = false;
+        }
+        Ok(())
     }
 }
 
@@ -158,7 +592,8 @@ impl<DiskManager: DiskManagerTrait, ReplacementStrategy: ReplacementStrategyTrai
     /// - Propagates errors from [`DiskManager::write`] and [`DiskManager::read`].
     /// - If all pages are pinned at least once, [`BufferManagerError::AllPagesPinned`] is returned.
     fn pin(&mut self, pid: PageID) -> Result<&mut MaterializedPage, BufferManagerError> {
-        todo!()
+        let frame = self.pin_frame(pid)?;
+        Ok(&mut self.pool[frame])
     }
 
     /// Unpins page *pid*.
@@ -173,6 +608,58 @@ impl<DiskManager: DiskManagerTrait, ReplacementStrategy: ReplacementStrategyTrai
     /// # Panics
     /// May panic if page ID of `page` is not loaded in [`BufferManager`].
     fn unpin(&mut self, page_id: PageID, dirty: bool) {
-        todo!()
+        let frame = *self
+            .page_table
+            .get(&page_id)
+            .unwrap_or_else(|| panic!("page {page_id} is not loaded"));
+        let frame_descriptor = &mut self.frame_descriptors[frame];
+        frame_descriptor.pin_count = frame_descriptor.pin_count.saturating_sub(1);
+        frame_descriptor.dirty |= dirty;
+    }
+}
+
+/// Size of the header of a [`MaterializedPage`] stored by a [`crate::disk::DiskManager`].
+const HEADER_SIZE: usize = crate::PAGE_SIZE - DATA_SIZE;
+
+/// Stores [`MaterializedPage`]s in a [`crate::disk::DiskManager`], with the PageID as header in
+/// front of the data. Its errors become [`BufferManagerError`]s through the `From` implementation
+/// of [`BufferManagerError`].
+impl DiskManagerTrait for crate::disk::DiskManager {
+    fn read(
+        &mut self,
+        page_id: PageID,
+        buf: &mut MaterializedPage,
+    ) -> Result<(), DiskManagerError> {
+        let mut page = [0u8; crate::PAGE_SIZE];
+        crate::disk::DiskManager::read(self, page_id, &mut page)?;
+        // Pages that were never written have no header, so the requested PageID is used.
+        buf.0 = page_id;
+        buf.1.copy_from_slice(&page[HEADER_SIZE..]);
+        Ok(())
+    }
+
+    fn write(&mut self, page_id: PageID, buf: &MaterializedPage) -> Result<(), DiskManagerError> {
+        let mut page = [0u8; crate::PAGE_SIZE];
+        page[..HEADER_SIZE].copy_from_slice(&buf.0.0.to_le_bytes());
+        page[HEADER_SIZE..].copy_from_slice(&buf.1);
+        crate::disk::DiskManager::write(self, page_id, &page)
+    }
+
+    fn allocate(&mut self) -> Result<PageID, DiskManagerError> {
+        crate::disk::DiskManager::allocate(self)
+    }
+
+    fn free(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        crate::disk::DiskManager::free(self, page_id)
+    }
+}
+
+/// Flushes all dirty pages, see [`BufferManager::flush_all`]. Errors are ignored, callers that
+/// need them must call [`BufferManager::shutdown`] instead.
+impl<DiskManager: DiskManagerTrait, ReplacementStrategy: ReplacementStrategyTrait> Drop
+    for BufferManager<DiskManager, ReplacementStrategy>
+{
+    fn drop(&mut self) {
+        let _ = self.flush_all();
     }
 }
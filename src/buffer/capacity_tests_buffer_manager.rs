@@ -0,0 +1,88 @@
+// Tests for buffer pools of runtime sizes
+
+#[cfg(test)]
+mod capacity {
+    use crate::buffer::buffer_manager::*;
+    use crate::buffer::concurrent::*;
+    use crate::buffer::*;
+    use crate::{BUFFER_POOL_SIZE, PageID};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn new_uses_buffer_pool_size() {
+        let buffer_manager = BufferManager::new(disk(1), LRUReplacementStrategy::default());
+        assert_eq!(buffer_manager.pool.len(), BUFFER_POOL_SIZE);
+        assert_eq!(buffer_manager.frame_descriptors.len(), BUFFER_POOL_SIZE);
+    }
+
+    fn small_pool_evicts<R: ReplacementStrategyTrait + Default>() -> Result<(), BufferManagerError>
+    {
+        let mut buffer_manager = BufferManager::with_capacity(disk(8), R::default(), 3);
+        assert_eq!(buffer_manager.pool.len(), 3);
+        assert_eq!(buffer_manager.frame_descriptors.len(), 3);
+
+        for i in 1..8 {
+            assert_eq!(buffer_manager.pin(PageID(i))?.1[0], i as u8);
+            buffer_manager.unpin(PageID(i), false);
+            assert!(buffer_manager.page_table.len() <= 3);
+        }
+        assert_eq!(buffer_manager.last_evict, PageID(4));
+
+        for i in 5..8 {
+            buffer_manager.pin(PageID(i))?;
+        }
+        assert_eq!(
+            buffer_manager.pin(PageID(1)),
+            Err(BufferManagerError::AllPagesPinned)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn small_pool_evicts_with_lru() -> Result<(), BufferManagerError> {
+        small_pool_evicts::<LRUReplacementStrategy>()
+    }
+
+    #[test]
+    fn small_pool_evicts_with_clock() -> Result<(), BufferManagerError> {
+        small_pool_evicts::<ClockReplacementStrategy>()
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one frame")]
+    fn empty_pool_is_rejected() {
+        BufferManager::with_capacity(disk(1), ClockReplacementStrategy::default(), 0);
+    }
+
+    fn concurrent_small_pool_evicts<S: SharedReplacementStrategyTrait + Default>() {
+        let disk = Arc::new(Mutex::new(DummyDiskManager::with_pages(8)));
+        let buffer_manager = ConcurrentBufferManager::with_capacity(disk, S::default(), 2);
+        assert_eq!(buffer_manager.frames().len(), 2);
+
+        for i in 1..8 {
+            buffer_manager.pin_exclusive(PageID(i)).unwrap().1[0] = i as u8;
+        }
+        let first = buffer_manager.pin_shared(PageID(6)).unwrap();
+        let second = buffer_manager.pin_shared(PageID(7)).unwrap();
+        assert!(matches!(
+            buffer_manager.pin_shared(PageID(1)),
+            Err(BufferManagerError::AllPagesPinned)
+        ));
+        drop((first, second));
+
+        for i in 1..8 {
+            assert_eq!(buffer_manager.pin_shared(PageID(i)).unwrap().1[0], i as u8);
+        }
+    }
+
+    #[test]
+    fn concurrent_small_pool_evicts_with_lru() {
+        concurrent_small_pool_evicts::<SharedLRUReplacementStrategy>();
+    }
+
+    #[test]
+    fn concurrent_small_pool_evicts_with_clock() {
+        concurrent_small_pool_evicts::<SharedClockReplacementStrategy>();
+    }
+}
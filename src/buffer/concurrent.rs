@@ -0,0 +1,547 @@
+//! Concurrent buffer manager
+//!
+//! [`BufferManager`](crate::buffer::buffer_manager::BufferManager) needs `&mut self` for every
+//! pin. [`ConcurrentBufferManager`] caches pages the same way, but is `Send + Sync` so that many
+//! threads can pin pages at once:
+//!
+//! - The page table maps [`PageID`]s to frames behind a single mutex that is held only for
+//!   bookkeeping, never during I/O.
+//! - Every [`Frame`] has a shared/exclusive latch around its page, see [`crate::buffer::latch`],
+//!   and an atomic pin count. Pins are only taken with the page table locked, so a frame seen
+//!   unpinned under the lock stays unpinned until the lock is released.
+//! - The replacement strategy, see [`SharedReplacementStrategyTrait`], is only called with the
+//!   page table locked.
+//!
+//! Loading a page claims a frame under the lock and then writes back the evicted page and reads
+//! the new one while holding the frame's latch exclusively. Threads that pin the page in the
+//! meantime wait for the latch until the page is read.
+
+use crate::buffer::latch::{Latch, LatchPolicy, PinMode};
+use crate::buffer::*;
+use crate::{BUFFER_POOL_SIZE, FrameID, PageID};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A frame of the [`ConcurrentBufferManager`] holding one page.
+#[derive(Debug, Default)]
+pub struct Frame {
+    /// Latch protecting `page`.
+    latch: Latch,
+    /// The cached page. Only accessed while holding `latch`.
+    page: UnsafeCell<MaterializedPage>,
+    /// [`PageID`] of the page in the frame, `PageID(0)` if the frame is empty.
+    page_id: AtomicUsize,
+    /// Number of times the page is currently pinned.
+    pin_count: AtomicU32,
+    /// True if and only if the page differs from the page on disk.
+    dirty: AtomicBool,
+}
+
+impl Frame {
+    /// [`PageID`] of the page in the frame, `PageID(0)` if the frame is empty.
+    pub fn page_id(&self) -> PageID {
+        PageID(self.page_id.load(Ordering::Acquire))
+    }
+
+    /// Number of times the page is currently pinned.
+    pub fn pin_count(&self) -> u32 {
+        self.pin_count.load(Ordering::Acquire)
+    }
+
+    /// True if and only if the page differs from the page on disk.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    fn unpin(&self) {
+        self.pin_count.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// SAFETY: `page` is only accessed through guards that hold `latch` in the matching mode, or by
+// the loading thread while it holds `latch` exclusively.
+unsafe impl Sync for Frame {}
+
+/// Replacement strategy of a [`ConcurrentBufferManager`].
+///
+/// All methods are called with the page table locked, so no pins are taken concurrently.
+pub trait SharedReplacementStrategyTrait: Send + Sync {
+    /// Records a pin of the page in `frame`.
+    fn on_pin(&self, frame: FrameID);
+
+    /// Returns an unpinned frame to reuse, `None` if all frames are pinned.
+    fn replace(&self, frames: &[Frame]) -> Option<FrameID>;
+
+    /// Unpinned frames, those the strategy will pick next first. Used by the
+    /// [`BackgroundWriter`](crate::buffer::background_writer::BackgroundWriter) to clean frames
+    /// before they are evicted. Must not change the state of the strategy.
+    ///
+    /// The default lists the unpinned frames in pool order.
+    fn eviction_order(&self, frames: &[Frame]) -> Vec<FrameID> {
+        (0..frames.len())
+            .filter(|&frame| frames[frame].pin_count() == 0)
+            .map(FrameID)
+            .collect()
+    }
+}
+
+/// LRU replacement for a [`ConcurrentBufferManager`].
+#[derive(Debug, Default)]
+pub struct SharedLRUReplacementStrategy {
+    /// Logical clock and the time of the last pin of every frame.
+    state: Mutex<(u64, Vec<u64>)>,
+}
+
+impl SharedReplacementStrategyTrait for SharedLRUReplacementStrategy {
+    fn on_pin(&self, frame: FrameID) {
+        let (time, last_used) = &mut *lock(&self.state);
+        *time += 1;
+        if last_used.len() <= frame.0 {
+            last_used.resize(frame.0 + 1, 0);
+        }
+        last_used[frame.0] = *time;
+    }
+
+    fn replace(&self, frames: &[Frame]) -> Option<FrameID> {
+        let (_, last_used) = &*lock(&self.state);
+        (0..frames.len())
+            .filter(|&frame| frames[frame].pin_count() == 0)
+            .min_by_key(|&frame| last_used.get(frame).copied().unwrap_or(0))
+            .map(FrameID)
+    }
+
+    fn eviction_order(&self, frames: &[Frame]) -> Vec<FrameID> {
+        let (_, last_used) = &*lock(&self.state);
+        let mut order: Vec<usize> = (0..frames.len())
+            .filter(|&frame| frames[frame].pin_count() == 0)
+            .collect();
+        order.sort_by_key(|&frame| last_used.get(frame).copied().unwrap_or(0));
+        order.into_iter().map(FrameID).collect()
+    }
+}
+
+/// CLOCK replacement for a [`ConcurrentBufferManager`].
+#[derive(Debug, Default)]
+pub struct SharedClockReplacementStrategy {
+    /// Position of the clock hand and the reference bit of every frame.
+    state: Mutex<(usize, Vec<bool>)>,
+}
+
+impl SharedReplacementStrategyTrait for SharedClockReplacementStrategy {
+    fn on_pin(&self, frame: FrameID) {
+        let (_, referenced) = &mut *lock(&self.state);
+        if referenced.len() <= frame.0 {
+            referenced.resize(frame.0 + 1, false);
+        }
+        referenced[frame.0] = true;
+    }
+
+    fn replace(&self, frames: &[Frame]) -> Option<FrameID> {
+        let (hand, referenced) = &mut *lock(&self.state);
+        referenced.resize(frames.len(), false);
+        // The first round clears reference bits, so the second round finds a victim unless all
+        // pages are pinned.
+        for _ in 0..2 * frames.len() {
+            let frame = *hand % frames.len();
+            *hand = (frame + 1) % frames.len();
+            if frames[frame].pin_count() > 0 {
+                continue;
+            }
+            if !referenced[frame] {
+                return Some(FrameID(frame));
+            }
+            referenced[frame] = false;
+        }
+        None
+    }
+
+    fn eviction_order(&self, frames: &[Frame]) -> Vec<FrameID> {
+        let (hand, referenced) = &*lock(&self.state);
+        // Unreferenced frames go in the first round of the hand, referenced ones in the second.
+        let (mut order, second): (Vec<FrameID>, Vec<FrameID>) = (0..frames.len())
+            .map(|offset| (hand + offset) % frames.len())
+            .filter(|&frame| frames[frame].pin_count() == 0)
+            .map(FrameID)
+            .partition(|frame| !referenced.get(frame.0).copied().unwrap_or(false));
+        order.extend(second);
+        order
+    }
+}
+
+/// Mapping of loaded pages to frames.
+#[derive(Debug, Default)]
+struct PageTable {
+    /// Frame of every loaded or loading page.
+    pages: HashMap<PageID, FrameID>,
+    /// Frames that never held a page.
+    unused: Vec<FrameID>,
+}
+
+/// Buffer manager that can be shared between threads.
+///
+/// Pages are accessed through [`ConcurrentBufferManager::pin_shared`] and
+/// [`ConcurrentBufferManager::pin_exclusive`], which return guards holding the frame's latch and
+/// pin. Requests that conflict with the current holders of a page wait or fail according to the
+/// [`LatchPolicy`].
+pub struct ConcurrentBufferManager<D: DiskManagerTrait + Send, S: SharedReplacementStrategyTrait> {
+    /// Access to underlying storage.
+    disk_manager: Arc<Mutex<D>>,
+    /// Strategy to pick frames for new pages.
+    replacement_strat: S,
+    /// Lookup table from loaded pages to frames.
+    page_table: Mutex<PageTable>,
+    /// The frames of the pool.
+    frames: Box<[Frame]>,
+    /// What conflicting latch requests do, see [`ConcurrentBufferManager::set_latch_policy`].
+    latch_policy: LatchPolicy,
+}
+
+/// Shared access to a pinned page. Releases the latch and unpins the page on drop.
+pub struct SharedPageGuard<'a> {
+    frame: &'a Frame,
+    policy: LatchPolicy,
+}
+
+/// Exclusive access to a pinned page. Releases the latch and unpins the page on drop.
+///
+/// Marks the page dirty once it is mutably dereferenced.
+pub struct ExclusivePageGuard<'a> {
+    frame: &'a Frame,
+    policy: LatchPolicy,
+}
+
+impl<D: DiskManagerTrait + Send, S: SharedReplacementStrategyTrait> ConcurrentBufferManager<D, S> {
+    /// Creates a buffer manager with [`BUFFER_POOL_SIZE`] frames.
+    pub fn new(disk_manager: Arc<Mutex<D>>, replacement_strat: S) -> Self {
+        Self::with_capacity(disk_manager, replacement_strat, BUFFER_POOL_SIZE)
+    }
+
+    /// Creates a buffer manager with `frames` frames.
+    ///
+    /// # Panics
+    /// Panics if `frames` is zero.
+    pub fn with_capacity(disk_manager: Arc<Mutex<D>>, replacement_strat: S, frames: usize) -> Self {
+        assert!(frames > 0, "a buffer pool needs at least one frame");
+        let frames: Box<[Frame]> = (0..frames).map(|_| Frame::default()).collect();
+        let page_table = PageTable {
+            pages: HashMap::new(),
+            unused: (0..frames.len()).rev().map(FrameID).collect(),
+        };
+        ConcurrentBufferManager {
+            disk_manager,
+            replacement_strat,
+            page_table: Mutex::new(page_table),
+            frames,
+            latch_policy: LatchPolicy::default(),
+        }
+    }
+
+    /// Sets what pins that conflict with the current holders of a page do, also for
+    /// [`SharedPageGuard::upgrade`]. Defaults to [`LatchPolicy::Block`].
+    pub fn set_latch_policy(&mut self, policy: LatchPolicy) {
+        self.latch_policy = policy;
+    }
+
+    /// What pins that conflict with the current holders of a page do.
+    pub fn latch_policy(&self) -> LatchPolicy {
+        self.latch_policy
+    }
+
+    /// Share of frames holding dirty pages, between 0 and 1.
+    pub fn dirty_ratio(&self) -> f64 {
+        let dirty = self.frames.iter().filter(|frame| frame.is_dirty()).count();
+        dirty as f64 / self.frames.len() as f64
+    }
+
+    /// Unpinned frames holding dirty pages, those the replacement strategy will pick next first.
+    pub(crate) fn dirty_eviction_candidates(&self) -> Vec<FrameID> {
+        let _page_table = lock(&self.page_table);
+        let mut order = self.replacement_strat.eviction_order(&self.frames);
+        order.retain(|frame| self.frames[frame.0].is_dirty());
+        order
+    }
+
+    /// Writes the page in `frame_id` to disk if it is dirty and unpinned, without evicting it.
+    ///
+    /// Returns false if the frame was skipped because it is pinned or clean.
+    ///
+    /// # Errors
+    /// Propagates errors of [`DiskManagerTrait::write`]. The page stays dirty then.
+    pub(crate) fn clean_frame(&self, frame_id: FrameID) -> Result<bool, BufferManagerError> {
+        let frame = &self.frames[frame_id.0];
+        {
+            let _page_table = lock(&self.page_table);
+            if frame.pin_count() > 0 || !frame.is_dirty() {
+                return Ok(false);
+            }
+            // Pin the frame so it is not evicted while we write it.
+            frame.pin_count.fetch_add(1, Ordering::AcqRel);
+        }
+
+        // Writers hold the latch exclusively, so the page cannot change while it is written.
+        frame.latch.acquire(PinMode::Shared, LatchPolicy::Block);
+        // SAFETY: the latch is held shared until it is released below.
+        let page = unsafe { &*frame.page.get() };
+        let written = lock(&self.disk_manager).write(frame.page_id(), page);
+        if written.is_ok() {
+            frame.dirty.store(false, Ordering::Release);
+        }
+        frame.latch.release(PinMode::Shared);
+        frame.unpin();
+        written?;
+        Ok(true)
+    }
+
+    /// The frames of the pool.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Frame holding `page_id`, `None` if the page is not loaded.
+    pub fn frame_of(&self, page_id: PageID) -> Option<&Frame> {
+        let frame = *lock(&self.page_table).pages.get(&page_id)?;
+        Some(&self.frames[frame.0])
+    }
+
+    /// Pins `page_id` for reading, shared with other shared holders.
+    ///
+    /// # Errors
+    /// - Returns [`BufferManagerError::InvalidPageID`] for `PageID(0)` and pages the disk manager
+    ///   rejects.
+    /// - Returns [`BufferManagerError::AllPagesPinned`] if the page is not loaded and all frames
+    ///   are pinned.
+    /// - Returns [`BufferManagerError::LatchConflict`] if the page is held exclusively, or is
+    ///   being read from disk, and the policy is [`LatchPolicy::Fail`].
+    /// - Propagates other errors of [`DiskManagerTrait::read`] and [`DiskManagerTrait::write`].
+    pub fn pin_shared(&self, page_id: PageID) -> Result<SharedPageGuard<'_>, BufferManagerError> {
+        let frame = self.pin_latched(page_id, PinMode::Shared)?;
+        Ok(SharedPageGuard {
+            frame,
+            policy: self.latch_policy,
+        })
+    }
+
+    /// Pins `page_id` for changing it, with no other holders.
+    ///
+    /// # Errors
+    /// See [`ConcurrentBufferManager::pin_shared`]. Returns [`BufferManagerError::LatchConflict`]
+    /// if the page has any other holders and the policy is [`LatchPolicy::Fail`].
+    pub fn pin_exclusive(
+        &self,
+        page_id: PageID,
+    ) -> Result<ExclusivePageGuard<'_>, BufferManagerError> {
+        let frame = self.pin_latched(page_id, PinMode::Exclusive)?;
+        Ok(ExclusivePageGuard {
+            frame,
+            policy: self.latch_policy,
+        })
+    }
+
+    /// Pins `page_id` and acquires the latch of its frame in `mode`.
+    fn pin_latched(&self, page_id: PageID, mode: PinMode) -> Result<&Frame, BufferManagerError> {
+        loop {
+            let frame = &self.frames[self.pin_frame(page_id)?.0];
+            if !frame.latch.acquire(mode, self.latch_policy) {
+                frame.unpin();
+                return Err(BufferManagerError::LatchConflict(page_id));
+            }
+            if frame.page_id() == page_id {
+                return Ok(frame);
+            }
+            // Loading the page failed while we waited, try again.
+            frame.latch.release(mode);
+            frame.unpin();
+        }
+    }
+
+    /// Pins `page_id` and returns its frame, loading the page if needed.
+    ///
+    /// The page is read by the time the caller gets the frame's latch. The caller must check that
+    /// the frame still holds `page_id` once it has the latch, as loading may have failed.
+    fn pin_frame(&self, page_id: PageID) -> Result<FrameID, BufferManagerError> {
+        if page_id.0 == 0 {
+            return Err(BufferManagerError::InvalidPageID(page_id));
+        }
+        loop {
+            let mut page_table = lock(&self.page_table);
+            if let Some(&frame) = page_table.pages.get(&page_id) {
+                self.frames[frame.0]
+                    .pin_count
+                    .fetch_add(1, Ordering::AcqRel);
+                self.replacement_strat.on_pin(frame);
+                return Ok(frame);
+            }
+
+            let frame = match page_table.unused.pop() {
+                Some(frame) => frame,
+                None => self
+                    .replacement_strat
+                    .replace(&self.frames)
+                    .ok_or(BufferManagerError::AllPagesPinned)?,
+            };
+            // Claim the frame, no other thread can pin it while we hold the page table.
+            self.frames[frame.0].pin_count.store(1, Ordering::Release);
+            drop(page_table);
+
+            if self.load(frame, page_id)? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Replaces the page in the claimed `frame` with `page_id`.
+    ///
+    /// Returns false if the frame cannot be taken over, because its old page was pinned or
+    /// `page_id` was loaded by another thread in the meantime. The claim is given up then.
+    fn load(&self, frame_id: FrameID, page_id: PageID) -> Result<bool, BufferManagerError> {
+        let frame = &self.frames[frame_id.0];
+        // Nobody else holds the latch of an unpinned frame, so this does not wait.
+        frame.latch.acquire(PinMode::Exclusive, LatchPolicy::Block);
+        // SAFETY: the latch is held exclusively until the end of the function.
+        let page = unsafe { &mut *frame.page.get() };
+        let result = self.load_latched(frame_id, page, page_id);
+        frame.latch.release(PinMode::Exclusive);
+        result
+    }
+
+    /// Body of [`ConcurrentBufferManager::load`] while holding the latch of the frame.
+    fn load_latched(
+        &self,
+        frame_id: FrameID,
+        page: &mut MaterializedPage,
+        page_id: PageID,
+    ) -> Result<bool, BufferManagerError> {
+        let frame = &self.frames[frame_id.0];
+        let old = frame.page_id();
+        if frame.is_dirty() {
+            let written = lock(&self.disk_manager).write(old, page);
+            if let Err(err) = written {
+                frame.unpin();
+                return Err(err.into());
+            }
+            frame.dirty.store(false, Ordering::Release);
+        }
+
+        {
+            let mut page_table = lock(&self.page_table);
+            if frame.pin_count() > 1 {
+                frame.unpin();
+                return Ok(false);
+            }
+            page_table.pages.remove(&old);
+            if page_table.pages.contains_key(&page_id) {
+                frame.page_id.store(0, Ordering::Release);
+                frame.unpin();
+                return Ok(false);
+            }
+            page_table.pages.insert(page_id, frame_id);
+            frame.page_id.store(page_id.0, Ordering::Release);
+            self.replacement_strat.on_pin(frame_id);
+        }
+
+        *page = MaterializedPage::new(page_id);
+        let read = lock(&self.disk_manager).read(page_id, page);
+        if let Err(err) = read {
+            // Threads waiting for the page see the empty frame and retry.
+            let mut page_table = lock(&self.page_table);
+            page_table.pages.remove(&page_id);
+            frame.page_id.store(0, Ordering::Release);
+            frame.unpin();
+            return Err(err.into());
+        }
+        Ok(true)
+    }
+}
+
+impl<'a> SharedPageGuard<'a> {
+    /// The pinned page.
+    pub fn page_id(&self) -> PageID {
+        self.frame.page_id()
+    }
+
+    /// Turns the shared hold into an exclusive one without letting other writers in between.
+    ///
+    /// Waits for the other shared holders to leave unless the policy is [`LatchPolicy::Fail`].
+    ///
+    /// # Errors
+    /// Returns the unchanged guard if other shared holders are present and the policy is
+    /// [`LatchPolicy::Fail`], or if another holder already waits to upgrade.
+    pub fn upgrade(self) -> Result<ExclusivePageGuard<'a>, Self> {
+        if !self.frame.latch.upgrade(self.policy) {
+            return Err(self);
+        }
+        let guard = ManuallyDrop::new(self);
+        Ok(ExclusivePageGuard {
+            frame: guard.frame,
+            policy: guard.policy,
+        })
+    }
+}
+
+impl Deref for SharedPageGuard<'_> {
+    type Target = MaterializedPage;
+
+    fn deref(&self) -> &MaterializedPage {
+        // SAFETY: the latch is held shared until drop.
+        unsafe { &*self.frame.page.get() }
+    }
+}
+
+impl Drop for SharedPageGuard<'_> {
+    fn drop(&mut self) {
+        // Release the latch before the pin, an unpinned frame must not be latched.
+        self.frame.latch.release(PinMode::Shared);
+        self.frame.unpin();
+    }
+}
+
+impl<'a> ExclusivePageGuard<'a> {
+    /// The pinned page.
+    pub fn page_id(&self) -> PageID {
+        self.frame.page_id()
+    }
+
+    /// Turns the exclusive hold into a shared one, letting other shared holders in.
+    pub fn downgrade(self) -> SharedPageGuard<'a> {
+        self.frame.latch.downgrade();
+        let guard = ManuallyDrop::new(self);
+        SharedPageGuard {
+            frame: guard.frame,
+            policy: guard.policy,
+        }
+    }
+}
+
+impl Deref for ExclusivePageGuard<'_> {
+    type Target = MaterializedPage;
+
+    fn deref(&self) -> &MaterializedPage {
+        // SAFETY: the latch is held exclusively until drop.
+        unsafe { &*self.frame.page.get() }
+    }
+}
+
+impl DerefMut for ExclusivePageGuard<'_> {
+    fn deref_mut(&mut self) -> &mut MaterializedPage {
+        self.frame.dirty.store(true, Ordering::Release);
+        // SAFETY: the latch is held exclusively until drop.
+        unsafe { &mut *self.frame.page.get() }
+    }
+}
+
+impl Drop for ExclusivePageGuard<'_> {
+    fn drop(&mut self) {
+        self.frame.latch.release(PinMode::Exclusive);
+        self.frame.unpin();
+    }
+}
+
+/// Locks `mutex`. A thread that panicked while holding it leaves consistent bookkeeping behind,
+/// so poisoning is ignored.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,167 @@
+// Tests for the concurrent buffer manager
+
+#[cfg(test)]
+mod concurrent {
+    use crate::buffer::concurrent::*;
+    use crate::buffer::*;
+    use crate::{BUFFER_POOL_SIZE, PageID};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use std::thread;
+
+    const THREADS: usize = 8;
+
+    fn counter(page: &MaterializedPage) -> u64 {
+        u64::from_le_bytes(page.1[..8].try_into().unwrap())
+    }
+
+    fn is_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn buffer_manager_is_send_and_sync() {
+        is_send_sync::<ConcurrentBufferManager<DummyDiskManager, SharedLRUReplacementStrategy>>();
+        is_send_sync::<ConcurrentBufferManager<DummyDiskManager, SharedClockReplacementStrategy>>();
+    }
+
+    fn increments_are_not_lost<S: SharedReplacementStrategyTrait>(strategy: S) {
+        // More pages than frames, so pages are evicted and written back concurrently.
+        let pages = BUFFER_POOL_SIZE + BUFFER_POOL_SIZE / 2;
+        let rounds = 2000;
+        let buffer_manager = ConcurrentBufferManager::new(concurrent_disk(pages + 1), strategy);
+
+        thread::scope(|scope| {
+            for seed in 0..THREADS as u64 {
+                let buffer_manager = &buffer_manager;
+                scope.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    for _ in 0..rounds {
+                        let page_id = PageID(rng.random_range(1..=pages));
+                        let mut page = buffer_manager.pin_exclusive(page_id).unwrap();
+                        let value = counter(&page) + 1;
+                        page.1[..8].copy_from_slice(&value.to_le_bytes());
+                    }
+                });
+            }
+        });
+
+        let total: u64 = (1..=pages)
+            .map(|i| counter(&buffer_manager.pin_shared(PageID(i)).unwrap()))
+            .sum();
+        assert_eq!(total, (THREADS * rounds) as u64);
+        assert!(
+            buffer_manager
+                .frames()
+                .iter()
+                .all(|frame| frame.pin_count() == 0)
+        );
+    }
+
+    #[test]
+    fn increments_are_not_lost_with_lru() {
+        increments_are_not_lost(SharedLRUReplacementStrategy::default());
+    }
+
+    #[test]
+    fn increments_are_not_lost_with_clock() {
+        increments_are_not_lost(SharedClockReplacementStrategy::default());
+    }
+
+    #[test]
+    fn readers_never_see_torn_pages() {
+        let pages = 64;
+        let buffer_manager = ConcurrentBufferManager::new(
+            concurrent_disk(pages + 1),
+            SharedClockReplacementStrategy::default(),
+        );
+
+        thread::scope(|scope| {
+            for seed in 0..THREADS as u64 {
+                let buffer_manager = &buffer_manager;
+                scope.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    for _ in 0..2000 {
+                        let page_id = PageID(rng.random_range(1..=pages));
+                        if seed % 2 == 0 {
+                            let mut page = buffer_manager.pin_exclusive(page_id).unwrap();
+                            page.1.fill(rng.random());
+                        } else {
+                            let page = buffer_manager.pin_shared(page_id).unwrap();
+                            let first = page.1[0];
+                            assert!(page.1.iter().all(|&byte| byte == first), "torn {page_id}");
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn shared_readers_hold_a_page_together() -> Result<(), BufferManagerError> {
+        let buffer_manager = ConcurrentBufferManager::new(
+            concurrent_disk(4),
+            SharedLRUReplacementStrategy::default(),
+        );
+        let first = buffer_manager.pin_shared(PageID(2))?;
+        thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let second = buffer_manager.pin_shared(PageID(2)).unwrap();
+                    assert_eq!(second.page_id(), PageID(2));
+                    assert_eq!(buffer_manager.frame_of(PageID(2)).unwrap().pin_count(), 2);
+                })
+                .join()
+                .unwrap();
+        });
+        drop(first);
+        assert_eq!(buffer_manager.frame_of(PageID(2)).unwrap().pin_count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn errors_leave_no_pins_behind() {
+        let pages = BUFFER_POOL_SIZE + 1;
+        let buffer_manager = ConcurrentBufferManager::new(
+            concurrent_disk(pages + 1),
+            SharedLRUReplacementStrategy::default(),
+        );
+        assert!(matches!(
+            buffer_manager.pin_shared(PageID(0)),
+            Err(BufferManagerError::InvalidPageID(PageID(0)))
+        ));
+        assert!(matches!(
+            buffer_manager.pin_shared(PageID(pages + 5)),
+            Err(BufferManagerError::InvalidPageID(_))
+        ));
+
+        let guards: Vec<_> = (1..=BUFFER_POOL_SIZE)
+            .map(|i| buffer_manager.pin_shared(PageID(i)).unwrap())
+            .collect();
+        assert!(matches!(
+            buffer_manager.pin_exclusive(PageID(pages)),
+            Err(BufferManagerError::AllPagesPinned)
+        ));
+        drop(guards);
+        assert!(buffer_manager.pin_exclusive(PageID(pages)).is_ok());
+        assert!(
+            buffer_manager
+                .frames()
+                .iter()
+                .all(|frame| frame.pin_count() == 0)
+        );
+    }
+
+    #[test]
+    fn dirty_pages_are_written_back_on_eviction() {
+        let pages = BUFFER_POOL_SIZE + 1;
+        let disk = concurrent_disk(pages + 1);
+        let buffer_manager =
+            ConcurrentBufferManager::new(disk.clone(), SharedLRUReplacementStrategy::default());
+        buffer_manager.pin_exclusive(PageID(1)).unwrap().1[0] = 7;
+        assert!(buffer_manager.frame_of(PageID(1)).unwrap().is_dirty());
+        for i in 2..=pages {
+            drop(buffer_manager.pin_shared(PageID(i)).unwrap());
+        }
+        assert!(buffer_manager.frame_of(PageID(1)).is_none());
+        assert_eq!(disk.lock().unwrap().pages[1].1[0], 7);
+    }
+}
@@ -0,0 +1,62 @@
+// Tests for the conversion of disk manager errors
+
+#[cfg(test)]
+mod error {
+    use crate::PageID;
+    use crate::buffer::BufferManagerError;
+    use crate::disk::{DiskManagerError, IoOp};
+    use std::error::Error;
+    use std::io;
+
+    #[test]
+    fn unallocated_pages_are_invalid() {
+        for err in [
+            DiskManagerError::InvalidPageID(PageID(0)),
+            DiskManagerError::PageFreed(PageID(3)),
+            DiskManagerError::OutOfRange {
+                page_id: PageID(9),
+                next_free: PageID(5),
+            },
+        ] {
+            let page_id = match &err {
+                DiskManagerError::InvalidPageID(page_id)
+                | DiskManagerError::PageFreed(page_id)
+                | DiskManagerError::OutOfRange { page_id, .. } => *page_id,
+                _ => unreachable!(),
+            };
+            assert_eq!(
+                BufferManagerError::from(err),
+                BufferManagerError::InvalidPageID(page_id)
+            );
+        }
+    }
+
+    #[test]
+    fn io_errors_keep_their_cause() {
+        let err = BufferManagerError::from(DiskManagerError::Io {
+            op: IoOp::Read,
+            page_id: PageID(7),
+            source: io::Error::other("disk on fire"),
+        });
+
+        let BufferManagerError::IOError(cause) = &err else {
+            panic!("Expected IOError, got {err:?}");
+        };
+        assert!(matches!(
+            cause,
+            DiskManagerError::Io {
+                op: IoOp::Read,
+                page_id: PageID(7),
+                ..
+            }
+        ));
+        let source = err.source().expect("cause is the source");
+        assert_eq!(source.to_string(), "read of page 7 failed!");
+        assert_eq!(
+            source.source().map(ToString::to_string),
+            Some("disk on fire".to_string())
+        );
+        // Causes cannot be compared, so I/O errors are not even equal to themselves.
+        assert_ne!(err, err);
+    }
+}
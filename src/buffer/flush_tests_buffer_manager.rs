@@ -0,0 +1,84 @@
+// Tests for flushing dirty pages
+
+#[cfg(test)]
+mod flush {
+    use crate::buffer::buffer_manager::*;
+    use crate::buffer::*;
+    use crate::disk::DiskManagerError;
+    use crate::PageID;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Pins the pages in the given order and unpins them dirty, with their PageID as contents.
+    fn dirty(
+        buffer_manager: &mut BufferManager<RecordingDisk, LRUReplacementStrategy>,
+        pages: &[usize],
+    ) -> Result<(), BufferManagerError> {
+        for &i in pages {
+            buffer_manager.pin(PageID(i))?.1[0] = i as u8;
+            buffer_manager.unpin(PageID(i), true);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn flush_page_writes_only_that_page() -> Result<(), BufferManagerError> {
+        let disk = Rc::new(RefCell::new(RecordingDisk::new(8)));
+        let mut buffer_manager =
+            BufferManager::new(disk.clone(), LRUReplacementStrategy::default());
+        dirty(&mut buffer_manager, &[2, 5])?;
+        buffer_manager.flush_page(PageID(5))?;
+        buffer_manager.flush_page(PageID(5))?;
+        buffer_manager.flush_page(PageID(7))?;
+        assert_eq!(disk.borrow().writes, vec![PageID(5)]);
+        assert_eq!(disk.borrow().inner.pages[5].1[0], 5);
+
+        let frame = buffer_manager.page_table[&PageID(5)];
+        assert!(!buffer_manager.frame_descriptors[frame].dirty);
+        let frame = buffer_manager.page_table[&PageID(2)];
+        assert!(buffer_manager.frame_descriptors[frame].dirty);
+
+        Ok(())
+    }
+
+    #[test]
+    fn flush_all_writes_in_page_order() -> Result<(), BufferManagerError> {
+        let disk = Rc::new(RefCell::new(RecordingDisk::new(16)));
+        let mut buffer_manager =
+            BufferManager::new(disk.clone(), LRUReplacementStrategy::default());
+        dirty(&mut buffer_manager, &[9, 3, 12, 1, 7])?;
+        buffer_manager.pin(PageID(4))?;
+        buffer_manager.unpin(PageID(4), false);
+        buffer_manager.flush_all()?;
+        assert_eq!(disk.borrow().writes, [1, 3, 7, 9, 12].map(PageID).to_vec());
+        assert!(buffer_manager.frame_descriptors.iter().all(|fd| !fd.dirty));
+
+        buffer_manager.flush_all()?;
+        assert_eq!(disk.borrow().writes.len(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn drop_flushes_and_shutdown_reports_errors() -> Result<(), BufferManagerError> {
+        let disk = Rc::new(RefCell::new(RecordingDisk::new(8)));
+        {
+            let mut buffer_manager =
+                BufferManager::new(disk.clone(), LRUReplacementStrategy::default());
+            dirty(&mut buffer_manager, &[6, 2])?;
+        }
+        assert_eq!(disk.borrow().writes, vec![PageID(2), PageID(6)]);
+        assert_eq!(disk.borrow().inner.pages[6].1[0], 6);
+
+        disk.borrow_mut().failing = Some(PageID(4));
+        let mut buffer_manager =
+            BufferManager::new(disk.clone(), LRUReplacementStrategy::default());
+        dirty(&mut buffer_manager, &[3, 4, 5])?;
+        assert!(matches!(
+            buffer_manager.shutdown(),
+            Err(BufferManagerError::IOError(DiskManagerError::IOError(_)))
+        ));
+        assert_eq!(disk.borrow().writes[2..], [PageID(3)]);
+
+        Ok(())
+    }
+}
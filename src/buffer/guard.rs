@@ -0,0 +1,228 @@
+//! RAII page guards
+//!
+//! [`BufferManagerTrait::pin`] and [`BufferManagerTrait::unpin`] rely on the caller to pair every
+//! pin with an unpin, which an early return easily breaks. [`BufferManager::pin_read`] and
+//! [`BufferManager::pin_write`] return guards instead that dereference to the pinned
+//! [`MaterializedPage`] and unpin it when they are dropped. A [`PageWriteGuard`] marks the page
+//! dirty once it is mutably dereferenced.
+//!
+//! [`BufferManager::pin_many`] pins several pages at once and hands out independent references
+//! to all of them through a single [`PinnedPages`] guard, e.g. to copy between pages or to hold a
+//! B-tree parent and child together.
+//!
+//! The manual API stays available for callers that need to manage pins themselves.
+
+use crate::buffer::buffer_manager::BufferManager;
+use crate::buffer::*;
+use crate::{FrameID, PageID};
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+
+/// Read access to a pinned page. Unpins the page on drop.
+pub struct PageReadGuard<'a, D: DiskManagerTrait, R: ReplacementStrategyTrait> {
+    buffer_manager: &'a mut BufferManager<D, R>,
+    page_id: PageID,
+    frame: FrameID,
+}
+
+/// Write access to a pinned page. Unpins the page on drop and marks it dirty if it was mutably
+/// dereferenced.
+pub struct PageWriteGuard<'a, D: DiskManagerTrait, R: ReplacementStrategyTrait> {
+    buffer_manager: &'a mut BufferManager<D, R>,
+    page_id: PageID,
+    frame: FrameID,
+    dirty: bool,
+}
+
+/// Access to several pinned pages. Unpins all of them on drop and marks those dirty that were
+/// mutably accessed.
+pub struct PinnedPages<'a, D: DiskManagerTrait, R: ReplacementStrategyTrait> {
+    buffer_manager: &'a mut BufferManager<D, R>,
+    /// Pinned pages in the order they were requested.
+    pages: Vec<(PageID, FrameID)>,
+    /// Per page, whether it was mutably accessed.
+    dirty: Vec<bool>,
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> BufferManager<D, R> {
+    /// Pins `page_id` like [`BufferManagerTrait::pin`] and returns a guard for reading it.
+    ///
+    /// # Errors
+    /// See [`BufferManagerTrait::pin`].
+    pub fn pin_read(
+        &mut self,
+        page_id: PageID,
+    ) -> Result<PageReadGuard<'_, D, R>, BufferManagerError> {
+        let frame = self.pin_frame(page_id)?;
+        Ok(PageReadGuard {
+            buffer_manager: self,
+            page_id,
+            frame,
+        })
+    }
+
+    /// Pins `page_id` like [`BufferManagerTrait::pin`] and returns a guard for changing it.
+    ///
+    /// # Errors
+    /// See [`BufferManagerTrait::pin`].
+    pub fn pin_write(
+        &mut self,
+        page_id: PageID,
+    ) -> Result<PageWriteGuard<'_, D, R>, BufferManagerError> {
+        let frame = self.pin_frame(page_id)?;
+        Ok(PageWriteGuard {
+            buffer_manager: self,
+            page_id,
+            frame,
+            dirty: false,
+        })
+    }
+
+    /// Pins all pages of `page_ids` and returns a guard giving access to all of them at once.
+    ///
+    /// Either all pages are pinned or none: pages pinned before an error are unpinned again.
+    ///
+    /// # Errors
+    /// - Returns [`BufferManagerError::DuplicatePageID`] if a page is requested twice.
+    /// - Returns [`BufferManagerError::AllPagesPinned`] if the pages do not fit into the unpinned
+    ///   frames.
+    /// - See [`BufferManagerTrait::pin`] for the other errors.
+    pub fn pin_many(
+        &mut self,
+        page_ids: &[PageID],
+    ) -> Result<PinnedPages<'_, D, R>, BufferManagerError> {
+        let mut seen = HashSet::new();
+        if let Some(&page_id) = page_ids.iter().find(|&&page_id| !seen.insert(page_id)) {
+            return Err(BufferManagerError::DuplicatePageID(page_id));
+        }
+
+        let mut pinned = PinnedPages {
+            buffer_manager: self,
+            pages: Vec::with_capacity(page_ids.len()),
+            dirty: vec![false; page_ids.len()],
+        };
+        for &page_id in page_ids {
+            let frame = pinned.buffer_manager.pin_frame(page_id)?;
+            pinned.pages.push((page_id, frame));
+        }
+        Ok(pinned)
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> PageReadGuard<'_, D, R> {
+    /// The pinned page.
+    pub fn page_id(&self) -> PageID {
+        self.page_id
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> Deref for PageReadGuard<'_, D, R> {
+    type Target = MaterializedPage;
+
+    fn deref(&self) -> &MaterializedPage {
+        &self.buffer_manager.pool[self.frame]
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> Drop for PageReadGuard<'_, D, R> {
+    fn drop(&mut self) {
+        self.buffer_manager.unpin(self.page_id, false);
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> PageWriteGuard<'_, D, R> {
+    /// The pinned page.
+    pub fn page_id(&self) -> PageID {
+        self.page_id
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> Deref for PageWriteGuard<'_, D, R> {
+    type Target = MaterializedPage;
+
+    fn deref(&self) -> &MaterializedPage {
+        &self.buffer_manager.pool[self.frame]
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> DerefMut for PageWriteGuard<'_, D, R> {
+    fn deref_mut(&mut self) -> &mut MaterializedPage {
+        self.dirty = true;
+        &mut self.buffer_manager.pool[self.frame]
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> Drop for PageWriteGuard<'_, D, R> {
+    fn drop(&mut self) {
+        self.buffer_manager.unpin(self.page_id, self.dirty);
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> PinnedPages<'_, D, R> {
+    /// Number of pinned pages.
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns true if no pages are pinned.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// The pinned pages in the order they were requested.
+    pub fn page_ids(&self) -> impl Iterator<Item = PageID> + '_ {
+        self.pages.iter().map(|&(page_id, _)| page_id)
+    }
+
+    /// The `index`-th requested page.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> &MaterializedPage {
+        &self.buffer_manager.pool[self.pages[index].1]
+    }
+
+    /// The `index`-th requested page for changing it. Marks the page dirty.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn get_mut(&mut self, index: usize) -> &mut MaterializedPage {
+        self.dirty[index] = true;
+        &mut self.buffer_manager.pool[self.pages[index].1]
+    }
+
+    /// All pages in the order they were requested.
+    pub fn pages(&self) -> Vec<&MaterializedPage> {
+        (0..self.len()).map(|index| self.get(index)).collect()
+    }
+
+    /// All pages in the order they were requested, for changing them at the same time. Marks all
+    /// pages dirty.
+    pub fn pages_mut(&mut self) -> Vec<&mut MaterializedPage> {
+        self.dirty.fill(true);
+        let index_of: HashMap<FrameID, usize> = self
+            .pages
+            .iter()
+            .enumerate()
+            .map(|(index, &(_, frame))| (frame, index))
+            .collect();
+        let mut pages: Vec<Option<&mut MaterializedPage>> = (0..self.len()).map(|_| None).collect();
+        for (frame, page) in self.buffer_manager.pool.iter_mut().enumerate() {
+            if let Some(&index) = index_of.get(&FrameID(frame)) {
+                pages[index] = Some(page);
+            }
+        }
+        pages
+            .into_iter()
+            .map(|page| page.expect("every pinned page has a frame"))
+            .collect()
+    }
+}
+
+impl<D: DiskManagerTrait, R: ReplacementStrategyTrait> Drop for PinnedPages<'_, D, R> {
+    fn drop(&mut self) {
+        for (&(page_id, _), &dirty) in self.pages.iter().zip(&self.dirty) {
+            self.buffer_manager.unpin(page_id, dirty);
+        }
+    }
+}
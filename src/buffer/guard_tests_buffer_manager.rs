@@ -0,0 +1,107 @@
+// Tests for RAII page guards
+
+#[cfg(test)]
+mod guard {
+    use crate::buffer::buffer_manager::*;
+    use crate::buffer::*;
+    use crate::{BUFFER_POOL_SIZE, PageID};
+
+    fn pin_count<R: ReplacementStrategyTrait>(
+        buffer_manager: &BufferManager<DummyDiskManager, R>,
+        page_id: PageID,
+    ) -> u16 {
+        buffer_manager.frame_descriptors[buffer_manager.page_table[&page_id]].pin_count
+    }
+
+    #[test]
+    fn read_guard_unpins_on_drop() -> Result<(), BufferManagerError> {
+        let mut buffer_manager = BufferManager::new(disk(4), LRUReplacementStrategy::default());
+        {
+            let page = buffer_manager.pin_read(PageID(3))?;
+            assert_eq!(page.page_id(), PageID(3));
+            assert_eq!(page.1[0], 3);
+        }
+        assert_eq!(pin_count(&buffer_manager, PageID(3)), 0);
+        let frame = buffer_manager.page_table[&PageID(3)];
+        assert!(!buffer_manager.frame_descriptors[frame].dirty);
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_guard_marks_dirty_only_when_written() -> Result<(), BufferManagerError> {
+        let mut buffer_manager = BufferManager::new(disk(4), LRUReplacementStrategy::default());
+        {
+            let page = buffer_manager.pin_write(PageID(1))?;
+            assert_eq!(page.1[0], 1);
+        }
+        let frame = buffer_manager.page_table[&PageID(1)];
+        assert!(!buffer_manager.frame_descriptors[frame].dirty);
+
+        {
+            let mut page = buffer_manager.pin_write(PageID(1))?;
+            page.1[0] = 42;
+        }
+        assert!(buffer_manager.frame_descriptors[frame].dirty);
+        assert_eq!(buffer_manager.frame_descriptors[frame].pin_count, 0);
+        assert_eq!(buffer_manager.pin_read(PageID(1))?.1[0], 42);
+
+        Ok(())
+    }
+
+    #[test]
+    fn early_return_does_not_leak_pins() {
+        /// Pins the page and bails out before reaching the end.
+        fn touch(
+            buffer_manager: &mut BufferManager<DummyDiskManager, ClockReplacementStrategy>,
+            page_id: PageID,
+        ) -> Result<(), BufferManagerError> {
+            let page = buffer_manager.pin_read(page_id)?;
+            if page.1[0] % 2 == 0 {
+                return Err(BufferManagerError::Unknown);
+            }
+            Ok(())
+        }
+
+        let pages = BUFFER_POOL_SIZE + 8;
+        let mut buffer_manager =
+            BufferManager::new(disk(pages), ClockReplacementStrategy::default());
+        // More pages than frames, so every frame must be unpinned again to be reused.
+        for i in 1..pages {
+            let result = touch(&mut buffer_manager, PageID(i));
+            assert_eq!(result.is_ok(), i % 2 == 1, "PID {i}");
+        }
+        assert!(
+            buffer_manager
+                .frame_descriptors
+                .iter()
+                .all(|fd| fd.pin_count == 0)
+        );
+    }
+
+    #[test]
+    fn failed_pin_returns_no_guard() {
+        let mut buffer_manager = BufferManager::new(disk(2), LRUReplacementStrategy::default());
+        assert!(matches!(
+            buffer_manager.pin_read(PageID(0)),
+            Err(BufferManagerError::InvalidPageID(PageID(0)))
+        ));
+        assert!(matches!(
+            buffer_manager.pin_write(PageID(5)),
+            Err(BufferManagerError::InvalidPageID(PageID(5)))
+        ));
+        assert!(buffer_manager.page_table.is_empty());
+    }
+
+    #[test]
+    fn manual_api_still_works_next_to_guards() -> Result<(), BufferManagerError> {
+        let mut buffer_manager = BufferManager::new(disk(4), LRUReplacementStrategy::default());
+        buffer_manager.pin(PageID(2))?;
+        drop(buffer_manager.pin_write(PageID(2))?);
+        assert_eq!(pin_count(&buffer_manager, PageID(2)), 1);
+        buffer_manager.unpin(PageID(2), false);
+        assert_eq!(pin_count(&buffer_manager, PageID(2)), 0);
+
+        Ok(())
+    }
+}
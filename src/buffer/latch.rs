@@ -0,0 +1,124 @@
+//! Frame latches
+//!
+//! A [`Latch`] protects the page of a
+//! [`ConcurrentBufferManager`](crate::buffer::concurrent::ConcurrentBufferManager) frame. It is
+//! held by many threads in [`PinMode::Shared`] or by one thread in [`PinMode::Exclusive`]. Unlike
+//! [`std::sync::RwLock`], a shared holder can upgrade to exclusive and an exclusive holder can
+//! downgrade to shared without releasing the latch in between.
+//!
+//! Requests that conflict with the current holders wait or fail according to the
+//! [`LatchPolicy`].
+
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+/// How a page is pinned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinMode {
+    /// Read access, shared with other shared holders.
+    Shared,
+    /// Write access, no other holders.
+    Exclusive,
+}
+
+/// What a latch request that conflicts with the current holders does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatchPolicy {
+    /// Wait until the conflicting holders release the latch.
+    #[default]
+    Block,
+    /// Fail right away with
+    /// [`BufferManagerError::LatchConflict`](crate::buffer::BufferManagerError::LatchConflict).
+    Fail,
+}
+
+/// Holders of a [`Latch`].
+#[derive(Debug, Default)]
+struct LatchState {
+    /// Number of shared holders.
+    shared: usize,
+    /// Whether the latch is held exclusively.
+    exclusive: bool,
+    /// Whether a shared holder waits to upgrade. New shared requests wait so it is not starved.
+    upgrading: bool,
+}
+
+/// Shared/exclusive latch with upgrade and downgrade.
+///
+/// The latch only tracks holders. The caller releases it in the mode it acquired it in.
+#[derive(Debug, Default)]
+pub(crate) struct Latch {
+    state: Mutex<LatchState>,
+    /// Notified whenever holders leave.
+    released: Condvar,
+}
+
+impl Latch {
+    /// Acquires the latch in `mode`. Returns false if the request conflicts and `policy` is
+    /// [`LatchPolicy::Fail`].
+    pub(crate) fn acquire(&self, mode: PinMode, policy: LatchPolicy) -> bool {
+        let mut state = self.lock();
+        loop {
+            let free = match mode {
+                PinMode::Shared => !state.exclusive && !state.upgrading,
+                PinMode::Exclusive => !state.exclusive && !state.upgrading && state.shared == 0,
+            };
+            if free {
+                match mode {
+                    PinMode::Shared => state.shared += 1,
+                    PinMode::Exclusive => state.exclusive = true,
+                }
+                return true;
+            }
+            if policy == LatchPolicy::Fail {
+                return false;
+            }
+            state = self.wait(state);
+        }
+    }
+
+    /// Releases the latch held in `mode`.
+    pub(crate) fn release(&self, mode: PinMode) {
+        let mut state = self.lock();
+        match mode {
+            PinMode::Shared => state.shared -= 1,
+            PinMode::Exclusive => state.exclusive = false,
+        }
+        self.released.notify_all();
+    }
+
+    /// Turns a shared hold into an exclusive one. Returns false, still holding the latch shared,
+    /// if other shared holders are present and `policy` is [`LatchPolicy::Fail`], or if another
+    /// holder is already waiting to upgrade, as both would wait for each other.
+    pub(crate) fn upgrade(&self, policy: LatchPolicy) -> bool {
+        let mut state = self.lock();
+        if state.upgrading || (state.shared > 1 && policy == LatchPolicy::Fail) {
+            return false;
+        }
+        state.upgrading = true;
+        while state.shared > 1 {
+            state = self.wait(state);
+        }
+        state.upgrading = false;
+        state.shared = 0;
+        state.exclusive = true;
+        true
+    }
+
+    /// Turns an exclusive hold into a shared one, letting other shared requests in.
+    pub(crate) fn downgrade(&self) {
+        let mut state = self.lock();
+        state.exclusive = false;
+        state.shared = 1;
+        self.released.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LatchState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, state: MutexGuard<'a, LatchState>) -> MutexGuard<'a, LatchState> {
+        self.released
+            .wait(state)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
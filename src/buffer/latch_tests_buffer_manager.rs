@@ -0,0 +1,136 @@
+// Tests for shared and exclusive pin modes
+
+#[cfg(test)]
+mod latch {
+    use crate::PageID;
+    use crate::buffer::concurrent::*;
+    use crate::buffer::latch::*;
+    use crate::buffer::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex, mpsc};
+    use std::thread;
+    use std::time::Duration;
+
+    type Manager = ConcurrentBufferManager<DummyDiskManager, SharedLRUReplacementStrategy>;
+
+    fn buffer_manager(policy: LatchPolicy) -> Manager {
+        let disk = Arc::new(Mutex::new(DummyDiskManager::with_pages(8)));
+        let mut buffer_manager =
+            ConcurrentBufferManager::new(disk, SharedLRUReplacementStrategy::default());
+        buffer_manager.set_latch_policy(policy);
+        buffer_manager
+    }
+
+    fn pin_count(buffer_manager: &Manager, page_id: PageID) -> u32 {
+        buffer_manager.frame_of(page_id).unwrap().pin_count()
+    }
+
+    #[test]
+    fn fail_policy_reports_conflicts() -> Result<(), BufferManagerError> {
+        let buffer_manager = buffer_manager(LatchPolicy::Fail);
+        assert_eq!(buffer_manager.latch_policy(), LatchPolicy::Fail);
+
+        let shared = buffer_manager.pin_shared(PageID(1))?;
+        let other = buffer_manager.pin_shared(PageID(1))?;
+        assert!(matches!(
+            buffer_manager.pin_exclusive(PageID(1)),
+            Err(BufferManagerError::LatchConflict(PageID(1)))
+        ));
+        assert_eq!(pin_count(&buffer_manager, PageID(1)), 2);
+        drop((shared, other));
+
+        let exclusive = buffer_manager.pin_exclusive(PageID(1))?;
+        assert!(matches!(
+            buffer_manager.pin_shared(PageID(1)),
+            Err(BufferManagerError::LatchConflict(PageID(1)))
+        ));
+        assert!(matches!(
+            buffer_manager.pin_exclusive(PageID(1)),
+            Err(BufferManagerError::LatchConflict(PageID(1)))
+        ));
+        assert_eq!(pin_count(&buffer_manager, PageID(1)), 1);
+        drop(exclusive);
+        assert_eq!(pin_count(&buffer_manager, PageID(1)), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn block_policy_waits_for_holders() -> Result<(), BufferManagerError> {
+        let buffer_manager = buffer_manager(LatchPolicy::Block);
+        let released = AtomicBool::new(false);
+        let shared = buffer_manager.pin_shared(PageID(2))?;
+
+        thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                let mut page = buffer_manager.pin_exclusive(PageID(2)).unwrap();
+                assert!(
+                    released.load(Ordering::SeqCst),
+                    "writer got in next to a reader"
+                );
+                page.1[0] = 1;
+            });
+            thread::sleep(Duration::from_millis(50));
+            released.store(true, Ordering::SeqCst);
+            drop(shared);
+            writer.join().unwrap();
+        });
+        assert_eq!(buffer_manager.pin_shared(PageID(2))?.1[0], 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn upgrade_and_downgrade() -> Result<(), BufferManagerError> {
+        let buffer_manager = buffer_manager(LatchPolicy::Fail);
+        let shared = buffer_manager.pin_shared(PageID(3))?;
+        let Ok(mut exclusive) = shared.upgrade() else {
+            panic!("sole reader must be able to upgrade");
+        };
+        exclusive.1[0] = 9;
+        assert!(matches!(
+            buffer_manager.pin_shared(PageID(3)),
+            Err(BufferManagerError::LatchConflict(_))
+        ));
+
+        let shared = exclusive.downgrade();
+        let other = buffer_manager.pin_shared(PageID(3))?;
+        assert_eq!(other.1[0], 9);
+        assert!(buffer_manager.frame_of(PageID(3)).unwrap().is_dirty());
+
+        // Another reader is present, so the upgrade fails and hands back the shared guard.
+        let shared = shared.upgrade().err().expect("upgrade must fail");
+        assert_eq!(shared.1[0], 9);
+        assert_eq!(pin_count(&buffer_manager, PageID(3)), 2);
+        drop((shared, other));
+        assert_eq!(pin_count(&buffer_manager, PageID(3)), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn blocking_upgrade_waits_for_other_readers() -> Result<(), BufferManagerError> {
+        let buffer_manager = buffer_manager(LatchPolicy::Block);
+        let (upgraded, done) = mpsc::channel();
+        let other = buffer_manager.pin_shared(PageID(4))?;
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let shared = buffer_manager.pin_shared(PageID(4)).unwrap();
+                let mut exclusive = shared.upgrade().ok().unwrap();
+                exclusive.1[0] = 4;
+                upgraded.send(()).unwrap();
+            });
+            assert!(done.recv_timeout(Duration::from_millis(50)).is_err());
+
+            // A second upgrader would wait for the first one forever, so it is turned away.
+            thread::sleep(Duration::from_millis(20));
+            let other = other.upgrade().err().expect("second upgrade must fail");
+            drop(other);
+            done.recv().unwrap();
+        });
+        assert_eq!(buffer_manager.pin_shared(PageID(4))?.1[0], 4);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+// Tests for the LRU-K replacement strategy
+
+#[cfg(test)]
+mod lru_k {
+    use crate::PageID;
+    use crate::buffer::buffer_manager::*;
+    use crate::buffer::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    /// Frames of the pool in the hit ratio tests.
+    const FRAMES: usize = 16;
+    /// Pages accessed over and over, fitting into the pool with room for a scan.
+    const HOT_PAGES: usize = 12;
+    const ROUNDS: usize = 50;
+    /// Hot accesses between two scans.
+    const HOT_ACCESSES: usize = 40;
+    /// Length of a scan of pages that are never accessed again.
+    const SCAN_LENGTH: usize = FRAMES;
+
+    /// Pins and unpins the pages in order.
+    fn access<R: ReplacementStrategyTrait>(
+        buffer_manager: &mut BufferManager<DummyDiskManager, R>,
+        pages: &[usize],
+    ) -> Result<(), BufferManagerError> {
+        for &i in pages {
+            buffer_manager.pin(PageID(i))?;
+            buffer_manager.unpin(PageID(i), false);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn pages_pinned_once_are_evicted_first() -> Result<(), BufferManagerError> {
+        let mut buffer_manager =
+            BufferManager::with_capacity(disk(5), LRUKReplacementStrategy::default(), 3);
+        access(&mut buffer_manager, &[1, 1, 2, 2, 3])?;
+
+        // Page 3 is the most recently used one, but was only pinned once.
+        access(&mut buffer_manager, &[4])?;
+        assert_eq!(buffer_manager.last_evict, PageID(3));
+        // Among pages pinned K times, the one with the oldest second to last pin goes.
+        access(&mut buffer_manager, &[2, 4])?;
+        access(&mut buffer_manager, &[3])?;
+        assert_eq!(buffer_manager.last_evict, PageID(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn correlated_pins_count_once() -> Result<(), BufferManagerError> {
+        let mut buffer_manager =
+            BufferManager::with_capacity(disk(5), LRUKReplacementStrategy::new(2, 2), 3);
+        // Pages 1 and 2 are pinned twice within the correlated period, page 3 with a gap.
+        access(&mut buffer_manager, &[1, 1, 2, 2, 3, 4, 3])?;
+        assert_eq!(buffer_manager.last_evict, PageID(1));
+
+        // Pages 3 and 4 are in their correlated periods, so page 2 goes.
+        access(&mut buffer_manager, &[1])?;
+        assert_eq!(buffer_manager.last_evict, PageID(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn falls_back_to_correlated_pages() -> Result<(), BufferManagerError> {
+        let mut buffer_manager =
+            BufferManager::with_capacity(disk(4), LRUKReplacementStrategy::new(2, 10), 2);
+        access(&mut buffer_manager, &[1, 2, 3])?;
+        assert_eq!(buffer_manager.last_evict, PageID(1));
+
+        buffer_manager.pin(PageID(2))?;
+        buffer_manager.pin(PageID(3))?;
+        assert_eq!(
+            buffer_manager.pin(PageID(1)),
+            Err(BufferManagerError::AllPagesPinned)
+        );
+
+        Ok(())
+    }
+
+    /// Hot pages accessed at random, polluted by scans over pages never accessed again. Scans
+    /// pin every page `scan_pins` times in a row.
+    fn scan_polluted_workload(scan_pins: usize) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut next_cold = HOT_PAGES + 1;
+        let mut workload = Vec::new();
+        for _ in 0..ROUNDS {
+            for _ in 0..HOT_ACCESSES {
+                workload.push(rng.random_range(1..=HOT_PAGES));
+            }
+            for page in next_cold..next_cold + SCAN_LENGTH {
+                workload.extend(std::iter::repeat_n(page, scan_pins));
+            }
+            next_cold += SCAN_LENGTH;
+        }
+        workload
+    }
+
+    /// Share of pins of `workload` that find their page in the pool.
+    fn hit_ratio<R: ReplacementStrategyTrait>(strategy: R, workload: &[usize]) -> f64 {
+        let pages = HOT_PAGES + ROUNDS * SCAN_LENGTH + 1;
+        let mut buffer_manager = BufferManager::with_capacity(disk(pages), strategy, FRAMES);
+        let mut hits = 0;
+        for &i in workload {
+            if buffer_manager.page_table.contains_key(&PageID(i)) {
+                hits += 1;
+            }
+            access(&mut buffer_manager, &[i]).unwrap();
+        }
+        hits as f64 / workload.len() as f64
+    }
+
+    #[test]
+    fn scans_do_not_push_out_hot_pages() {
+        let workload = scan_polluted_workload(1);
+        let lru = hit_ratio(LRUReplacementStrategy::default(), &workload);
+        let lru_k = hit_ratio(LRUKReplacementStrategy::default(), &workload);
+        assert!(lru_k > lru + 0.1, "LRU-K hit ratio {lru_k}, LRU {lru}");
+    }
+
+    #[test]
+    fn correlated_scan_pins_do_not_push_out_hot_pages() {
+        let workload = scan_polluted_workload(2);
+        let lru = hit_ratio(LRUReplacementStrategy::default(), &workload);
+        let uncorrelated = hit_ratio(LRUKReplacementStrategy::new(2, 0), &workload);
+        let lru_k = hit_ratio(LRUKReplacementStrategy::new(2, 2), &workload);
+        assert!(lru_k > lru + 0.1, "LRU-K hit ratio {lru_k}, LRU {lru}");
+        assert!(
+            lru_k > uncorrelated + 0.1,
+            "LRU-K hit ratio {lru_k}, without correlated period {uncorrelated}"
+        );
+    }
+}
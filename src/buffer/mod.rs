@@ -20,24 +20,55 @@ use crate::disk::DiskManagerError;
 use thiserror::Error;
 
 /// Errors for BufferManager operations
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug)]
 pub enum BufferManagerError {
     #[error("buffer pool ran out of free frames!")]
     AllPagesPinned,
     #[error("invalid page ID: {0}!")]
     InvalidPageID(PageID),
+    /// A page was requested more than once, see
+    /// [`BufferManager::pin_many`](buffer_manager::BufferManager::pin_many).
+    #[error("page {0} requested twice!")]
+    DuplicatePageID(PageID),
+    /// The page is latched in a conflicting mode and the latch policy is to fail, see
+    /// [`LatchPolicy`](latch::LatchPolicy).
+    #[error("page {0} is latched in a conflicting mode!")]
+    LatchConflict(PageID),
+    /// The page cannot be deleted while it is pinned.
+    #[error("page {0} is still pinned!")]
+    PagePinned(PageID),
+    /// Any other error of the disk manager, kept as the source.
     #[error("an I/O error occurred in the underlying disk manager!")]
-    IOError,
+    IOError(#[source] DiskManagerError),
     #[error("an unknown error occurred!")]
     Unknown,
 }
 
+/// Errors of the disk manager do not implement `PartialEq`, so a [`BufferManagerError::IOError`]
+/// is never equal to another error. Use `matches!` to check for one.
+impl PartialEq for BufferManagerError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::InvalidPageID(a), Self::InvalidPageID(b))
+            | (Self::DuplicatePageID(a), Self::DuplicatePageID(b))
+            | (Self::LatchConflict(a), Self::LatchConflict(b))
+            | (Self::PagePinned(a), Self::PagePinned(b)) => a == b,
+            (Self::AllPagesPinned, Self::AllPagesPinned) | (Self::Unknown, Self::Unknown) => true,
+            _ => false,
+        }
+    }
+}
+
 /// Conversion from relevant [`DiskManagerError`]s to [`BufferManagerError`]s
 impl From<DiskManagerError> for BufferManagerError {
     fn from(value: DiskManagerError) -> Self {
         match value {
-            DiskManagerError::InvalidPageID(page_id) => BufferManagerError::InvalidPageID(page_id),
-            DiskManagerError::IOError(_) => BufferManagerError::IOError,
+            DiskManagerError::InvalidPageID(page_id)
+            | DiskManagerError::PageFreed(page_id)
+            | DiskManagerError::OutOfRange { page_id, .. } => {
+                BufferManagerError::InvalidPageID(page_id)
+            }
+            cause => BufferManagerError::IOError(cause),
         }
     }
 }
@@ -127,15 +158,63 @@ pub trait DiskManagerTrait {
     /// # Errors
     /// Returns [`DiskManagerError::InvalidPageID`] if `page_id` is not in a valid range.
     fn write(&mut self, page_id: PageID, buf: &MaterializedPage) -> Result<(), DiskManagerError>;
+
+    /// Allocate a new page and return its ID.
+    ///
+    /// # Errors
+    /// Returns a [`DiskManagerError`] if no page can be allocated.
+    fn allocate(&mut self) -> Result<PageID, DiskManagerError>;
+
+    /// Free `page_id` so it can be allocated again.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::InvalidPageID`] if `page_id` is not in a valid range.
+    fn free(&mut self, page_id: PageID) -> Result<(), DiskManagerError>;
 }
 
 /// A dummy implementation of [`DiskManagerTrait`] for testing purposes.
+#[cfg(test)]
 #[derive(Debug)]
 struct DummyDiskManager {
     pages: Vec<MaterializedPage>,
 }
 
+#[cfg(test)]
+impl DummyDiskManager {
+    /// Disk with `pages` pages filled with their PageID.
+    fn with_pages(pages: usize) -> Self {
+        let pages = (0..pages)
+            .map(|i| {
+                let mut page = MaterializedPage::new(PageID(i));
+                page.1.fill(i as u8);
+                page
+            })
+            .collect();
+        DummyDiskManager { pages }
+    }
+
+    /// Disk with `pages` zeroed pages.
+    fn zeroed(pages: usize) -> Self {
+        let pages = (0..pages).map(PageID).map(MaterializedPage::new).collect();
+        DummyDiskManager { pages }
+    }
+}
+
+/// A [`DummyDiskManager::with_pages`] to share between a buffer manager and a test.
+#[cfg(test)]
+fn disk(pages: usize) -> std::rc::Rc<std::cell::RefCell<DummyDiskManager>> {
+    std::rc::Rc::new(std::cell::RefCell::new(DummyDiskManager::with_pages(pages)))
+}
+
+/// A [`DummyDiskManager::zeroed`] to share between a concurrent buffer manager and a test. The
+/// pages start out zeroed, so tests can count in them.
+#[cfg(test)]
+fn concurrent_disk(pages: usize) -> std::sync::Arc<std::sync::Mutex<DummyDiskManager>> {
+    std::sync::Arc::new(std::sync::Mutex::new(DummyDiskManager::zeroed(pages)))
+}
+
 /// A dummy implementation of [`DiskManagerTrait`] for testing purposes.
+#[cfg(test)]
 impl DiskManagerTrait for DummyDiskManager {
     fn read(
         &mut self,
@@ -162,6 +241,71 @@ impl DiskManagerTrait for DummyDiskManager {
 
         Ok(())
     }
+
+    fn allocate(&mut self) -> Result<PageID, DiskManagerError> {
+        let page_id = PageID(self.pages.len());
+        self.pages.push(MaterializedPage::new(page_id));
+
+        Ok(page_id)
+    }
+
+    fn free(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        let page = self
+            .pages
+            .get_mut(page_id.0)
+            .ok_or(DiskManagerError::InvalidPageID(page_id))?;
+
+        *page = MaterializedPage::new(page_id);
+
+        Ok(())
+    }
+}
+
+/// A [`DummyDiskManager`] with zeroed pages that records the order of writes and fails writes of
+/// `failing`.
+#[cfg(test)]
+struct RecordingDisk {
+    inner: DummyDiskManager,
+    writes: Vec<PageID>,
+    failing: Option<PageID>,
+}
+
+#[cfg(test)]
+impl RecordingDisk {
+    fn new(pages: usize) -> Self {
+        RecordingDisk {
+            inner: DummyDiskManager::zeroed(pages),
+            writes: Vec::new(),
+            failing: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl DiskManagerTrait for RecordingDisk {
+    fn read(
+        &mut self,
+        page_id: PageID,
+        buf: &mut MaterializedPage,
+    ) -> Result<(), DiskManagerError> {
+        self.inner.read(page_id, buf)
+    }
+
+    fn write(&mut self, page_id: PageID, buf: &MaterializedPage) -> Result<(), DiskManagerError> {
+        if self.failing == Some(page_id) {
+            return Err(std::io::Error::other("disk on fire").into());
+        }
+        self.writes.push(page_id);
+        self.inner.write(page_id, buf)
+    }
+
+    fn allocate(&mut self) -> Result<PageID, DiskManagerError> {
+        self.inner.allocate()
+    }
+
+    fn free(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        self.inner.free(page_id)
+    }
 }
 
 /// Trait defining the interface of a replacement strategy.
@@ -205,10 +349,12 @@ pub trait ReplacementStrategyTrait {
 }
 
 /// A dummy implementation of [`ReplacementStrategyTrait`] for testing purposes.
+#[cfg(test)]
 #[derive(Default)]
 struct DummyReplacementStrategy {}
 
 /// A dummy implementation of [`ReplacementStrategyTrait`] for testing purposes.
+#[cfg(test)]
 impl ReplacementStrategyTrait for DummyReplacementStrategy {
     /// Panics if called
     fn replace(
@@ -223,8 +369,23 @@ impl ReplacementStrategyTrait for DummyReplacementStrategy {
 
 // The tests
 mod advanced_tests_buffer_manager;
+mod background_writer_tests_buffer_manager;
 mod basic_tests_buffer_manager;
+mod capacity_tests_buffer_manager;
+mod concurrent_tests_buffer_manager;
+mod error_tests_buffer_manager;
+mod flush_tests_buffer_manager;
+mod guard_tests_buffer_manager;
+mod latch_tests_buffer_manager;
+mod lru_k_tests_buffer_manager;
+mod new_page_tests_buffer_manager;
+mod pin_many_tests_buffer_manager;
+mod resize_tests_buffer_manager;
 
 // The implementations
+pub mod background_writer;
 pub mod buffer_manager;
+pub mod concurrent;
 mod frame_pool;
+pub mod guard;
+pub mod latch;
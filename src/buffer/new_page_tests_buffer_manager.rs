@@ -0,0 +1,133 @@
+// Tests for creating and deleting pages through the buffer manager
+
+#[cfg(test)]
+mod new_page {
+    use crate::buffer::buffer_manager::*;
+    use crate::buffer::*;
+    use crate::disk::{DiskManager, DiskManagerError};
+    use crate::{BUFFER_POOL_SIZE, FrameID, PageID};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn new_page_is_pinned_zeroed_and_dirty() -> Result<(), BufferManagerError> {
+        let disk = disk(4);
+        let mut buffer_manager =
+            BufferManager::new(disk.clone(), LRUReplacementStrategy::default());
+        let page_id = {
+            let page = buffer_manager.new_page()?;
+            assert_eq!(page.page_id(), PageID(4));
+            assert_eq!(*page, MaterializedPage::new(PageID(4)));
+            page.page_id()
+        };
+        assert_eq!(disk.borrow().pages.len(), 5);
+
+        let frame = buffer_manager.page_table[&page_id];
+        assert_eq!(buffer_manager.frame_descriptors[frame].pin_count, 0);
+        assert!(buffer_manager.frame_descriptors[frame].dirty);
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_page_fails_when_all_pages_are_pinned() -> Result<(), BufferManagerError> {
+        let disk = disk(BUFFER_POOL_SIZE + 1);
+        let mut buffer_manager =
+            BufferManager::new(disk.clone(), ClockReplacementStrategy::default());
+        for i in 1..=BUFFER_POOL_SIZE {
+            buffer_manager.pin(PageID(i))?;
+        }
+        assert!(matches!(
+            buffer_manager.new_page(),
+            Err(BufferManagerError::AllPagesPinned)
+        ));
+        assert!(
+            !buffer_manager
+                .page_table
+                .contains_key(&PageID(BUFFER_POOL_SIZE + 1))
+        );
+
+        buffer_manager.unpin(PageID(1), false);
+        let page_id = buffer_manager.new_page()?.page_id();
+        assert_eq!(buffer_manager.last_evict, PageID(1));
+        assert!(buffer_manager.page_table.contains_key(&page_id));
+
+        Ok(())
+    }
+
+    #[test]
+    fn delete_page_drops_frame_and_frees_page() -> Result<(), BufferManagerError> {
+        let disk = disk(8);
+        let mut buffer_manager =
+            BufferManager::new(disk.clone(), LRUReplacementStrategy::default());
+        buffer_manager.pin(PageID(3))?.1[0] = 42;
+        assert_eq!(
+            buffer_manager.delete_page(PageID(3)),
+            Err(BufferManagerError::PagePinned(PageID(3)))
+        );
+        assert!(buffer_manager.page_table.contains_key(&PageID(3)));
+
+        buffer_manager.unpin(PageID(3), true);
+        buffer_manager.delete_page(PageID(3))?;
+        assert!(!buffer_manager.page_table.contains_key(&PageID(3)));
+        let frame = FrameID(0);
+        assert_eq!(buffer_manager.frame_descriptors[frame].page_id, PageID(0));
+        // Freed without writing back the dirty contents.
+        assert_eq!(disk.borrow().pages[3], MaterializedPage::new(PageID(3)));
+
+        // Pages that are not loaded are freed on disk only.
+        buffer_manager.delete_page(PageID(5))?;
+        assert_eq!(disk.borrow().pages[5], MaterializedPage::new(PageID(5)));
+        assert_eq!(
+            buffer_manager.delete_page(PageID(50)),
+            Err(BufferManagerError::InvalidPageID(PageID(50)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_frames_are_reused() -> Result<(), BufferManagerError> {
+        let mut buffer_manager = BufferManager::new(disk(8), LRUReplacementStrategy::default());
+        for i in 1..=3 {
+            buffer_manager.pin(PageID(i))?;
+            buffer_manager.unpin(PageID(i), false);
+        }
+        buffer_manager.delete_page(PageID(2))?;
+        buffer_manager.pin(PageID(6))?;
+        assert_eq!(buffer_manager.page_table[&PageID(6)], FrameID(1));
+        buffer_manager.pin(PageID(7))?;
+        assert_eq!(buffer_manager.page_table[&PageID(7)], FrameID(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn pages_are_created_and_deleted_on_disk() -> Result<(), BufferManagerError> {
+        let disk = Rc::new(RefCell::new(
+            DiskManager::temp().map_err(DiskManagerError::from)?,
+        ));
+        let mut buffer_manager =
+            BufferManager::new(disk.clone(), ClockReplacementStrategy::default());
+        let page_id = {
+            let mut page = buffer_manager.new_page()?;
+            page.1[0] = 42;
+            page.page_id()
+        };
+        assert_eq!(page_id, PageID(1));
+        buffer_manager.flush_page(page_id)?;
+        let mut page = MaterializedPage::default();
+        DiskManagerTrait::read(&mut *disk.borrow_mut(), page_id, &mut page)?;
+        assert_eq!(page.0, page_id);
+        assert_eq!(page.1[0], 42);
+
+        buffer_manager.delete_page(page_id)?;
+        assert!(!buffer_manager.page_table.contains_key(&page_id));
+        assert_eq!(disk.borrow().free_list(), &[page_id]);
+        assert_eq!(
+            buffer_manager.pin(page_id),
+            Err(BufferManagerError::InvalidPageID(page_id))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+// Tests for pinning several pages at once
+
+#[cfg(test)]
+mod pin_many {
+    use crate::buffer::buffer_manager::*;
+    use crate::buffer::*;
+    use crate::{BUFFER_POOL_SIZE, PageID};
+
+    fn descriptor<R: ReplacementStrategyTrait>(
+        buffer_manager: &BufferManager<DummyDiskManager, R>,
+        page_id: PageID,
+    ) -> (u16, bool) {
+        let fd = &buffer_manager.frame_descriptors[buffer_manager.page_table[&page_id]];
+        (fd.pin_count, fd.dirty)
+    }
+
+    #[test]
+    fn copy_between_pages() -> Result<(), BufferManagerError> {
+        let mut buffer_manager = BufferManager::new(disk(8), LRUReplacementStrategy::default());
+        {
+            let mut pinned = buffer_manager.pin_many(&[PageID(5), PageID(2)])?;
+            assert_eq!(pinned.len(), 2);
+            assert_eq!(
+                pinned.page_ids().collect::<Vec<_>>(),
+                vec![PageID(5), PageID(2)]
+            );
+            assert_eq!(pinned.get(0).1[0], 5);
+
+            let [source, target] = &mut pinned.pages_mut()[..] else {
+                panic!("Expected two pages");
+            };
+            target.1.copy_from_slice(&source.1);
+        }
+        assert_eq!(descriptor(&buffer_manager, PageID(5)), (0, true));
+        assert_eq!(descriptor(&buffer_manager, PageID(2)), (0, true));
+        assert_eq!(buffer_manager.pin_read(PageID(2))?.1[0], 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_written_pages_become_dirty() -> Result<(), BufferManagerError> {
+        let mut buffer_manager = BufferManager::new(disk(8), ClockReplacementStrategy::default());
+        {
+            let mut pinned = buffer_manager.pin_many(&[PageID(1), PageID(3), PageID(7)])?;
+            assert_eq!(pinned.pages().iter().map(|page| page.1[0]).sum::<u8>(), 11);
+            pinned.get_mut(1).1[1] = 9;
+        }
+        assert_eq!(descriptor(&buffer_manager, PageID(1)), (0, false));
+        assert_eq!(descriptor(&buffer_manager, PageID(3)), (0, true));
+        assert_eq!(descriptor(&buffer_manager, PageID(7)), (0, false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let mut buffer_manager = BufferManager::new(disk(8), LRUReplacementStrategy::default());
+        assert!(matches!(
+            buffer_manager.pin_many(&[PageID(1), PageID(2), PageID(1)]),
+            Err(BufferManagerError::DuplicatePageID(PageID(1)))
+        ));
+        assert!(buffer_manager.page_table.is_empty());
+    }
+
+    #[test]
+    fn failure_unpins_pages_pinned_so_far() -> Result<(), BufferManagerError> {
+        let mut buffer_manager = BufferManager::new(disk(4), LRUReplacementStrategy::default());
+        assert!(matches!(
+            buffer_manager.pin_many(&[PageID(1), PageID(2), PageID(9)]),
+            Err(BufferManagerError::InvalidPageID(PageID(9)))
+        ));
+        assert_eq!(descriptor(&buffer_manager, PageID(1)), (0, false));
+        assert_eq!(descriptor(&buffer_manager, PageID(2)), (0, false));
+
+        // More pages than frames cannot be pinned together.
+        let pages = BUFFER_POOL_SIZE + 2;
+        let mut buffer_manager = BufferManager::new(disk(pages), LRUReplacementStrategy::default());
+        let page_ids: Vec<PageID> = (1..pages).map(PageID).collect();
+        assert!(matches!(
+            buffer_manager.pin_many(&page_ids),
+            Err(BufferManagerError::AllPagesPinned)
+        ));
+        assert!(
+            buffer_manager
+                .frame_descriptors
+                .iter()
+                .all(|fd| fd.pin_count == 0)
+        );
+
+        Ok(())
+    }
+}
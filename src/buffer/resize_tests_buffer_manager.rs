@@ -0,0 +1,133 @@
+// Tests for growing and shrinking the buffer pool
+
+#[cfg(test)]
+mod resize {
+    use crate::buffer::buffer_manager::*;
+    use crate::buffer::*;
+    use crate::{FrameID, PageID};
+
+    /// Loads pages `first..=last` unpinned, in order.
+    fn load<R: ReplacementStrategyTrait>(
+        buffer_manager: &mut BufferManager<DummyDiskManager, R>,
+        first: usize,
+        last: usize,
+    ) -> Result<(), BufferManagerError> {
+        for i in first..=last {
+            buffer_manager.pin(PageID(i))?;
+            buffer_manager.unpin(PageID(i), false);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn grow_adds_frames() -> Result<(), BufferManagerError> {
+        let mut buffer_manager =
+            BufferManager::with_capacity(disk(5), ClockReplacementStrategy::default(), 2);
+        load(&mut buffer_manager, 1, 2)?;
+
+        buffer_manager.resize(4)?;
+        assert_eq!(buffer_manager.pool.len(), 4);
+        assert_eq!(buffer_manager.frame_descriptors.len(), 4);
+        load(&mut buffer_manager, 3, 4)?;
+        assert_eq!(buffer_manager.last_evict, PageID(0));
+        assert_eq!(buffer_manager.page_table.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn shrink_writes_back_evicted_pages() -> Result<(), BufferManagerError> {
+        let disk = disk(5);
+        let mut buffer_manager =
+            BufferManager::with_capacity(disk.clone(), LRUReplacementStrategy::default(), 4);
+        for i in 1..=4 {
+            buffer_manager.pin(PageID(i))?.1[0] = 10 * i as u8;
+            buffer_manager.unpin(PageID(i), true);
+        }
+
+        buffer_manager.resize(2)?;
+        assert_eq!(buffer_manager.pool.len(), 2);
+        assert_eq!(buffer_manager.page_table.len(), 2);
+        assert!(buffer_manager.page_table.values().all(|frame| frame.0 < 2));
+        for i in 3..=4 {
+            assert!(!buffer_manager.page_table.contains_key(&PageID(i)));
+            assert_eq!(disk.borrow().pages[i].1[0], 10 * i as u8);
+        }
+
+        for i in 1..=4 {
+            assert_eq!(buffer_manager.pin(PageID(i))?.1[0], 10 * i as u8);
+            buffer_manager.unpin(PageID(i), false);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn shrink_keeps_pinned_pages() -> Result<(), BufferManagerError> {
+        let mut buffer_manager =
+            BufferManager::with_capacity(disk(5), LRUReplacementStrategy::default(), 4);
+        load(&mut buffer_manager, 1, 2)?;
+        buffer_manager.pin(PageID(3))?.1[0] = 33;
+        buffer_manager.pin(PageID(4))?;
+        buffer_manager.pin(PageID(4))?;
+
+        buffer_manager.resize(2)?;
+        assert_eq!(buffer_manager.page_table.len(), 2);
+        assert!(!buffer_manager.page_table.contains_key(&PageID(1)));
+        assert!(!buffer_manager.page_table.contains_key(&PageID(2)));
+        assert_eq!(buffer_manager.last_evict, PageID(2));
+
+        let frame = buffer_manager.page_table[&PageID(3)];
+        assert_eq!(buffer_manager.frame_descriptors[frame].pin_count, 1);
+        assert_eq!(buffer_manager.pool[frame].1[0], 33);
+        let frame = buffer_manager.page_table[&PageID(4)];
+        assert_eq!(buffer_manager.frame_descriptors[frame].pin_count, 2);
+
+        buffer_manager.unpin(PageID(3), false);
+        load(&mut buffer_manager, 1, 1)?;
+        assert_eq!(buffer_manager.last_evict, PageID(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shrink_fails_if_too_many_pages_are_pinned() -> Result<(), BufferManagerError> {
+        let mut buffer_manager =
+            BufferManager::with_capacity(disk(5), ClockReplacementStrategy::default(), 4);
+        for i in 1..=3 {
+            buffer_manager.pin(PageID(i))?;
+        }
+
+        assert_eq!(
+            buffer_manager.resize(2),
+            Err(BufferManagerError::AllPagesPinned)
+        );
+        assert_eq!(buffer_manager.pool.len(), 4);
+        assert_eq!(buffer_manager.page_table.len(), 3);
+        load(&mut buffer_manager, 4, 4)?;
+        assert_eq!(buffer_manager.last_evict, PageID(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shrink_moves_pages_into_empty_frames() -> Result<(), BufferManagerError> {
+        let mut buffer_manager =
+            BufferManager::with_capacity(disk(5), ClockReplacementStrategy::default(), 4);
+        load(&mut buffer_manager, 1, 4)?;
+        buffer_manager.delete_page(PageID(1))?;
+
+        buffer_manager.resize(3)?;
+        assert_eq!(buffer_manager.last_evict, PageID(0));
+        assert_eq!(buffer_manager.page_table[&PageID(4)], FrameID(0));
+        assert_eq!(buffer_manager.pin(PageID(4))?.1[0], 4);
+        buffer_manager.unpin(PageID(4), false);
+
+        // The pool is full again, so the next page evicts one.
+        load(&mut buffer_manager, 1, 1)?;
+        assert_ne!(buffer_manager.last_evict, PageID(0));
+        assert_eq!(buffer_manager.page_table.len(), 3);
+
+        Ok(())
+    }
+}
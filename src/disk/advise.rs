@@ -0,0 +1,128 @@
+//! Access-pattern hints
+//!
+//! Callers that know how they are going to access pages, e.g. a table scan or random index
+//! lookups, tell the [`DiskManager`] with [`DiskManager::advise`]. The hint is passed on to the
+//! [`StorageBackend`](crate::disk::storage::StorageBackend), which maps it to `posix_fadvise` for
+//! files. Hints never change what is read or written and backends are free to ignore them.
+//!
+//! In addition, [`DiskManager::read`] detects runs of consecutive [`PageID`]s. Once a run is
+//! [`SEQUENTIAL_THRESHOLD`] reads long, the pages following it are announced with
+//! [`Advice::WillNeed`] in windows of [`DiskManager::read_ahead`] pages, so the operating system
+//! can read them ahead.
+
+use crate::PageID;
+use crate::disk::disk_manager::io_error;
+use crate::disk::{DiskManager, DiskManagerError, IoOp};
+use std::ops::Range;
+
+/// Number of consecutive page reads after which read-ahead starts.
+pub const SEQUENTIAL_THRESHOLD: usize = 4;
+
+/// Default number of pages read ahead at once, see [`DiskManager::set_read_ahead`].
+pub const DEFAULT_READ_AHEAD: usize = 32;
+
+/// How a range of pages is going to be accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// The pages will be read in ascending order.
+    Sequential,
+    /// The pages will be read in no particular order, reading ahead is wasted.
+    Random,
+    /// The pages will be read soon.
+    WillNeed,
+    /// The pages will not be read soon.
+    DontNeed,
+}
+
+/// Recognizes runs of consecutive page reads.
+#[derive(Debug, Clone)]
+pub(crate) struct SequentialDetector {
+    /// Page read last, `None` before the first read.
+    last: Option<PageID>,
+    /// Number of consecutive pages read up to and including `last`.
+    run: usize,
+    /// End of the pages announced for read-ahead.
+    ahead_until: PageID,
+    /// Pages to read ahead at once, 0 if read-ahead is disabled.
+    window: usize,
+}
+
+impl Default for SequentialDetector {
+    fn default() -> Self {
+        SequentialDetector {
+            last: None,
+            run: 0,
+            ahead_until: PageID(0),
+            window: DEFAULT_READ_AHEAD,
+        }
+    }
+}
+
+impl SequentialDetector {
+    /// Records a read of `page_id`. Returns the range to read ahead, if any.
+    fn record(&mut self, page_id: PageID, next_free: PageID) -> Option<Range<PageID>> {
+        if self.last.is_some_and(|last| last.0 + 1 == page_id.0) {
+            self.run += 1;
+        } else {
+            self.run = 1;
+            self.ahead_until = PageID(0);
+        }
+        self.last = Some(page_id);
+
+        if self.window == 0 || self.run < SEQUENTIAL_THRESHOLD {
+            return None;
+        }
+        // Announce the next window once the reader enters the second half of the current one.
+        if page_id.0 + self.window / 2 < self.ahead_until.0 {
+            return None;
+        }
+        let start = self.ahead_until.max(PageID(page_id.0 + 1));
+        let end = PageID((start.0 + self.window).min(next_free.0));
+        if start >= end {
+            return None;
+        }
+        self.ahead_until = end;
+        Some(start..end)
+    }
+}
+
+impl DiskManager {
+    /// Tells the storage how the pages in `pages` are going to be accessed.
+    ///
+    /// The range is clipped to the allocated pages. Backends that do not support hints ignore
+    /// them.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::Io`] if the backend rejects the hint.
+    pub fn advise(&mut self, pages: Range<PageID>, advice: Advice) -> Result<(), DiskManagerError> {
+        let end = pages.end.min(self.next_free);
+        if pages.start >= end {
+            return Ok(());
+        }
+        let offset = self.offset(pages.start);
+        let len = self.offset(end) - offset;
+        self.storage
+            .advise(offset, len, advice)
+            .map_err(io_error(IoOp::Read, pages.start))
+    }
+
+    /// Sets the number of pages announced at once when sequential reads are detected. 0 disables
+    /// the detection.
+    pub fn set_read_ahead(&mut self, pages: usize) {
+        self.access.window = pages;
+    }
+
+    /// Number of pages read ahead at once, see [`DiskManager::set_read_ahead`].
+    pub fn read_ahead(&self) -> usize {
+        self.access.window
+    }
+
+    /// Feeds a read of `page_id` to the sequential-access detector and issues read-ahead.
+    ///
+    /// Read-ahead is only a hint, failures are ignored.
+    pub(crate) fn track_read(&mut self, page_id: PageID) {
+        if let Some(pages) = self.access.record(page_id, self.next_free) {
+            let _ = self.advise(pages, Advice::WillNeed);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// Tests for access-pattern hints and sequential read-ahead
+
+#[cfg(test)]
+mod advise {
+    use crate::disk::advise::*;
+    use crate::disk::storage::*;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    type Hints = Arc<Mutex<Vec<(u64, u64, Advice)>>>;
+
+    /// In-memory storage that records the hints it gets.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        inner: MemoryBackend,
+        hints: Hints,
+    }
+
+    impl StorageBackend for Recorder {
+        fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            self.inner.read_at(offset, buf)
+        }
+
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+            self.inner.write_at(offset, buf)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.inner.sync()
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            self.inner.len()
+        }
+
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.inner.set_len(len)
+        }
+
+        fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+            self.hints.lock().unwrap().push((offset, len, advice));
+            Ok(())
+        }
+    }
+
+    /// Creates a database with `pages` allocated pages whose hints end up in the returned list.
+    fn recording(pages: usize) -> Result<(DiskManager, Hints), DiskManagerError> {
+        let storage = Recorder::default();
+        let hints = storage.hints.clone();
+        let mut dm = DiskManager::with_backend(Box::new(storage))?;
+        for _ in 0..pages {
+            dm.allocate()?;
+        }
+        Ok((dm, hints))
+    }
+
+    fn bytes(pages: usize) -> u64 {
+        (pages * PAGE_SIZE) as u64
+    }
+
+    #[test]
+    fn advise_is_clipped_to_allocated_pages() -> Result<(), DiskManagerError> {
+        let (mut dm, hints) = recording(10)?;
+        dm.advise(PageID(2)..PageID(5), Advice::Random)?;
+        dm.advise(PageID(8)..PageID(100), Advice::DontNeed)?;
+        dm.advise(PageID(20)..PageID(30), Advice::Sequential)?;
+        assert_eq!(
+            *hints.lock().unwrap(),
+            vec![
+                (bytes(2), bytes(3), Advice::Random),
+                (bytes(8), bytes(3), Advice::DontNeed),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn sequential_reads_trigger_read_ahead() -> Result<(), DiskManagerError> {
+        let (mut dm, hints) = recording(100)?;
+        dm.set_read_ahead(8);
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in 1..SEQUENTIAL_THRESHOLD {
+            dm.read(PageID(i), &mut buf)?;
+        }
+        assert!(hints.lock().unwrap().is_empty());
+
+        dm.read(PageID(SEQUENTIAL_THRESHOLD), &mut buf)?;
+        let start = SEQUENTIAL_THRESHOLD + 1;
+        assert_eq!(
+            *hints.lock().unwrap(),
+            vec![(bytes(start), bytes(8), Advice::WillNeed)]
+        );
+
+        // The next window is announced halfway through the current one.
+        for i in start..start + 4 {
+            dm.read(PageID(i), &mut buf)?;
+        }
+        assert_eq!(hints.lock().unwrap().len(), 1);
+        dm.read(PageID(start + 4), &mut buf)?;
+        assert_eq!(
+            hints.lock().unwrap()[1],
+            (bytes(start + 8), bytes(8), Advice::WillNeed)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn random_reads_do_not_read_ahead() -> Result<(), DiskManagerError> {
+        let (mut dm, hints) = recording(100)?;
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in [7, 3, 50, 51, 52, 20, 21, 22, 90, 1] {
+            dm.read(PageID(i), &mut buf)?;
+        }
+        assert!(hints.lock().unwrap().is_empty());
+
+        dm.set_read_ahead(0);
+        for i in 1..20 {
+            dm.read(PageID(i), &mut buf)?;
+        }
+        assert!(hints.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_ahead_stops_at_next_free() -> Result<(), DiskManagerError> {
+        let (mut dm, hints) = recording(6)?;
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in 1..=6 {
+            dm.read(PageID(i), &mut buf)?;
+        }
+        assert_eq!(
+            *hints.lock().unwrap(),
+            vec![(bytes(5), bytes(2), Advice::WillNeed)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_backend_accepts_hints() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::new("/tmp/database_advise_file.dmdb")?;
+        for _ in 0..8 {
+            let pid = dm.allocate()?;
+            dm.write(pid, &[pid.0 as u8; PAGE_SIZE])?;
+        }
+        for advice in [
+            Advice::Sequential,
+            Advice::Random,
+            Advice::WillNeed,
+            Advice::DontNeed,
+        ] {
+            dm.advise(PageID(1)..PageID(9), advice)?;
+        }
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in 1..=8 {
+            dm.read(PageID(i), &mut buf)?;
+            assert_eq!(buf, [i as u8; PAGE_SIZE]);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! Online backup of a database file
+//!
+//! A backup captures the state of a [`DiskManager`] at the moment [`DiskManager::start_backup`]
+//! is called, including `next_free` and the free list, while the disk manager stays in use.
+//! Pages are copied in small batches with [`DiskManager::backup_step`] between regular
+//! operations. If a page that has not been copied yet is about to be overwritten,
+//! [`DiskManager::write`] copies its old contents to the backup first (copy-before-write).
+//!
+//! Pages are copied as stored, so the backup of an encrypted database is encrypted with the same
+//! key.
+//!
+//! The backup is a regular database file that can be opened with [`DiskManager::open`]. The
+//! [`BackupManifest`] returned by [`DiskManager::finish_backup`] holds a checksum of every copied
+//! page and is used to verify the backup.
+
+use crate::PageID;
+use crate::disk::checksum::page_checksum;
+use crate::disk::disk_manager::invalid_data;
+use crate::disk::incremental::ChangeTracker;
+use crate::disk::{DiskManager, DiskManagerError};
+use std::collections::HashSet;
+use std::io;
+
+/// State of a backup in progress.
+#[derive(Debug)]
+pub(crate) struct BackupState {
+    /// The backup file. Its `next_free` and free list are the ones captured at the start.
+    target: DiskManager,
+    /// Next page visited by [`DiskManager::backup_step`].
+    cursor: usize,
+    /// `pending[i]` is true if page `i` was in use at the start and has not been copied yet.
+    pending: Vec<bool>,
+    /// `written[i]` is true if page `i` had a checksum at the start, see
+    /// [`DiskManager::copy_to_backup`].
+    written: Vec<bool>,
+    /// Checksums of the copied pages, `None` for pages that are free or were never written.
+    checksums: Vec<Option<u32>>,
+}
+
+/// Result of a finished backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupManifest {
+    /// Backup epoch started by this backup. Pass it to [`DiskManager::incremental_backup`] to
+    /// back up the changes made after this backup.
+    pub epoch: u64,
+    /// Epoch the changes of an incremental backup are relative to, `None` for full backups.
+    pub base_epoch: Option<u64>,
+    /// `next_free` of the source at the start of the backup.
+    pub next_free: PageID,
+    /// Free list of the source at the start of the backup.
+    pub free_list: Vec<PageID>,
+    /// Checksum of every page in `[0, next_free)` as copied from the source. `None` for pages
+    /// that were free or never written.
+    pub checksums: Vec<Option<u32>>,
+}
+
+impl BackupManifest {
+    /// Compares the full backup in `path` with the source state captured in this manifest.
+    ///
+    /// Returns the pages whose checksum in the backup differs from the checksum of the source.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::IOError`] if the backup cannot be opened or read.
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::InvalidData`] if the allocator
+    ///   metadata of the backup differs from the manifest.
+    pub fn verify(&self, path: &str) -> Result<Vec<PageID>, DiskManagerError> {
+        let mut backup = DiskManager::open_stored(path)?;
+        if backup.next_free != self.next_free || !backup.free_list.iter().eq(&self.free_list) {
+            return Err(invalid_data("allocator metadata of backup differs"));
+        }
+
+        let mut frame = vec![0u8; backup.frame_size()];
+        let mut mismatched = Vec::new();
+        for (i, checksum) in self.checksums.iter().enumerate() {
+            let Some(expected) = checksum else {
+                continue;
+            };
+            backup.read_at(backup.offset(PageID(i)), &mut frame)?;
+            if page_checksum(&frame) != *expected {
+                mismatched.push(PageID(i));
+            }
+        }
+        Ok(mismatched)
+    }
+}
+
+impl DiskManager {
+    /// Starts a backup of the current state into a new database file at `path`.
+    ///
+    /// Captures `next_free` and the free list and starts a new backup epoch. No pages are copied
+    /// yet, see [`DiskManager::backup_step`] and [`DiskManager::finish_backup`].
+    ///
+    /// # Errors
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::ResourceBusy`] if a backup is already
+    ///   in progress.
+    /// - Returns [`DiskManagerError::IOError`] if creating the backup file fails.
+    pub fn start_backup(&mut self, path: &str) -> Result<(), DiskManagerError> {
+        if self.backup.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                "a backup is already in progress",
+            )
+            .into());
+        }
+
+        let free: HashSet<PageID> = self.free_list.iter().copied().collect();
+        let pending = (0..self.next_free.0)
+            .map(|i| i != 0 && !free.contains(&PageID(i)))
+            .collect();
+        // Taken now, as freeing a page during the backup clears its checksum.
+        let written = (0..self.next_free.0)
+            .map(|i| self.checksum(PageID(i)).is_some())
+            .collect();
+        let mut target = DiskManager::new(path)?;
+        target.next_free = self.next_free;
+        target.free_list = self.free_list.clone();
+        target.encryption = self.encryption.clone();
+        target.secure_delete = self.secure_delete;
+        target.changes = ChangeTracker::starting_at(self.changes.close_epoch());
+
+        self.backup = Some(Box::new(BackupState {
+            target,
+            cursor: 1,
+            pending,
+            written,
+            checksums: vec![None; self.next_free.0],
+        }));
+        Ok(())
+    }
+
+    /// Copies up to `max_pages` pages of the backup in progress.
+    ///
+    /// Returns `true` once all pages have been copied.
+    ///
+    /// # Errors
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::InvalidInput`] if no backup is in
+    ///   progress.
+    /// - Return [`DiskManagerError::IOError`] if file operations return an [`io::Error`]. The
+    ///   backup stays in progress.
+    pub fn backup_step(&mut self, max_pages: usize) -> Result<bool, DiskManagerError> {
+        let mut backup = self.backup.take().ok_or_else(no_backup)?;
+
+        let mut result = Ok(());
+        let mut copied = 0;
+        while copied < max_pages && backup.cursor < backup.pending.len() {
+            let page_id = PageID(backup.cursor);
+            if backup.pending[page_id.0] {
+                result = self.copy_to_backup(&mut backup, page_id);
+                if result.is_err() {
+                    break;
+                }
+                copied += 1;
+            }
+            backup.cursor += 1;
+        }
+        let done = backup.cursor >= backup.pending.len();
+
+        self.backup = Some(backup);
+        result.map(|_| done)
+    }
+
+    /// Copies all remaining pages and writes the captured allocator metadata to the backup.
+    ///
+    /// # Errors
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::InvalidInput`] if no backup is in
+    ///   progress.
+    /// - Return [`DiskManagerError::IOError`] if file operations return an [`io::Error`].
+    pub fn finish_backup(&mut self) -> Result<BackupManifest, DiskManagerError> {
+        while !self.backup_step(usize::MAX)? {}
+        let mut backup = self.backup.take().ok_or_else(no_backup)?;
+
+        let target = &mut backup.target;
+        target.storage.set_len(target.offset(target.next_free))?;
+        target.sync_metadata()?;
+
+        Ok(BackupManifest {
+            epoch: target.changes.epoch(),
+            base_epoch: None,
+            next_free: target.next_free,
+            free_list: target.free_list.iter().copied().collect(),
+            checksums: backup.checksums,
+        })
+    }
+
+    /// Abandons the backup in progress. The partially written backup file is left behind.
+    pub fn abort_backup(&mut self) {
+        self.backup = None;
+    }
+
+    /// Writes a consistent backup of the current state to `path`.
+    ///
+    /// Shorthand for [`DiskManager::start_backup`] followed by [`DiskManager::finish_backup`].
+    ///
+    /// # Errors
+    /// See [`DiskManager::start_backup`] and [`DiskManager::finish_backup`].
+    pub fn backup_to(&mut self, path: &str) -> Result<BackupManifest, DiskManagerError> {
+        self.start_backup(path)?;
+        self.finish_backup()
+    }
+
+    /// Copies the current contents of `page_id` to the backup in progress, if the page still has
+    /// to be copied. Called by [`DiskManager::write`] and [`DiskManager::free`] before a page is
+    /// changed.
+    pub(crate) fn copy_before_write(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        let Some(mut backup) = self.backup.take() else {
+            return Ok(());
+        };
+        let result = if backup.pending.get(page_id.0).copied().unwrap_or(false) {
+            self.copy_to_backup(&mut backup, page_id)
+        } else {
+            Ok(())
+        };
+        self.backup = Some(backup);
+        result
+    }
+
+    /// Copies `page_id` from the database file to the backup file.
+    ///
+    /// Pages without a checksum at the start of the backup were either never written or written
+    /// after the last [`DiskManager::sync_metadata`] of a reopened file. They are copied unless
+    /// they hold zeros, which they read as from the backup.
+    fn copy_to_backup(
+        &mut self,
+        backup: &mut BackupState,
+        page_id: PageID,
+    ) -> Result<(), DiskManagerError> {
+        let written = backup.written[page_id.0];
+        let offset = self.offset(page_id);
+        let mut frame = vec![0u8; self.frame_size()];
+        if written {
+            self.read_at(offset, &mut frame)?;
+        } else {
+            self.read_at_or_zeros(offset, &mut frame)?;
+        }
+        if written || frame.iter().any(|&byte| byte != 0) {
+            let checksum = page_checksum(&frame);
+            backup.target.write_at(offset, &frame)?;
+            backup.target.set_checksum(page_id, Some(checksum));
+            backup.checksums[page_id.0] = Some(checksum);
+        }
+        backup.pending[page_id.0] = false;
+        Ok(())
+    }
+}
+
+fn no_backup() -> DiskManagerError {
+    io::Error::new(io::ErrorKind::InvalidInput, "no backup in progress").into()
+}
@@ -0,0 +1,212 @@
+// Tests for persisted allocator metadata and online backups
+
+#[cfg(test)]
+mod backup {
+    use crate::disk::checksum::page_checksum;
+    use crate::disk::incremental::restore;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::FileExt;
+
+    /// Allocates and writes `count` pages, page `i` filled with `i`, and frees every third page.
+    fn populate(dm: &mut DiskManager, count: u8) -> Result<(), DiskManagerError> {
+        for i in 1..=count {
+            let pid = dm.allocate()?;
+            dm.write(pid, &page(i))?;
+        }
+        for i in (3..=count).step_by(3) {
+            dm.free(PageID(i as usize))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn sync_metadata_and_open() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_backup_reopen.dmdb";
+        {
+            let mut dm = DiskManager::new(filename)?;
+            populate(&mut dm, 20)?;
+            dm.sync_metadata()?;
+        }
+
+        let mut dm = DiskManager::open(filename)?;
+        assert_eq!(dm.next_free(), PageID(21));
+        assert_eq!(dm.free_list(), &[3, 6, 9, 12, 15, 18].map(PageID));
+
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(PageID(7), &mut buf)?;
+        assert_eq!(buf, page(7));
+        assert_eq!(dm.allocate()?, PageID(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn pages_written_after_sync_metadata_survive_open() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_backup_unsynced.dmdb";
+        let backup_path = "/tmp/database_backup_unsynced.bak";
+        let late = {
+            let mut dm = DiskManager::new(filename)?;
+            populate(&mut dm, 20)?;
+            let late = dm.allocate()?;
+            dm.sync_metadata()?;
+            dm.write(PageID(7), &page(70))?;
+            dm.write(late, &page(100))?;
+            late
+        };
+
+        let mut dm = DiskManager::open(filename)?;
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(PageID(7), &mut buf)?;
+        assert_eq!(buf, page(70));
+        dm.read(late, &mut buf)?;
+        assert_eq!(buf, page(100));
+
+        dm.backup_to(backup_path)?;
+        let mut backup = DiskManager::open(backup_path)?;
+        backup.read(late, &mut buf)?;
+        assert_eq!(buf, page(100));
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_requires_superblock() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_backup_no_superblock.dmdb";
+        let mut dm = DiskManager::new(filename)?;
+        let pid = dm.allocate()?;
+        dm.write(pid, &page(1))?;
+
+        assert!(matches!(
+            DiskManager::open(filename),
+            Err(DiskManagerError::IOError(err)) if err.kind() == std::io::ErrorKind::InvalidData
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn backup_to_copies_pages_and_metadata() -> Result<(), DiskManagerError> {
+        let backup_path = "/tmp/database_backup_full.bak";
+        let mut dm = DiskManager::new("/tmp/database_backup_full.dmdb")?;
+        let unwritten = PageID(31);
+        populate(&mut dm, 30)?;
+        dm.free_list.clear();
+        assert_eq!(dm.allocate()?, unwritten);
+        for i in (3..=30).step_by(3) {
+            dm.free(PageID(i))?;
+        }
+
+        let manifest = dm.backup_to(backup_path)?;
+        assert_eq!(manifest.next_free, PageID(32));
+        assert_eq!(manifest.checksums[unwritten.0], None);
+        assert_eq!(manifest.checksums[5], Some(page_checksum(&page(5))));
+        assert_eq!(manifest.verify(backup_path)?, vec![]);
+
+        let mut backup = DiskManager::open(backup_path)?;
+        assert_eq!(backup.next_free(), dm.next_free());
+        assert_eq!(backup.free_list(), dm.free_list());
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in (1..=30u8).filter(|i| i % 3 != 0) {
+            backup.read(PageID(i as usize), &mut buf)?;
+            assert_eq!(buf, page(i), "PID {i} differs in backup");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn online_backup_keeps_start_state() -> Result<(), DiskManagerError> {
+        let backup_path = "/tmp/database_backup_online.bak";
+        let mut dm = DiskManager::new("/tmp/database_backup_online.dmdb")?;
+        populate(&mut dm, 30)?;
+        let free_list = dm.free_list().clone();
+
+        dm.start_backup(backup_path)?;
+        assert!(!dm.backup_step(5)?);
+
+        // Keep using the disk manager: overwrite pages before and after the backup cursor,
+        // reuse a free page and grow the file.
+        dm.write(PageID(1), &page(101))?;
+        dm.write(PageID(29), &page(129))?;
+        let reused = dm.allocate()?;
+        dm.write(reused, &page(103))?;
+        let new = dm.allocate()?;
+        dm.write(new, &page(131))?;
+        dm.free(PageID(2))?;
+
+        while !dm.backup_step(5)? {
+            dm.write(PageID(28), &page(128))?;
+        }
+        let manifest = dm.finish_backup()?;
+        assert_eq!(manifest.verify(backup_path)?, vec![]);
+
+        let mut backup = DiskManager::open(backup_path)?;
+        assert_eq!(backup.next_free(), PageID(31));
+        assert_eq!(backup.free_list(), &free_list);
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in (1..=30u8).filter(|i| i % 3 != 0) {
+            backup.read(PageID(i as usize), &mut buf)?;
+            assert_eq!(buf, page(i), "PID {i} was not backed up as of the start");
+        }
+
+        dm.read(PageID(29), &mut buf)?;
+        assert_eq!(buf, page(129));
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_freed_during_backup_is_restored() -> Result<(), DiskManagerError> {
+        let backup_path = "/tmp/database_backup_freed.bak";
+        let mut dm = DiskManager::new("/tmp/database_backup_freed.dmdb")?;
+        populate(&mut dm, 20)?;
+
+        dm.start_backup(backup_path)?;
+        assert!(!dm.backup_step(3)?);
+        // Page 8 is not copied yet. Persisting the free list writes a link into it.
+        dm.free(PageID(8))?;
+        dm.sync_metadata()?;
+        let manifest = dm.finish_backup()?;
+        assert_eq!(manifest.checksums[8], Some(page_checksum(&page(8))));
+        assert_eq!(manifest.verify(backup_path)?, vec![]);
+
+        let mut restored = restore("/tmp/database_backup_freed_restored.dmdb", backup_path, &[])?;
+        assert!(!restored.free_list().contains(&PageID(8)));
+        let mut buf = [0u8; PAGE_SIZE];
+        restored.read(PageID(8), &mut buf)?;
+        assert_eq!(buf, page(8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_detects_damaged_pages() -> Result<(), DiskManagerError> {
+        let backup_path = "/tmp/database_backup_damaged.bak";
+        let mut dm = DiskManager::new("/tmp/database_backup_damaged.dmdb")?;
+        populate(&mut dm, 10)?;
+        let manifest = dm.backup_to(backup_path)?;
+
+        let file = OpenOptions::new().write(true).open(backup_path)?;
+        file.write_all_at(&[0xAB], (4 * PAGE_SIZE + 17) as u64)?;
+
+        assert_eq!(manifest.verify(backup_path)?, vec![PageID(4)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn one_backup_at_a_time() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::new("/tmp/database_backup_busy.dmdb")?;
+        populate(&mut dm, 4)?;
+
+        assert!(dm.backup_step(1).is_err());
+        dm.start_backup("/tmp/database_backup_busy_1.bak")?;
+        assert!(dm.start_backup("/tmp/database_backup_busy_2.bak").is_err());
+        dm.abort_backup();
+        assert!(dm.finish_backup().is_err());
+
+        Ok(())
+    }
+}
@@ -18,7 +18,7 @@ mod basic {
         let mut sample = vec![];
 
         for _ in 0..100 {
-            let pid = dm.allocate();
+            let pid = dm.allocate()?;
             let mut page = test_page;
             page[0] = pid.0 as u8;
             dm.write(pid, &page)?;
@@ -48,16 +48,12 @@ mod basic {
         let free_list = vec![PageID(5)];
 
         {
-            let mut dm = DiskManager {
-                file,
-                next_free,
-                free_list: free_list.into(),
-            };
-            assert_eq!(dm.allocate(), PageID(5));
+            let mut dm = DiskManager::from_parts(file, next_free, free_list.into());
+            assert_eq!(dm.allocate()?, PageID(5));
             assert_eq!(dm.next_free, PageID(10));
             assert_eq!(dm.free_list, vec![]);
 
-            assert_eq!(dm.allocate(), PageID(10));
+            assert_eq!(dm.allocate()?, PageID(10));
             assert_eq!(dm.next_free, PageID(11));
             assert_eq!(dm.free_list, vec![]);
         }
@@ -78,11 +74,7 @@ mod basic {
         let free_list = vec![PageID(5)];
 
         {
-            let mut dm = DiskManager {
-                file,
-                next_free,
-                free_list: free_list.into(),
-            };
+            let mut dm = DiskManager::from_parts(file, next_free, free_list.into());
             dm.free(PageID(2))?;
             assert_eq!(dm.next_free, PageID(10));
             assert_eq!(dm.free_list, vec![PageID(5), PageID(2)]);
@@ -91,8 +83,8 @@ mod basic {
             assert!(result.is_err());
             let result = result.unwrap_err();
             match result {
-                DiskManagerError::InvalidPageID(pid) => assert_eq!(pid, PageID(15)),
-                _ => panic!("Expected InvalidPageID error"),
+                DiskManagerError::OutOfRange { page_id, .. } => assert_eq!(page_id, PageID(15)),
+                _ => panic!("Expected OutOfRange error"),
             }
             assert_eq!(dm.next_free, PageID(10));
             assert_eq!(dm.free_list, vec![PageID(5), PageID(2)]);
@@ -118,13 +110,9 @@ mod basic {
         let free_list = vec![PageID(5)];
 
         {
-            let mut dm = DiskManager {
-                file,
-                next_free,
-                free_list: free_list.into(),
-            };
+            let mut dm = DiskManager::from_parts(file, next_free, free_list.into());
 
-            assert_eq!(dm.allocate(), PageID(5));
+            assert_eq!(dm.allocate()?, PageID(5));
             assert_eq!(dm.next_free, PageID(10));
             assert_eq!(dm.free_list, vec![]);
 
@@ -136,17 +124,17 @@ mod basic {
             assert!(result.is_err());
             let result = result.unwrap_err();
             match result {
-                DiskManagerError::InvalidPageID(pid) => assert_eq!(pid, PageID(15)),
-                _ => panic!("Expected InvalidPageID error"),
+                DiskManagerError::OutOfRange { page_id, .. } => assert_eq!(page_id, PageID(15)),
+                _ => panic!("Expected OutOfRange error"),
             }
             assert_eq!(dm.next_free, PageID(10));
             assert_eq!(dm.free_list, vec![PageID(2)]);
 
-            assert_eq!(dm.allocate(), PageID(2));
+            assert_eq!(dm.allocate()?, PageID(2));
             assert_eq!(dm.next_free, PageID(10));
             assert_eq!(dm.free_list, vec![]);
 
-            assert_eq!(dm.allocate(), PageID(10));
+            assert_eq!(dm.allocate()?, PageID(10));
             assert_eq!(dm.next_free, PageID(11));
             assert_eq!(dm.free_list, vec![]);
 
@@ -160,7 +148,7 @@ mod basic {
             dm.free(PageID(2))?;
             assert_eq!(dm.next_free, PageID(11));
             assert_eq!(dm.free_list, vec![PageID(10), PageID(1), PageID(2)]);
-            assert_eq!(dm.allocate(), PageID(10));
+            assert_eq!(dm.allocate()?, PageID(10));
             assert_eq!(dm.next_free, PageID(11));
             assert_eq!(dm.free_list, vec![PageID(1), PageID(2)]);
         }
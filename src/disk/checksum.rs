@@ -0,0 +1,106 @@
+//! Page checksums
+//!
+//! [`DiskManager::write`] records a CRC-32 of every page it writes. [`DiskManager::sync_metadata`]
+//! stores the checksums as a table directly behind the last allocated page, starting at page
+//! `next_free`. The superblock records the number of table pages and a checksum of the table
+//! itself. [`DiskManager::allocate`] skips the table pages, so the table stays intact until the
+//! next [`DiskManager::sync_metadata`] writes a new one and puts the skipped pages on the free list.
+//!
+//! Pages written since the last [`DiskManager::sync_metadata`] have no entry in the table, so a
+//! missing checksum does not mean that the page holds no data.
+//!
+//! Every entry of the table is a u64 that holds the checksum in its low 32 bits. Bit 32 is set if
+//! the page was written since it was allocated.
+
+use crate::disk::disk_manager::invalid_data;
+use crate::disk::{DiskManager, DiskManagerError};
+use crate::{PAGE_SIZE, PageID};
+
+/// Number of checksum table entries per page.
+pub const ENTRIES_PER_PAGE: usize = PAGE_SIZE / size_of::<u64>();
+
+/// Marks table entries of pages that were written.
+const WRITTEN: u64 = 1 << 32;
+
+/// Checksum of the contents of a page as stored in the file.
+///
+/// For encrypted databases, `page` is the whole encrypted frame.
+pub fn page_checksum(page: &[u8]) -> u32 {
+    crc32fast::hash(page)
+}
+
+/// Number of pages of the checksum table of a file with the given `next_free`.
+pub fn table_pages(next_free: PageID) -> u64 {
+    next_free.0.div_ceil(ENTRIES_PER_PAGE) as u64
+}
+
+impl DiskManager {
+    /// Checksum of the contents last written to `page_id`.
+    ///
+    /// `None` if the page was not written since it was allocated.
+    pub fn checksum(&self, page_id: PageID) -> Option<u32> {
+        self.checksums.get(page_id.0).copied().flatten()
+    }
+
+    /// Records the checksum of the contents of `page_id`, `None` if the page holds no data.
+    pub(crate) fn set_checksum(&mut self, page_id: PageID, checksum: Option<u32>) {
+        if page_id.0 >= self.checksums.len() {
+            self.checksums.resize(page_id.0 + 1, None);
+        }
+        self.checksums[page_id.0] = checksum;
+    }
+
+    /// Writes the checksum table starting at page `next_free`.
+    ///
+    /// Returns the number of table pages and the checksum of the table.
+    pub(crate) fn write_checksum_table(&mut self) -> Result<(u64, u32), DiskManagerError> {
+        let pages = table_pages(self.next_free);
+        let mut table = vec![0u8; pages as usize * PAGE_SIZE];
+        for (i, entry) in table.chunks_exact_mut(size_of::<u64>()).enumerate() {
+            let word = match self.checksum(PageID(i)) {
+                Some(checksum) if i < self.next_free.0 => WRITTEN | checksum as u64,
+                _ => 0,
+            };
+            entry.copy_from_slice(&word.to_le_bytes());
+        }
+
+        self.write_at(self.offset(self.next_free), &table)?;
+        Ok((pages, crc32fast::hash(&table)))
+    }
+
+    /// Reads the checksum table written by [`DiskManager::write_checksum_table`].
+    ///
+    /// # Errors
+    /// - Returns an [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`] if the table
+    ///   does not match `pages` and `crc`.
+    /// - Return [`DiskManagerError::IOError`] if file operations return an error.
+    pub(crate) fn read_checksum_table(
+        &mut self,
+        pages: u64,
+        crc: u32,
+    ) -> Result<Vec<Option<u32>>, DiskManagerError> {
+        if pages != table_pages(self.next_free) {
+            return Err(invalid_data("checksum table has the wrong size"));
+        }
+
+        let mut table = vec![0u8; pages as usize * PAGE_SIZE];
+        match self.read_at(self.offset(self.next_free), &mut table) {
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Err(invalid_data("checksum table is missing"));
+            }
+            result => result?,
+        }
+        if crc32fast::hash(&table) != crc {
+            return Err(invalid_data("checksum table is damaged"));
+        }
+
+        Ok(table
+            .chunks_exact(size_of::<u64>())
+            .take(self.next_free.0)
+            .map(|entry| {
+                let word = u64::from_le_bytes(entry.try_into().unwrap());
+                (word & WRITTEN != 0).then_some(word as u32)
+            })
+            .collect())
+    }
+}
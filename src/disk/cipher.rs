@@ -0,0 +1,231 @@
+//! Encryption at rest
+//!
+//! A [`DiskManager`] created with [`DiskManager::new_encrypted`] passes every page through a
+//! [`PageCipher`] in [`DiskManager::write`] and [`DiskManager::read`]. The cipher turns a page
+//! into a frame of `PAGE_SIZE + overhead` bytes, so page `i` is stored at `i * frame_size`.
+//! Page 0 stays unencrypted and holds the [`Superblock`](crate::disk::superblock::Superblock),
+//! which records the overhead and a key check value of the cipher.
+//!
+//! Opening an encrypted file with a different key fails with [`DiskManagerError::WrongKey`]
+//! before any page is read. A page that fails authentication with the right key was damaged or
+//! moved to another [`PageID`].
+//!
+//! Checksums, backups and `sdms-fsck` work on the stored frames and do not need the key.
+
+use crate::disk::disk_manager::invalid_data;
+use crate::disk::superblock::Superblock;
+use crate::disk::{DiskManager, DiskManagerError, RawPage};
+use crate::{PAGE_SIZE, PageID};
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::Rng;
+use std::fmt;
+use std::sync::Arc;
+
+/// Size of the key check value stored in the superblock.
+pub const KEY_CHECK_SIZE: usize = 16;
+
+/// Encrypts and authenticates pages.
+///
+/// Implementations must bind the ciphertext to the [`PageID`], so a frame copied to another page
+/// fails to decrypt.
+pub trait PageCipher: fmt::Debug + Send + Sync {
+    /// Bytes stored per page in addition to [`PAGE_SIZE`], e.g. for a nonce and a tag.
+    fn overhead(&self) -> usize;
+
+    /// Value that identifies the key without revealing it. Stored in the superblock and compared
+    /// on open.
+    fn key_check(&self) -> [u8; KEY_CHECK_SIZE];
+
+    /// Encrypts `page` into `frame`, which has `PAGE_SIZE + overhead` bytes.
+    fn encrypt(&self, page_id: PageID, page: &RawPage, frame: &mut [u8]);
+
+    /// Decrypts `frame` into `page`.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::Corrupted`] if the frame fails authentication.
+    fn decrypt(
+        &self,
+        page_id: PageID,
+        frame: &[u8],
+        page: &mut RawPage,
+    ) -> Result<(), DiskManagerError>;
+}
+
+/// XChaCha20-Poly1305 with a random nonce per write and the [`PageID`] as associated data.
+///
+/// A frame consists of the nonce, the encrypted page and the tag.
+pub struct XChaChaPageCipher {
+    aead: XChaCha20Poly1305,
+}
+
+impl XChaChaPageCipher {
+    /// Size of the nonce stored in front of every page.
+    const NONCE_SIZE: usize = 24;
+    /// Size of the tag stored behind every page.
+    const TAG_SIZE: usize = 16;
+
+    /// Creates a cipher for a database with the given 256 bit key.
+    pub fn new(key: &[u8; 32]) -> Self {
+        XChaChaPageCipher {
+            aead: XChaCha20Poly1305::new(key.into()),
+        }
+    }
+}
+
+impl fmt::Debug for XChaChaPageCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("XChaChaPageCipher").finish_non_exhaustive()
+    }
+}
+
+impl PageCipher for XChaChaPageCipher {
+    fn overhead(&self) -> usize {
+        Self::NONCE_SIZE + Self::TAG_SIZE
+    }
+
+    fn key_check(&self) -> [u8; KEY_CHECK_SIZE] {
+        // The tag of an empty message under a fixed nonce depends on the key only.
+        let tag = self
+            .aead
+            .encrypt_in_place_detached(&XNonce::default(), b"sdms key check", &mut [])
+            .expect("encrypting an empty message cannot fail");
+        tag.into()
+    }
+
+    fn encrypt(&self, page_id: PageID, page: &RawPage, frame: &mut [u8]) {
+        let (nonce, rest) = frame.split_at_mut(Self::NONCE_SIZE);
+        let (data, tag) = rest.split_at_mut(PAGE_SIZE);
+        rand::rng().fill(nonce);
+        data.copy_from_slice(page);
+        let computed = self
+            .aead
+            .encrypt_in_place_detached(
+                XNonce::from_slice(nonce),
+                &(page_id.0 as u64).to_le_bytes(),
+                data,
+            )
+            .expect("a page is below the message size limit");
+        tag.copy_from_slice(&computed);
+    }
+
+    fn decrypt(
+        &self,
+        page_id: PageID,
+        frame: &[u8],
+        page: &mut RawPage,
+    ) -> Result<(), DiskManagerError> {
+        let (nonce, rest) = frame.split_at(Self::NONCE_SIZE);
+        let (data, tag) = rest.split_at(PAGE_SIZE);
+        page.copy_from_slice(data);
+        self.aead
+            .decrypt_in_place_detached(
+                XNonce::from_slice(nonce),
+                &(page_id.0 as u64).to_le_bytes(),
+                page,
+                tag.into(),
+            )
+            .map_err(|_| DiskManagerError::Corrupted(page_id))
+    }
+}
+
+/// Encryption settings of a database file.
+#[derive(Debug, Clone)]
+pub(crate) struct Encryption {
+    /// Bytes stored per page in addition to [`PAGE_SIZE`].
+    pub(crate) overhead: usize,
+    /// Key check value from the superblock.
+    pub(crate) key_check: [u8; KEY_CHECK_SIZE],
+    /// The cipher, `None` if the file was opened without a key, e.g. to check or back it up.
+    pub(crate) cipher: Option<Arc<dyn PageCipher>>,
+}
+
+impl Encryption {
+    /// Settings recorded in `superblock`, without a cipher. `None` if the file is not encrypted.
+    pub(crate) fn from_superblock(superblock: &Superblock) -> Option<Self> {
+        (superblock.cipher_overhead != 0).then(|| Encryption {
+            overhead: superblock.cipher_overhead as usize,
+            key_check: superblock.key_check,
+            cipher: None,
+        })
+    }
+
+    /// Settings for a new file encrypted with `cipher`.
+    pub(crate) fn with_cipher(cipher: Arc<dyn PageCipher>) -> Self {
+        Encryption {
+            overhead: cipher.overhead(),
+            key_check: cipher.key_check(),
+            cipher: Some(cipher),
+        }
+    }
+}
+
+impl DiskManager {
+    /// Create an encrypted DiskManager for a new database file.
+    ///
+    /// Like [`DiskManager::new`], but every page is encrypted with `cipher`. The key is recorded
+    /// in the superblock by [`DiskManager::sync_metadata`].
+    ///
+    /// # Errors
+    /// Will return [`std::io::Error`] if opening `filename` returns an error.
+    pub fn new_encrypted(
+        filename: &str,
+        cipher: Arc<dyn PageCipher>,
+    ) -> Result<Self, std::io::Error> {
+        let mut dm = DiskManager::new(filename)?;
+        dm.encryption = Some(Encryption::with_cipher(cipher));
+        Ok(dm)
+    }
+
+    /// Opens an existing encrypted database file.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::WrongKey`] if the file was encrypted with another key.
+    /// - Returns an [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`] if the file is
+    ///   not encrypted.
+    /// - See [`DiskManager::open`] for the other errors.
+    pub fn open_encrypted(
+        filename: &str,
+        cipher: Arc<dyn PageCipher>,
+    ) -> Result<Self, DiskManagerError> {
+        let mut dm = DiskManager::open_stored(filename)?;
+        let Some(encryption) = &mut dm.encryption else {
+            return Err(invalid_data("database is not encrypted"));
+        };
+        if encryption.overhead != cipher.overhead() || encryption.key_check != cipher.key_check() {
+            return Err(DiskManagerError::WrongKey);
+        }
+        encryption.cipher = Some(cipher);
+        Ok(dm)
+    }
+
+    /// Returns true if the pages of this database are encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Bytes stored per page, [`PAGE_SIZE`] plus the overhead of the cipher.
+    pub(crate) fn frame_size(&self) -> usize {
+        PAGE_SIZE + self.encryption.as_ref().map_or(0, |e| e.overhead)
+    }
+
+    /// The cipher of an encrypted database, `None` for plain databases.
+    ///
+    /// # Errors
+    /// Returns an [`std::io::Error`] of kind [`std::io::ErrorKind::PermissionDenied`] if the
+    /// database is encrypted but was opened without a key.
+    pub(crate) fn cipher(&self) -> Result<Option<Arc<dyn PageCipher>>, DiskManagerError> {
+        match &self.encryption {
+            None => Ok(None),
+            Some(Encryption {
+                cipher: Some(cipher),
+                ..
+            }) => Ok(Some(cipher.clone())),
+            Some(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "database is encrypted and was opened without a key",
+            )
+            .into()),
+        }
+    }
+}
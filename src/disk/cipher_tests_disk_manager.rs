@@ -0,0 +1,146 @@
+// Tests for encryption at rest
+
+#[cfg(test)]
+mod cipher {
+    use crate::disk::cipher::*;
+    use crate::disk::fsck;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::os::unix::fs::FileExt;
+    use std::sync::Arc;
+
+    fn cipher(key: u8) -> Arc<dyn PageCipher> {
+        Arc::new(XChaChaPageCipher::new(&[key; 32]))
+    }
+
+    /// Page filled with a recognizable pattern derived from `value`.
+    fn secret_page(value: u8) -> RawPage {
+        let mut page = [0u8; PAGE_SIZE];
+        for (i, chunk) in page.chunks_mut(16).enumerate() {
+            chunk.copy_from_slice(format!("secret {value:03} {i:05}").as_bytes());
+        }
+        page
+    }
+
+    /// Creates an encrypted file with pages 1..=4 written and page 5 allocated.
+    fn create(filename: &str) -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::new_encrypted(filename, cipher(1))?;
+        for i in 1..=4u8 {
+            let pid = dm.allocate()?;
+            dm.write(pid, &secret_page(i))?;
+        }
+        dm.allocate()?;
+        dm.sync_metadata()
+    }
+
+    #[test]
+    fn write_read_and_reopen() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_cipher_reopen.dmdb";
+        create(filename)?;
+
+        let mut dm = DiskManager::open_encrypted(filename, cipher(1))?;
+        assert!(dm.is_encrypted());
+        assert_eq!(dm.next_free(), PageID(6));
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in 1..=4u8 {
+            dm.read(PageID(i as usize), &mut buf)?;
+            assert_eq!(buf, secret_page(i), "PID {i} differs after reopen");
+        }
+        dm.read(PageID(5), &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+
+        dm.write(PageID(2), &secret_page(9))?;
+        dm.read(PageID(2), &mut buf)?;
+        assert_eq!(buf, secret_page(9));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_key_and_missing_key() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_cipher_wrong_key.dmdb";
+        create(filename)?;
+
+        assert!(matches!(
+            DiskManager::open_encrypted(filename, cipher(2)),
+            Err(DiskManagerError::WrongKey)
+        ));
+        assert!(matches!(
+            DiskManager::open(filename),
+            Err(DiskManagerError::IOError(err)) if err.kind() == io::ErrorKind::PermissionDenied
+        ));
+
+        let plain = "/tmp/database_cipher_plain.dmdb";
+        DiskManager::new(plain)?.sync_metadata()?;
+        assert!(matches!(
+            DiskManager::open_encrypted(plain, cipher(1)),
+            Err(DiskManagerError::IOError(err)) if err.kind() == io::ErrorKind::InvalidData
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_holds_no_plaintext() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_cipher_plaintext.dmdb";
+        create(filename)?;
+
+        let contents = std::fs::read(filename)?;
+        let needle = b"secret 001";
+        assert!(
+            !contents
+                .windows(needle.len())
+                .any(|window| window == needle)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn moved_frames_fail_authentication() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_cipher_moved.dmdb";
+        create(filename)?;
+        let frame_size = PAGE_SIZE + cipher(1).overhead();
+        {
+            let file = OpenOptions::new().read(true).write(true).open(filename)?;
+            let mut frame = vec![0u8; frame_size];
+            file.read_exact_at(&mut frame, frame_size as u64)?;
+            file.write_all_at(&frame, 2 * frame_size as u64)?;
+        }
+
+        let mut dm = DiskManager::open_encrypted(filename, cipher(1))?;
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(PageID(1), &mut buf)?;
+        assert!(matches!(
+            dm.read(PageID(2), &mut buf),
+            Err(DiskManagerError::Corrupted(PageID(2)))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn backup_and_fsck_without_key() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_cipher_backup.dmdb";
+        let backup = "/tmp/database_cipher_backup.dmdb.bak";
+        create(filename)?;
+
+        let mut dm = DiskManager::open_encrypted(filename, cipher(1))?;
+        let manifest = dm.backup_to(backup)?;
+        assert_eq!(manifest.verify(backup)?, vec![]);
+
+        let report = fsck::check(filename)?;
+        assert!(report.is_clean(), "{report}");
+        let report = fsck::check(backup)?;
+        assert!(report.is_clean(), "{report}");
+
+        let mut restored = DiskManager::open_encrypted(backup, cipher(1))?;
+        let mut buf = [0u8; PAGE_SIZE];
+        restored.read(PageID(3), &mut buf)?;
+        assert_eq!(buf, secret_page(3));
+
+        Ok(())
+    }
+}
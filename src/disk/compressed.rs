@@ -0,0 +1,460 @@
+//! Compressed storage mode
+//!
+//! A [`CompressedDiskManager`] compresses every page with LZ4 before writing it and stores the
+//! resulting variable-length blob in a run of [`SECTOR_SIZE`] byte sectors. A page map from
+//! [`PageID`] to sector run (the indirection map) locates the blobs. Pages that do not compress
+//! are stored as they are.
+//!
+//! A write never overwrites the blob of the previous contents. Sectors released by writes and
+//! frees are reused only after the next [`CompressedDiskManager::sync_metadata`], so a file that
+//! is reopened after a crash shows the state of the last sync.
+//!
+//! # File layout
+//!
+//! - Page 0 holds the [`Superblock`] with mode [`StorageMode::Compressed`]. `next_free` is the
+//!   logical `next_free`, `root` is the first page of the page map.
+//! - The page map starts at a page boundary. It holds its length in bytes (u64), a checksum of
+//!   the entries (u32), 4 bytes padding and one u64 entry per page: 0 for free pages,
+//!   [`UNWRITTEN`] for pages that were never written and `sector << 16 | length` for stored blobs.
+//! - All other sectors behind page 0 hold blobs or are free.
+//!
+//! The free sectors and the free list are not stored. [`CompressedDiskManager::open`] rebuilds
+//! them from the page map.
+
+use crate::disk::disk_manager::{invalid_data, unallocated};
+use crate::disk::superblock::{StorageMode, Superblock};
+use crate::disk::{DiskManager, DiskManagerError, RawPage};
+use crate::{PAGE_SIZE, PageID};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::OpenOptions;
+
+/// Allocation unit for compressed blobs in bytes.
+pub const SECTOR_SIZE: usize = 256;
+
+/// First sector behind page 0.
+const FIRST_SECTOR: u64 = (PAGE_SIZE / SECTOR_SIZE) as u64;
+
+/// Page map entry of a page that is allocated but was never written.
+const UNWRITTEN: u64 = u64::MAX;
+
+/// Bytes of the page map in front of the entries.
+const MAP_HEADER_SIZE: usize = 16;
+
+/// Location of a stored blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Free,
+    Unwritten,
+    Stored { sector: u64, len: usize },
+}
+
+impl Slot {
+    fn encode(self) -> u64 {
+        match self {
+            Slot::Free => 0,
+            Slot::Unwritten => UNWRITTEN,
+            Slot::Stored { sector, len } => sector << 16 | len as u64,
+        }
+    }
+
+    fn decode(word: u64) -> Self {
+        match word {
+            0 => Slot::Free,
+            UNWRITTEN => Slot::Unwritten,
+            word => Slot::Stored {
+                sector: word >> 16,
+                len: (word & 0xFFFF) as usize,
+            },
+        }
+    }
+}
+
+/// Number of sectors needed for `len` bytes.
+fn sectors(len: usize) -> u64 {
+    len.div_ceil(SECTOR_SIZE) as u64
+}
+
+/// Space usage of a [`CompressedDiskManager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionStatistics {
+    /// Number of pages with stored contents.
+    pub stored_pages: usize,
+    /// Size of the stored pages before compression.
+    pub logical_bytes: u64,
+    /// Size of the stored blobs.
+    pub compressed_bytes: u64,
+    /// Size of the sectors holding the stored blobs.
+    pub allocated_bytes: u64,
+    /// Size of the file.
+    pub file_bytes: u64,
+}
+
+impl CompressionStatistics {
+    /// Logical size divided by compressed size, `1.0` if no page is stored.
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            1.0
+        } else {
+            self.logical_bytes as f64 / self.compressed_bytes as f64
+        }
+    }
+}
+
+/// Free sector runs, keyed by their first sector.
+#[derive(Debug, Default)]
+struct FreeSectors {
+    runs: BTreeMap<u64, u64>,
+}
+
+impl FreeSectors {
+    /// Takes `count` sectors starting at a multiple of `align` from the first run that is large
+    /// enough.
+    fn take(&mut self, count: u64, align: u64) -> Option<u64> {
+        let (start, len, aligned) = self.runs.iter().find_map(|(&start, &len)| {
+            let aligned = start.div_ceil(align) * align;
+            (aligned + count <= start + len).then_some((start, len, aligned))
+        })?;
+        self.runs.remove(&start);
+        if aligned > start {
+            self.runs.insert(start, aligned - start);
+        }
+        if start + len > aligned + count {
+            self.runs
+                .insert(aligned + count, start + len - aligned - count);
+        }
+        Some(aligned)
+    }
+
+    /// Returns `count` sectors starting at `start`, merging adjacent runs.
+    fn give(&mut self, mut start: u64, mut count: u64) {
+        if let Some((&before, &len)) = self.runs.range(..start).next_back()
+            && before + len == start
+        {
+            self.runs.remove(&before);
+            start = before;
+            count += len;
+        }
+        if let Some(len) = self.runs.remove(&(start + count)) {
+            count += len;
+        }
+        self.runs.insert(start, count);
+    }
+}
+
+/// Stores pages compressed in variable-length blobs.
+#[derive(Debug)]
+pub struct CompressedDiskManager {
+    /// The database file. Its allocator is not used.
+    disk: DiskManager,
+    /// Location of every page, indexed by [`PageID`]. Its length is `next_free`.
+    slots: Vec<Slot>,
+    /// Logical page ids that can be reused by [`CompressedDiskManager::allocate`].
+    free_list: VecDeque<PageID>,
+    /// Sectors that can be reused.
+    free_sectors: FreeSectors,
+    /// Sector runs released since the last sync. Still referenced by the page map on disk.
+    released: Vec<(u64, u64)>,
+    /// First sector behind all used sectors.
+    end_sector: u64,
+    /// Sectors of the page map on disk, `None` before the first sync.
+    map: Option<(u64, u64)>,
+}
+
+impl CompressedDiskManager {
+    /// Creates an empty compressed database in `filename`.
+    ///
+    /// Empties the file if it already exists.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::IOError`] if creating or writing the file fails.
+    pub fn create(filename: &str) -> Result<Self, DiskManagerError> {
+        CompressedDiskManager::create_on(DiskManager::new(filename)?)
+    }
+
+    /// Creates an empty compressed database in the empty database `disk`, like
+    /// [`CompressedDiskManager::create`].
+    pub(crate) fn create_on(disk: DiskManager) -> Result<Self, DiskManagerError> {
+        let mut compressed = CompressedDiskManager {
+            disk,
+            slots: vec![Slot::Free],
+            free_list: VecDeque::new(),
+            free_sectors: FreeSectors::default(),
+            released: Vec::new(),
+            end_sector: FIRST_SECTOR,
+            map: None,
+        };
+        compressed.sync_metadata()?;
+        Ok(compressed)
+    }
+
+    /// Opens an existing compressed database in the state of its last sync.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::IOError`] if reading the file fails.
+    /// - Returns an [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`] if the file
+    ///   is not a compressed database or its page map is damaged.
+    pub fn open(filename: &str) -> Result<Self, DiskManagerError> {
+        let file = OpenOptions::new().read(true).write(true).open(filename)?;
+        let mut disk = DiskManager::from_parts(file, PageID(1), VecDeque::new());
+
+        let superblock = Superblock::load(&mut disk)?;
+        if superblock.mode != StorageMode::Compressed || superblock.next_free.0 == 0 {
+            return Err(invalid_data("not a compressed database"));
+        }
+        let map_start = disk.offset(superblock.root) / SECTOR_SIZE as u64;
+        let slots = Self::load_map(&mut disk, map_start, superblock.next_free)?;
+        let map = (map_start, sectors(MAP_HEADER_SIZE + slots.len() * 8));
+
+        let mut used: Vec<(u64, u64)> = slots
+            .iter()
+            .filter_map(|slot| match *slot {
+                Slot::Stored { sector, len } => Some((sector, sectors(len))),
+                _ => None,
+            })
+            .collect();
+        used.push(map);
+        used.sort_unstable();
+
+        let mut free_sectors = FreeSectors::default();
+        let mut end_sector = FIRST_SECTOR;
+        for (start, count) in used {
+            if start < end_sector {
+                return Err(invalid_data("overlapping blobs in compressed page map"));
+            }
+            if start > end_sector {
+                free_sectors.give(end_sector, start - end_sector);
+            }
+            end_sector = start + count;
+        }
+
+        Ok(CompressedDiskManager {
+            disk,
+            free_list: (1..slots.len())
+                .filter(|&i| slots[i] == Slot::Free)
+                .map(PageID)
+                .collect(),
+            slots,
+            free_sectors,
+            released: Vec::new(),
+            end_sector,
+            map: Some(map),
+        })
+    }
+
+    /// Highest allocated [`PageID`] + 1.
+    pub fn next_free(&self) -> PageID {
+        PageID(self.slots.len())
+    }
+
+    /// Pages that are not in use anymore, in the order they will be reused.
+    pub fn free_list(&self) -> &VecDeque<PageID> {
+        &self.free_list
+    }
+
+    /// Current space usage, including the compression ratio.
+    pub fn statistics(&self) -> CompressionStatistics {
+        let mut statistics = CompressionStatistics {
+            file_bytes: self.end_sector * SECTOR_SIZE as u64,
+            ..Default::default()
+        };
+        for slot in &self.slots {
+            if let Slot::Stored { len, .. } = *slot {
+                statistics.stored_pages += 1;
+                statistics.logical_bytes += PAGE_SIZE as u64;
+                statistics.compressed_bytes += len as u64;
+                statistics.allocated_bytes += sectors(len) * SECTOR_SIZE as u64;
+            }
+        }
+        statistics
+    }
+
+    /// Get a PageID for a new page, either from the free list or using `next_free`.
+    pub fn allocate(&mut self) -> PageID {
+        let page_id = match self.free_list.pop_front() {
+            Some(page_id) => page_id,
+            None => {
+                self.slots.push(Slot::Free);
+                PageID(self.slots.len() - 1)
+            }
+        };
+        self.slots[page_id.0] = Slot::Unwritten;
+        page_id
+    }
+
+    /// Mark the given page id as free.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::PageFreed`] or [`DiskManagerError::OutOfRange`] if `page_id` is
+    /// not allocated.
+    pub fn free(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        let slot = self.allocated_slot(page_id)?;
+        self.release(slot);
+        self.slots[page_id.0] = Slot::Free;
+        self.free_list.push_back(page_id);
+        Ok(())
+    }
+
+    /// Reads and decompresses `page_id`.
+    ///
+    /// Pages that were allocated but never written read as zeros.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::PageFreed`] or [`DiskManagerError::OutOfRange`] if `page_id` is
+    ///   not allocated.
+    /// - Returns an [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`] if the blob
+    ///   cannot be decompressed.
+    /// - Return [`DiskManagerError::IOError`] if file operations return an error.
+    pub fn read(&mut self, page_id: PageID, buf: &mut RawPage) -> Result<(), DiskManagerError> {
+        match self.allocated_slot(page_id)? {
+            Slot::Stored { sector, len } if len == PAGE_SIZE => {
+                self.disk.read_at(sector * SECTOR_SIZE as u64, buf)?;
+            }
+            Slot::Stored { sector, len } => {
+                let mut blob = vec![0u8; len];
+                self.disk.read_at(sector * SECTOR_SIZE as u64, &mut blob)?;
+                match lz4_flex::block::decompress_into(&blob, buf) {
+                    Ok(PAGE_SIZE) => {}
+                    _ => return Err(invalid_data("damaged compressed page")),
+                }
+            }
+            _ => buf.fill(0),
+        }
+        Ok(())
+    }
+
+    /// Compresses `buf` and writes it to fresh sectors.
+    ///
+    /// The sectors of the previous contents are reused after the next sync.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::PageFreed`] or [`DiskManagerError::OutOfRange`] if `page_id` is
+    ///   not allocated.
+    /// - Return [`DiskManagerError::IOError`] if file operations return an error.
+    pub fn write(&mut self, page_id: PageID, buf: &RawPage) -> Result<(), DiskManagerError> {
+        let previous = self.allocated_slot(page_id)?;
+
+        let compressed = lz4_flex::block::compress(buf);
+        let blob: &[u8] = if compressed.len() < PAGE_SIZE {
+            &compressed
+        } else {
+            buf
+        };
+        let count = sectors(blob.len());
+        let sector = self.allocate_sectors(count, 1);
+        if let Err(err) = self.write_sectors(sector, blob) {
+            self.free_sectors.give(sector, count);
+            return Err(err);
+        }
+
+        self.release(previous);
+        self.slots[page_id.0] = Slot::Stored {
+            sector,
+            len: blob.len(),
+        };
+        Ok(())
+    }
+
+    /// Persists the page map, so the file can be reopened with [`CompressedDiskManager::open`].
+    ///
+    /// Writes the map to fresh sectors behind all blobs and then points the superblock to it.
+    /// Afterwards, the sectors released since the last sync can be reused.
+    ///
+    /// # Errors
+    /// Return [`DiskManagerError::IOError`] if file operations return an error.
+    pub fn sync_metadata(&mut self) -> Result<(), DiskManagerError> {
+        let mut map = vec![0u8; MAP_HEADER_SIZE];
+        for slot in &self.slots {
+            map.extend(slot.encode().to_le_bytes());
+        }
+        let crc = crc32fast::hash(&map[MAP_HEADER_SIZE..]);
+        let len = map.len() as u64;
+        map[..8].copy_from_slice(&len.to_le_bytes());
+        map[8..12].copy_from_slice(&crc.to_le_bytes());
+
+        // The superblock addresses pages, so the map starts at a page boundary.
+        let per_page = FIRST_SECTOR;
+        let count = sectors(map.len());
+        let start = self.allocate_sectors(count, per_page);
+        let stored = self.write_sectors(start, &map).and_then(|()| {
+            let epoch = Superblock::load(&mut self.disk).map_or(0, |superblock| superblock.epoch);
+            let superblock = Superblock {
+                mode: StorageMode::Compressed,
+                epoch: epoch + 1,
+                next_free: self.next_free(),
+                root: PageID((start / per_page) as usize),
+                ..Default::default()
+            };
+            superblock.store(&mut self.disk)
+        });
+        if let Err(err) = stored {
+            // The superblock still points to the previous map, so the new one can be reused.
+            self.free_sectors.give(start, count);
+            return Err(err);
+        }
+
+        if let Some((start, count)) = self.map.replace((start, count)) {
+            self.released.push((start, count));
+        }
+        for (start, count) in std::mem::take(&mut self.released) {
+            self.free_sectors.give(start, count);
+        }
+        Ok(())
+    }
+
+    /// Reads the page map starting at sector `start`.
+    fn load_map(
+        disk: &mut DiskManager,
+        start: u64,
+        next_free: PageID,
+    ) -> Result<Vec<Slot>, DiskManagerError> {
+        let mut map = vec![0u8; MAP_HEADER_SIZE + next_free.0 * 8];
+        disk.read_at(start * SECTOR_SIZE as u64, &mut map)?;
+        let len = u64::from_le_bytes(map[..8].try_into().unwrap());
+        let crc = u32::from_le_bytes(map[8..12].try_into().unwrap());
+        if len != map.len() as u64 || crc32fast::hash(&map[MAP_HEADER_SIZE..]) != crc {
+            return Err(invalid_data("damaged compressed page map"));
+        }
+
+        Ok(map[MAP_HEADER_SIZE..]
+            .chunks_exact(8)
+            .map(|word| Slot::decode(u64::from_le_bytes(word.try_into().unwrap())))
+            .collect())
+    }
+
+    /// Slot of `page_id`, which must be allocated.
+    fn allocated_slot(&self, page_id: PageID) -> Result<Slot, DiskManagerError> {
+        match self.slots.get(page_id.0) {
+            Some(Slot::Free) | None => Err(unallocated(page_id, self.next_free())),
+            Some(&slot) => Ok(slot),
+        }
+    }
+
+    /// Takes `count` free sectors starting at a multiple of `align`, growing the file if no free
+    /// run is large enough.
+    fn allocate_sectors(&mut self, count: u64, align: u64) -> u64 {
+        if let Some(start) = self.free_sectors.take(count, align) {
+            return start;
+        }
+        let start = self.end_sector.div_ceil(align) * align;
+        if start > self.end_sector {
+            self.free_sectors
+                .give(self.end_sector, start - self.end_sector);
+        }
+        self.end_sector = start + count;
+        start
+    }
+
+    /// Writes `bytes` to the sectors starting at `start` and calls fsync.
+    fn write_sectors(&mut self, start: u64, bytes: &[u8]) -> Result<(), DiskManagerError> {
+        self.disk.write_at(start * SECTOR_SIZE as u64, bytes)?;
+        self.disk.sync()?;
+        Ok(())
+    }
+
+    /// Releases the sectors of `slot` after the next sync.
+    fn release(&mut self, slot: Slot) {
+        if let Slot::Stored { sector, len } = slot {
+            self.released.push((sector, sectors(len)));
+        }
+    }
+}
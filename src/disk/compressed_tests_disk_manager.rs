@@ -0,0 +1,213 @@
+// Tests for the compressed storage mode
+
+#[cfg(test)]
+mod compressed {
+    use crate::disk::compressed::*;
+    use crate::disk::storage::*;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use rand::Rng;
+    use std::io;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// In-memory storage whose writes fail while `failing` is set.
+    #[derive(Debug, Default)]
+    struct FailingWrites {
+        inner: MemoryBackend,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl StorageBackend for FailingWrites {
+        fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            self.inner.read_at(offset, buf)
+        }
+
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(io::Error::other("disk on fire"));
+            }
+            self.inner.write_at(offset, buf)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.inner.sync()
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            self.inner.len()
+        }
+
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.inner.set_len(len)
+        }
+    }
+
+    /// Mostly zero page with a short pattern derived from `value`.
+    fn sparse_page(value: u8) -> RawPage {
+        let mut page = [0u8; PAGE_SIZE];
+        for (i, byte) in page[..64].iter_mut().enumerate() {
+            *byte = value.wrapping_add(i as u8);
+        }
+        page
+    }
+
+    fn random_page() -> RawPage {
+        let mut page = [0u8; PAGE_SIZE];
+        rand::rng().fill(&mut page[..]);
+        page
+    }
+
+    #[test]
+    fn write_read_and_reopen() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_compressed_reopen.dmdb";
+        let random = random_page();
+        {
+            let mut dm = CompressedDiskManager::create(filename)?;
+            for i in 1..=20u8 {
+                let pid = dm.allocate();
+                dm.write(pid, &sparse_page(i))?;
+            }
+            dm.write(PageID(7), &random)?;
+            dm.free(PageID(3))?;
+            let unwritten = dm.allocate();
+            assert_eq!(unwritten, PageID(3));
+            dm.sync_metadata()?;
+        }
+
+        let mut dm = CompressedDiskManager::open(filename)?;
+        assert_eq!(dm.next_free(), PageID(21));
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in (1..=20u8).filter(|&i| i != 3 && i != 7) {
+            dm.read(PageID(i as usize), &mut buf)?;
+            assert_eq!(buf, sparse_page(i), "PID {i} differs after reopen");
+        }
+        dm.read(PageID(7), &mut buf)?;
+        assert_eq!(buf, random);
+        dm.read(PageID(3), &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn statistics_report_ratio() -> Result<(), DiskManagerError> {
+        let mut dm = CompressedDiskManager::create("/tmp/database_compressed_stats.dmdb")?;
+        assert_eq!(dm.statistics().ratio(), 1.0);
+        for i in 1..=10u8 {
+            let pid = dm.allocate();
+            dm.write(pid, &sparse_page(i))?;
+        }
+
+        let statistics = dm.statistics();
+        assert_eq!(statistics.stored_pages, 10);
+        assert_eq!(statistics.logical_bytes, 10 * PAGE_SIZE as u64);
+        assert!(statistics.compressed_bytes <= statistics.allocated_bytes);
+        assert!(statistics.ratio() > 10.0, "ratio {}", statistics.ratio());
+
+        // Incompressible pages are stored as they are.
+        let pid = dm.allocate();
+        dm.write(pid, &random_page())?;
+        assert_eq!(
+            dm.statistics().compressed_bytes,
+            statistics.compressed_bytes + PAGE_SIZE as u64
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unsynced_writes_keep_synced_state() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_compressed_crash.dmdb";
+        {
+            let mut dm = CompressedDiskManager::create(filename)?;
+            let pid = dm.allocate();
+            dm.write(pid, &sparse_page(1))?;
+            dm.sync_metadata()?;
+
+            // Overwrite with pages of different sizes, so the blobs move around.
+            for i in 2..=10u8 {
+                dm.write(pid, &sparse_page(i))?;
+                dm.write(pid, &random_page())?;
+            }
+            // dropped without sync, like a crash
+        }
+
+        let mut dm = CompressedDiskManager::open(filename)?;
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(PageID(1), &mut buf)?;
+        assert_eq!(buf, sparse_page(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sectors_are_reused_after_sync() -> Result<(), DiskManagerError> {
+        let mut dm = CompressedDiskManager::create("/tmp/database_compressed_reuse.dmdb")?;
+        let pid = dm.allocate();
+        dm.write(pid, &random_page())?;
+        dm.sync_metadata()?;
+
+        for _ in 0..20 {
+            dm.write(pid, &random_page())?;
+            dm.sync_metadata()?;
+        }
+        // The file holds at most the current and previous blob and page map.
+        assert!(
+            dm.statistics().file_bytes <= 6 * PAGE_SIZE as u64,
+            "file grew to {} bytes",
+            dm.statistics().file_bytes
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_page_ids() -> Result<(), DiskManagerError> {
+        let mut dm = CompressedDiskManager::create("/tmp/database_compressed_invalid.dmdb")?;
+        let mut buf = [0u8; PAGE_SIZE];
+        assert!(matches!(
+            dm.read(PageID(0), &mut buf),
+            Err(DiskManagerError::InvalidPageID(_))
+        ));
+        assert!(matches!(
+            dm.read(PageID(1), &mut buf),
+            Err(DiskManagerError::OutOfRange { .. })
+        ));
+        let pid = dm.allocate();
+        dm.free(pid)?;
+        assert!(matches!(
+            dm.write(pid, &buf),
+            Err(DiskManagerError::PageFreed(_))
+        ));
+        assert!(DiskManager::open("/tmp/database_compressed_invalid.dmdb").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_writes_release_their_sectors() -> Result<(), DiskManagerError> {
+        let grown = |fail: bool| -> Result<u64, DiskManagerError> {
+            let storage = FailingWrites::default();
+            let failing = storage.failing.clone();
+            let disk = DiskManager::with_backend(Box::new(storage))?;
+            let mut dm = CompressedDiskManager::create_on(disk)?;
+            let pid = dm.allocate();
+            dm.write(pid, &random_page())?;
+            dm.sync_metadata()?;
+
+            if fail {
+                failing.store(true, Ordering::Relaxed);
+                assert!(dm.write(pid, &random_page()).is_err());
+                assert!(dm.sync_metadata().is_err());
+                failing.store(false, Ordering::Relaxed);
+            }
+            dm.write(pid, &random_page())?;
+            dm.sync_metadata()?;
+            Ok(dm.statistics().file_bytes)
+        };
+
+        assert_eq!(grown(true)?, grown(false)?);
+        Ok(())
+    }
+}
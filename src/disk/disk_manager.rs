@@ -1,10 +1,20 @@
+use crate::disk::advise::SequentialDetector;
+use crate::disk::checksum::page_checksum;
+use crate::disk::cipher::Encryption;
+use crate::disk::fsck::Problem;
+use crate::disk::incremental::ChangeTracker;
+use crate::disk::quota::Quota;
+use crate::disk::secure_delete::SecureDelete;
+use crate::disk::storage::{FileBackend, MemoryBackend, StorageBackend};
+use crate::disk::superblock::{StorageMode, Superblock};
 use crate::disk::*;
 use crate::{PAGE_SIZE, PageID};
 use std::{
-    collections::VecDeque,
-    fs::OpenOptions,
-    io::{self, Read, Seek, Write},
+    collections::{HashSet, VecDeque},
+    fs::{File, OpenOptions},
+    io,
 };
+use uuid::Uuid;
 
 impl DiskManager {
     /// Create a DiskManager to manage a database file on disk
@@ -16,35 +26,352 @@ impl DiskManager {
     ///
     /// Will return [`io::Error`] if opening `filename` returns an error.
     pub fn new(filename: &str) -> Result<Self, io::Error> {
-        todo!()
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(filename)?;
+
+        Ok(DiskManager::from_parts(file, PageID(1), VecDeque::new()))
+    }
+
+    /// Create a DiskManager for a temporary database file.
+    ///
+    /// The file is created with a unique name in [`std::env::temp_dir`] and unlinked right away,
+    /// so it is removed when the DiskManager is dropped, even if the process crashes. Suited for
+    /// tests and spill files.
+    ///
+    /// # Errors
+    /// Will return [`io::Error`] if creating or unlinking the file fails.
+    pub fn temp() -> Result<Self, io::Error> {
+        let path = std::env::temp_dir().join(format!("sdms-{}.tmp", Uuid::new_v4()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        std::fs::remove_file(&path)?;
+
+        Ok(DiskManager::from_parts(file, PageID(1), VecDeque::new()))
+    }
+
+    /// Create a DiskManager that stores its pages in `storage`.
+    ///
+    /// Like [`DiskManager::new`], `storage` is emptied first.
+    ///
+    /// # Errors
+    /// Will return [`io::Error`] if emptying `storage` fails.
+    pub fn with_backend(mut storage: Box<dyn StorageBackend>) -> Result<Self, io::Error> {
+        storage.set_len(0)?;
+        Ok(DiskManager::from_backend(
+            storage,
+            PageID(1),
+            VecDeque::new(),
+        ))
+    }
+
+    /// Create a DiskManager that keeps its pages in RAM, see [`MemoryBackend`].
+    pub fn in_memory() -> Self {
+        DiskManager::from_backend(Box::new(MemoryBackend::new()), PageID(1), VecDeque::new())
+    }
+
+    /// Opens an existing database file written with [`DiskManager::sync_metadata`].
+    ///
+    /// Restores `next_free` and the free list from the superblock in page 0. Starts a new backup
+    /// epoch, as changes made before the file was closed are not known.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::IOError`] if opening or reading `filename` fails.
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::InvalidData`] if the file has no valid
+    ///   superblock or its free list or checksum table is damaged.
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::PermissionDenied`] if the file is
+    ///   encrypted, see [`DiskManager::open_encrypted`].
+    pub fn open(filename: &str) -> Result<Self, DiskManagerError> {
+        let dm = DiskManager::open_stored(filename)?;
+        dm.cipher()?;
+        Ok(dm)
+    }
+
+    /// Opens an existing database file like [`DiskManager::open`], but without write access.
+    ///
+    /// Every operation that writes to the file fails with [`DiskManagerError::IOError`].
+    ///
+    /// # Errors
+    /// See [`DiskManager::open`].
+    pub fn open_read_only(filename: &str) -> Result<Self, DiskManagerError> {
+        let file = OpenOptions::new().read(true).open(filename)?;
+        let dm = DiskManager::open_storage(Box::new(FileBackend::new(file)))?;
+        dm.cipher()?;
+        Ok(dm)
+    }
+
+    /// Opens an existing database stored in `storage`, like [`DiskManager::open`].
+    ///
+    /// # Errors
+    /// See [`DiskManager::open`].
+    pub fn open_backend(storage: Box<dyn StorageBackend>) -> Result<Self, DiskManagerError> {
+        let dm = DiskManager::open_storage(storage)?;
+        dm.cipher()?;
+        Ok(dm)
+    }
+
+    /// Opens an existing database file, encrypted or not, without a key.
+    ///
+    /// Pages of an encrypted file can only be accessed as stored, e.g. to copy them.
+    pub(crate) fn open_stored(filename: &str) -> Result<Self, DiskManagerError> {
+        let file = OpenOptions::new().read(true).write(true).open(filename)?;
+        DiskManager::open_storage(Box::new(FileBackend::new(file)))
+    }
+
+    /// Restores the allocator state of the database in `storage` from its superblock.
+    fn open_storage(storage: Box<dyn StorageBackend>) -> Result<Self, DiskManagerError> {
+        let mut dm = DiskManager::from_backend(storage, PageID(1), VecDeque::new());
+        dm.allocated_on_disk = dm.storage.len()?;
+
+        let superblock = Superblock::load(&mut dm)?;
+        if superblock.mode != StorageMode::Plain || superblock.next_free.0 == 0 {
+            return Err(invalid_data("not a plain database file"));
+        }
+        dm.next_free = superblock.next_free;
+        dm.encryption = Encryption::from_superblock(&superblock);
+        dm.secure_delete = superblock.secure_delete;
+        dm.free_list = dm.read_free_chain(superblock.root)?;
+        dm.checksums =
+            dm.read_checksum_table(superblock.checksum_pages, superblock.checksum_crc)?;
+        dm.checksum_table = dm.next_free.0..dm.next_free.0 + superblock.checksum_pages as usize;
+        dm.changes = ChangeTracker::starting_at(superblock.backup_epoch + 1);
+
+        Ok(dm)
+    }
+
+    /// Creates a DiskManager for an opened database `file` with the given allocator state.
+    pub(crate) fn from_parts(file: File, next_free: PageID, free_list: VecDeque<PageID>) -> Self {
+        DiskManager::from_backend(Box::new(FileBackend::new(file)), next_free, free_list)
+    }
+
+    /// Creates a DiskManager for a database in `storage` with the given allocator state.
+    pub(crate) fn from_backend(
+        storage: Box<dyn StorageBackend>,
+        next_free: PageID,
+        free_list: VecDeque<PageID>,
+    ) -> Self {
+        DiskManager {
+            storage,
+            next_free,
+            free_list,
+            backup: None,
+            changes: ChangeTracker::starting_at(0),
+            checksums: Vec::new(),
+            checksum_table: 0..0,
+            encryption: None,
+            secure_delete: SecureDelete::Off,
+            quota: Quota::Unlimited,
+            reserve_space: false,
+            growth_chunk: 0,
+            allocated_on_disk: 0,
+            access: SequentialDetector::default(),
+        }
+    }
+
+    /// Highest allocated [`PageID`] + 1.
+    pub fn next_free(&self) -> PageID {
+        self.next_free
+    }
+
+    /// Pages that are not in use anymore, in the order they will be reused.
+    pub fn free_list(&self) -> &VecDeque<PageID> {
+        &self.free_list
+    }
+
+    /// Persists `next_free`, the free list and the page checksums, so the file can be reopened with
+    /// [`DiskManager::open`].
+    ///
+    /// The free list is stored as a chain through the free pages themselves: the first 8 bytes of
+    /// every free page hold the id of the next one. The superblock points to the head of the chain.
+    /// The checksums are stored behind the last page, see [`crate::disk::checksum`].
+    ///
+    /// # Errors
+    /// Return [`DiskManagerError::IOError`] if file operations return an [`io::Error`].
+    pub fn sync_metadata(&mut self) -> Result<(), DiskManagerError> {
+        // Table pages skipped by `allocate` are free, the new table is written behind them.
+        let skipped = self.checksum_table.start
+            ..self
+                .checksum_table
+                .end
+                .min(self.next_free.0)
+                .max(self.checksum_table.start);
+        self.checksum_table.start = skipped.end;
+        self.free_list.extend(skipped.map(PageID));
+        let chain: Vec<(PageID, u64)> = self
+            .free_list
+            .iter()
+            .enumerate()
+            .map(|(i, &page_id)| {
+                let next = self.free_list.get(i + 1).map_or(0, |next| next.0 as u64);
+                (page_id, next)
+            })
+            .collect();
+        let mut link = [0u8; PAGE_SIZE];
+        for (page_id, next) in chain {
+            link[..8].copy_from_slice(&next.to_le_bytes());
+            self.write_at(self.offset(page_id), &link)?;
+        }
+        let (checksum_pages, checksum_crc) = self.write_checksum_table()?;
+        self.checksum_table = self.next_free.0..self.next_free.0 + checksum_pages as usize;
+        self.sync()?;
+
+        let epoch = Superblock::load(self).map_or(0, |superblock| superblock.epoch);
+        let mut superblock = Superblock {
+            mode: StorageMode::Plain,
+            epoch: epoch + 1,
+            next_free: self.next_free,
+            root: self.free_list.front().copied().unwrap_or_default(),
+            backup_epoch: self.changes.epoch(),
+            checksum_pages,
+            checksum_crc,
+            secure_delete: self.secure_delete,
+            ..Default::default()
+        };
+        if let Some(encryption) = &self.encryption {
+            superblock.cipher_overhead = encryption.overhead as u32;
+            superblock.key_check = encryption.key_check;
+        }
+        superblock.store(self)
+    }
+
+    /// Follows the free list chain written by [`DiskManager::sync_metadata`] starting at `head`.
+    fn read_free_chain(&mut self, head: PageID) -> Result<VecDeque<PageID>, DiskManagerError> {
+        match self.walk_free_chain(head) {
+            (free_list, None) => Ok(free_list),
+            (_, Some(_)) => Err(invalid_data("damaged free list chain")),
+        }
+    }
+
+    /// Follows the free list chain starting at `head` until it ends or breaks.
+    ///
+    /// Returns the pages visited before the chain broke and the reason it broke, if it did.
+    pub(crate) fn walk_free_chain(&mut self, head: PageID) -> (VecDeque<PageID>, Option<Problem>) {
+        let mut free_list = VecDeque::new();
+        let mut seen = HashSet::new();
+        let mut link = [0u8; 8];
+        let mut page_id = head;
+        while page_id.0 != 0 {
+            if page_id >= self.next_free {
+                return (free_list, Some(Problem::FreeListOutOfRange(page_id)));
+            }
+            if !seen.insert(page_id) {
+                return (free_list, Some(Problem::FreeListDuplicate(page_id)));
+            }
+            if self.read_at(self.offset(page_id), &mut link).is_err() {
+                return (free_list, Some(Problem::FreeListUnreadable(page_id)));
+            }
+            free_list.push_back(page_id);
+            page_id = PageID(u64::from_le_bytes(link) as usize);
+        }
+        (free_list, None)
     }
 
     /// Get a PageID for a new page, either from the `free_list` or using `next_free`.
-    pub fn allocate(&mut self) -> PageID {
-        todo!()
+    ///
+    /// The new page reads as zeros: pages from the free list are cleared, and the pages of the
+    /// checksum table of the last [`DiskManager::sync_metadata`] are skipped.
+    ///
+    /// Growing the database is subject to the quota and allocates disk space for the new page if
+    /// enabled, see [`crate::disk::quota`] and [`crate::disk::preallocate`]. With space
+    /// reservation, pages from the free list get their space reserved again, as it may have been
+    /// released since.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::QuotaExceeded`] if a new page would exceed the quota.
+    /// - Returns [`DiskManagerError::Io`] if reserving disk space fails, e.g. with an
+    ///   [`io::Error`] of kind [`io::ErrorKind::StorageFull`]. The page stays free then.
+    pub fn allocate(&mut self) -> Result<PageID, DiskManagerError> {
+        if let Some(&page_id) = self.free_list.front() {
+            self.reserve(page_id)?;
+            // Free pages keep their old contents and the link of the free list chain.
+            self.write_zeros_at(self.offset(page_id), self.frame_size() as u64)
+                .map_err(io_error(IoOp::Allocate, page_id))?;
+            self.free_list.pop_front();
+            return Ok(page_id);
+        }
+
+        let page_id = if self.checksum_table.contains(&self.next_free.0) {
+            PageID(self.checksum_table.end)
+        } else {
+            self.next_free
+        };
+        self.check_quota(page_id)?;
+        self.make_room(page_id)?;
+        self.next_free = PageID(page_id.0 + 1);
+        Ok(page_id)
     }
 
     /// Mark the given page id as free
     ///
     /// This allows reusing the page id for new pages.
+    /// The contents of the page are removed first if secure delete is enabled, see
+    /// [`DiskManager::set_secure_delete`]. A backup in progress receives them before, as
+    /// secure delete and [`DiskManager::sync_metadata`] overwrite free pages.
     ///
     /// # Errors
-    /// Returns [`DiskManagerError::InvalidPageID`] if `page_id` is not in the
-    /// interval of allocated pages or if the page is already on the free list.
+    /// - Returns [`DiskManagerError::InvalidPageID`] for page 0,
+    ///   [`DiskManagerError::OutOfRange`] if `page_id` was never allocated and
+    ///   [`DiskManagerError::PageFreed`] if the page is already on the free list.
+    /// - Returns [`DiskManagerError::Io`] if removing the contents fails.
     pub fn free(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
-        todo!()
+        self.check_page_id(page_id)?;
+        self.copy_before_write(page_id)?;
+        self.wipe(page_id)?;
+        self.free_list.push_back(page_id);
+        self.set_checksum(page_id, None);
+        Ok(())
     }
 
     /// Reads a page from the database file on disk
     ///
     /// PageID serves as an offset to the position of the page in the file.
+    /// Pages that were never written read as zeros, even if they lie beyond the end of the file.
+    /// Reads of consecutive pages trigger read-ahead, see [`crate::disk::advise`].
     ///
     /// # Errors
-    /// - Returns [`DiskManagerError::InvalidPageID`] if `page_id` is not in the
-    ///   interval of allocated pages or if the page is on the free list.
-    /// - Return [`DiskManagerError::IOError`] if file operations return an [`io::Error`].
+    /// - Returns [`DiskManagerError::InvalidPageID`], [`DiskManagerError::OutOfRange`] or
+    ///   [`DiskManagerError::PageFreed`] if `page_id` is not allocated, see
+    ///   [`DiskManager::free`].
+    /// - Returns [`DiskManagerError::Io`] if file operations return an [`io::Error`].
+    /// - Returns [`DiskManagerError::ShortRead`] if the page of an encrypted database was written
+    ///   but the file ends within it.
+    /// - Returns [`DiskManagerError::Corrupted`] if the page of an encrypted database fails
+    ///   authentication.
     pub fn read(&mut self, page_id: PageID, buf: &mut RawPage) -> Result<(), DiskManagerError> {
-        todo!()
+        self.check_page_id(page_id)?;
+        self.track_read(page_id);
+        let offset = self.offset(page_id);
+        let Some(cipher) = self.cipher()? else {
+            return self
+                .read_at_or_zeros(offset, buf)
+                .map_err(io_error(IoOp::Read, page_id));
+        };
+        let mut frame = vec![0u8; self.frame_size()];
+        if self.checksum(page_id).is_none() {
+            // Either never written or written after the last `sync_metadata`. Frames are never
+            // zero, so a page that holds zeros was never written.
+            self.read_at_or_zeros(offset, &mut frame)
+                .map_err(io_error(IoOp::Read, page_id))?;
+            if frame.iter().all(|&byte| byte == 0) {
+                buf.fill(0);
+                return Ok(());
+            }
+        } else {
+            self.read_at(offset, &mut frame).map_err(|err| {
+                if err.kind() == io::ErrorKind::UnexpectedEof {
+                    DiskManagerError::ShortRead(page_id)
+                } else {
+                    io_error(IoOp::Read, page_id)(err)
+                }
+            })?;
+        }
+        cipher.decrypt(page_id, &frame, buf)
     }
 
     /// Writes page to the database file on disk
@@ -52,11 +379,138 @@ impl DiskManager {
     /// PageID serves as an offset to the position of the page in the file.
     /// Ensure all data is written to disk by calling fsync.
     ///
+    /// A page of zeros is not written. Its space is released by punching a hole into the file
+    /// instead, so it reads as zeros without taking up disk space. Pages of encrypted databases
+    /// are always written, as their frames are never zero. With space reservation enabled, zeros
+    /// are written to keep the space reserved, see [`crate::disk::quota`].
+    ///
     /// # Errors
-    /// - Returns [`DiskManagerError::InvalidPageID`] if `page_id` is not in the
-    ///   interval of allocated pages or if the page is on the free list.
-    /// - Return [`DiskManagerError::IOError`] if file operations return an [`io::Error`].
+    /// - Returns [`DiskManagerError::InvalidPageID`], [`DiskManagerError::OutOfRange`] or
+    ///   [`DiskManagerError::PageFreed`] if `page_id` is not allocated, see
+    ///   [`DiskManager::free`].
+    /// - Returns [`DiskManagerError::Io`] if file operations return an [`io::Error`].
     pub fn write(&mut self, page_id: PageID, buf: &RawPage) -> Result<(), DiskManagerError> {
-        todo!()
+        self.check_page_id(page_id)?;
+        let encrypted = self.cipher()?.map(|cipher| {
+            let mut frame = vec![0u8; self.frame_size()];
+            cipher.encrypt(page_id, buf, &mut frame);
+            frame
+        });
+        let frame = encrypted.as_deref().unwrap_or(buf);
+
+        self.copy_before_write(page_id)?;
+        let offset = self.offset(page_id);
+        if frame.iter().all(|&byte| byte == 0) {
+            self.write_zeros_at(offset, frame.len() as u64)
+        } else {
+            self.write_at(offset, frame)
+        }
+        .and_then(|()| self.sync())
+        .map_err(io_error(IoOp::Write, page_id))?;
+        self.allocated_on_disk = self.allocated_on_disk.max(offset + frame.len() as u64);
+        self.changes.record(page_id);
+        self.set_checksum(page_id, Some(page_checksum(frame)));
+        Ok(())
+    }
+
+    /// Checks that `page_id` lies in `[1, next_free)` and is neither on the free list nor a page of
+    /// the checksum table skipped by [`DiskManager::allocate`].
+    fn check_page_id(&self, page_id: PageID) -> Result<(), DiskManagerError> {
+        if page_id.0 == 0
+            || page_id >= self.next_free
+            || self.free_list.contains(&page_id)
+            || self.checksum_table.contains(&page_id.0)
+        {
+            return Err(unallocated(page_id, self.next_free));
+        }
+        Ok(())
+    }
+
+    /// Byte offset of `page_id` in the database file.
+    pub(crate) fn offset(&self, page_id: PageID) -> u64 {
+        (page_id.0 * self.frame_size()) as u64
     }
+
+    /// Reads `buf.len()` bytes starting at byte `offset` of the database file.
+    ///
+    /// Does not check page ids. Used for file regions that are not pages handed out by
+    /// [`DiskManager::allocate`], e.g. the [`Superblock`](crate::disk::superblock::Superblock).
+    pub(crate) fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.storage.read_at(offset, buf)
+    }
+
+    /// Writes `buf` starting at byte `offset` of the database file.
+    ///
+    /// Does not check page ids and does not call fsync, see [`DiskManager::read_at`] and
+    /// [`DiskManager::sync`].
+    pub(crate) fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        self.storage.write_at(offset, buf)
+    }
+
+    /// Reads `buf.len()` bytes starting at byte `offset` like [`DiskManager::read_at`], but fills
+    /// the part of `buf` beyond the end of the file with zeros.
+    pub(crate) fn read_at_or_zeros(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let stored = self
+            .storage
+            .len()?
+            .saturating_sub(offset)
+            .min(buf.len() as u64) as usize;
+        if stored > 0 {
+            self.storage.read_at(offset, &mut buf[..stored])?;
+        }
+        buf[stored..].fill(0);
+        Ok(())
+    }
+
+    /// Makes `len` bytes starting at byte `offset` read as zeros without writing them.
+    ///
+    /// Punches a hole into the file, which releases the disk space of the range. Extends the file
+    /// if the range ends beyond it, so the range still belongs to the file. Falls back to writing
+    /// zeros if the storage does not support holes, and writes zeros with space reservation
+    /// enabled, so the reserved space is kept.
+    pub(crate) fn write_zeros_at(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        if self.reserve_space {
+            return self.write_at(offset, &vec![0u8; len as usize]);
+        }
+        if self.storage.len()? < offset + len {
+            self.storage.set_len(offset + len)?;
+        }
+        match self.storage.punch_hole(offset, len) {
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                self.write_at(offset, &vec![0u8; len as usize])
+            }
+            result => result,
+        }
+    }
+
+    /// Calls fsync on the database file.
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        self.storage.sync()
+    }
+}
+
+/// Error for accessing `page_id` of a database with the given `next_free` when the page is not
+/// allocated: page 0, never allocated or freed.
+pub(crate) fn unallocated(page_id: PageID, next_free: PageID) -> DiskManagerError {
+    if page_id.0 == 0 {
+        DiskManagerError::InvalidPageID(page_id)
+    } else if page_id >= next_free {
+        DiskManagerError::OutOfRange { page_id, next_free }
+    } else {
+        DiskManagerError::PageFreed(page_id)
+    }
+}
+
+/// Wraps an [`io::Error`] of operation `op` on `page_id` into [`DiskManagerError::Io`].
+pub(crate) fn io_error(op: IoOp, page_id: PageID) -> impl FnOnce(io::Error) -> DiskManagerError {
+    move |source| DiskManagerError::Io {
+        op,
+        page_id,
+        source,
+    }
+}
+
+/// Creates an [`io::Error`] of kind [`io::ErrorKind::InvalidData`].
+pub(crate) fn invalid_data(message: &str) -> DiskManagerError {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
 }
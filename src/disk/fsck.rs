@@ -0,0 +1,368 @@
+//! Consistency check of a database file
+//!
+//! [`check`] validates a plain database file written with [`DiskManager::sync_metadata`] without
+//! modifying it:
+//!
+//! - the superblock in page 0 is valid and describes a plain file,
+//! - the file consists of whole pages,
+//! - the free list chain stays in `[1, next_free)` and visits no page twice,
+//! - the checksum table is intact and every written page matches its checksum.
+//!
+//! Pages that were allocated but never written are listed in the report, they are not an error.
+//!
+//! [`repair`] rebuilds the allocator metadata of a damaged file. It never touches the contents of
+//! pages in use, so damaged pages are reported but not fixed.
+
+use crate::disk::checksum::page_checksum;
+use crate::disk::cipher::Encryption;
+use crate::disk::incremental::ChangeTracker;
+use crate::disk::superblock::{StorageMode, Superblock};
+use crate::disk::{DiskManager, DiskManagerError};
+use crate::{PAGE_SIZE, PageID};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+
+/// An inconsistency found by [`check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// Page 0 holds no valid superblock.
+    InvalidSuperblock,
+    /// The file is not a plain database file.
+    UnsupportedMode(StorageMode),
+    /// The file length of `length` bytes is not a multiple of the page size.
+    PartialPage { length: u64 },
+    /// The free list points to a page outside of `[1, next_free)`.
+    FreeListOutOfRange(PageID),
+    /// The free list visits a page twice.
+    FreeListDuplicate(PageID),
+    /// A page of the free list lies beyond the end of the file.
+    FreeListUnreadable(PageID),
+    /// The checksum table is missing or damaged, so no page can be verified.
+    ChecksumTableDamaged,
+    /// The contents of a page do not match its checksum.
+    ChecksumMismatch(PageID),
+    /// A written page lies beyond the end of the file.
+    MissingPage(PageID),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::InvalidSuperblock => write!(f, "no valid superblock in page 0"),
+            Problem::UnsupportedMode(mode) => write!(f, "unsupported storage mode {mode:?}"),
+            Problem::PartialPage { length } => {
+                write!(f, "file length {length} is not a multiple of {PAGE_SIZE}")
+            }
+            Problem::FreeListOutOfRange(page_id) => {
+                write!(f, "free list entry {page_id} is out of range")
+            }
+            Problem::FreeListDuplicate(page_id) => {
+                write!(f, "free list contains page {page_id} twice")
+            }
+            Problem::FreeListUnreadable(page_id) => {
+                write!(
+                    f,
+                    "free list entry {page_id} lies beyond the end of the file"
+                )
+            }
+            Problem::ChecksumTableDamaged => write!(f, "checksum table is missing or damaged"),
+            Problem::ChecksumMismatch(page_id) => {
+                write!(f, "page {page_id} does not match its checksum")
+            }
+            Problem::MissingPage(page_id) => {
+                write!(f, "page {page_id} lies beyond the end of the file")
+            }
+        }
+    }
+}
+
+/// Result of [`check`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FsckReport {
+    /// The current superblock, `None` if page 0 holds no valid superblock.
+    pub superblock: Option<Superblock>,
+    /// Number of whole pages in the file, including page 0 and the checksum table.
+    pub file_pages: u64,
+    /// Free list as far as it could be followed.
+    pub free_list: Vec<PageID>,
+    /// Number of pages whose checksum was verified.
+    pub checked_pages: usize,
+    /// Pages that were allocated but never written.
+    pub never_written: Vec<PageID>,
+    /// Inconsistencies found, in the order they were found.
+    pub problems: Vec<Problem>,
+}
+
+impl FsckReport {
+    /// Returns true if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for FsckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "file pages:    {}", self.file_pages)?;
+        if let Some(superblock) = &self.superblock {
+            writeln!(f, "epoch:         {}", superblock.epoch)?;
+            writeln!(f, "next_free:     {}", superblock.next_free)?;
+        }
+        writeln!(f, "free pages:    {}", self.free_list.len())?;
+        writeln!(f, "checked pages: {}", self.checked_pages)?;
+        writeln!(f, "never written: {}", self.never_written.len())?;
+        for problem in &self.problems {
+            writeln!(f, "problem: {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Change made by [`repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairAction {
+    /// Padded a trailing partial page with zeros.
+    PaddedPartialPage,
+    /// Wrote a new superblock. All pages below `next_free` are treated as in use.
+    RebuiltSuperblock { next_free: PageID },
+    /// Cut the free list where it broke. The `kept` pages before the break stay free, the pages
+    /// behind it are leaked.
+    TruncatedFreeList { kept: usize },
+    /// Recomputed the checksum table from the current page contents.
+    RebuiltChecksums,
+}
+
+impl fmt::Display for RepairAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepairAction::PaddedPartialPage => write!(f, "padded trailing partial page"),
+            RepairAction::RebuiltSuperblock { next_free } => {
+                write!(f, "rebuilt superblock with next_free {next_free}")
+            }
+            RepairAction::TruncatedFreeList { kept } => {
+                write!(f, "truncated free list to {kept} pages")
+            }
+            RepairAction::RebuiltChecksums => write!(f, "rebuilt checksum table"),
+        }
+    }
+}
+
+/// Result of [`repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Changes made, empty if the file was clean.
+    pub actions: Vec<RepairAction>,
+    /// Result of checking the file after the repair.
+    pub report: FsckReport,
+}
+
+/// Checks the database file at `path` without modifying it.
+///
+/// # Errors
+/// Returns [`DiskManagerError::IOError`] if the file cannot be opened or read. Inconsistencies
+/// are reported in the [`FsckReport`].
+pub fn check(path: &str) -> Result<FsckReport, DiskManagerError> {
+    let file = OpenOptions::new().read(true).open(path)?;
+    let mut dm = DiskManager::from_parts(file, PageID(1), VecDeque::new());
+    let mut report = FsckReport::default();
+
+    let length = dm.storage.len()?;
+    let superblock = load_superblock(&mut dm, &mut report)?;
+    dm.encryption = superblock.as_ref().and_then(Encryption::from_superblock);
+    let frame_size = dm.frame_size() as u64;
+    report.file_pages = length / frame_size;
+    // The checksum table behind the frames of an encrypted file is stored in plain pages, so
+    // its length is no multiple of the frame size.
+    if !dm.is_encrypted() && length % frame_size != 0 {
+        report.problems.insert(0, Problem::PartialPage { length });
+    }
+
+    let Some(superblock) = superblock else {
+        return Ok(report);
+    };
+    dm.next_free = superblock.next_free;
+
+    let (free_list, broken) = dm.walk_free_chain(superblock.root);
+    report.free_list = free_list.iter().copied().collect();
+    report.problems.extend(broken);
+
+    match dm.read_checksum_table(superblock.checksum_pages, superblock.checksum_crc) {
+        Ok(checksums) => dm.checksums = checksums,
+        Err(DiskManagerError::IOError(err)) if err.kind() == io::ErrorKind::InvalidData => {
+            report.problems.push(Problem::ChecksumTableDamaged);
+            return Ok(report);
+        }
+        Err(err) => return Err(err),
+    }
+
+    let free: HashSet<PageID> = free_list.into_iter().collect();
+    let mut frame = vec![0u8; dm.frame_size()];
+    for page_id in (1..dm.next_free.0).map(PageID) {
+        if free.contains(&page_id) {
+            continue;
+        }
+        let Some(expected) = dm.checksum(page_id) else {
+            report.never_written.push(page_id);
+            continue;
+        };
+        match dm.read_at(dm.offset(page_id), &mut frame) {
+            Ok(()) if page_checksum(&frame) == expected => {}
+            Ok(()) => report.problems.push(Problem::ChecksumMismatch(page_id)),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                report.problems.push(Problem::MissingPage(page_id));
+            }
+            Err(err) => return Err(err.into()),
+        }
+        report.checked_pages += 1;
+    }
+
+    Ok(report)
+}
+
+/// Reads the superblock of `dm`. Records a problem and returns `None` if it cannot be used.
+fn load_superblock(
+    dm: &mut DiskManager,
+    report: &mut FsckReport,
+) -> Result<Option<Superblock>, DiskManagerError> {
+    let superblock = match Superblock::load(dm) {
+        Ok(superblock) => superblock,
+        Err(DiskManagerError::IOError(err))
+            if matches!(
+                err.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ) =>
+        {
+            report.problems.push(Problem::InvalidSuperblock);
+            return Ok(None);
+        }
+        Err(err) => return Err(err),
+    };
+    report.superblock = Some(superblock.clone());
+
+    if superblock.mode != StorageMode::Plain {
+        report
+            .problems
+            .push(Problem::UnsupportedMode(superblock.mode));
+        return Ok(None);
+    }
+    if superblock.next_free.0 == 0 {
+        report.problems.push(Problem::InvalidSuperblock);
+        return Ok(None);
+    }
+    Ok(Some(superblock))
+}
+
+/// Checks the database file at `path` and rebuilds its allocator metadata if needed.
+///
+/// - A trailing partial page is padded with zeros.
+/// - Without a valid superblock, every page of the file is treated as in use and the free list
+///   starts empty.
+/// - A broken free list is cut where it broke.
+/// - A damaged checksum table is recomputed from the current page contents.
+///
+/// Checksum mismatches of pages in use are left alone. Run [`check`] on a copy first to see what
+/// would change.
+///
+/// # Errors
+/// - Returns an [`io::Error`] of kind [`io::ErrorKind::InvalidInput`] if the file is not a plain
+///   database file.
+/// - Returns [`DiskManagerError::IOError`] if file operations return an [`io::Error`].
+pub fn repair(path: &str) -> Result<RepairReport, DiskManagerError> {
+    let report = check(path)?;
+    let mut actions = Vec::new();
+    if report.is_clean() {
+        return Ok(RepairReport { actions, report });
+    }
+    if let Some(Problem::UnsupportedMode(mode)) = report
+        .problems
+        .iter()
+        .find(|problem| matches!(problem, Problem::UnsupportedMode(_)))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot repair files in storage mode {mode:?}"),
+        )
+        .into());
+    }
+
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut dm = DiskManager::from_parts(file, PageID(1), VecDeque::new());
+    let has = |wanted: fn(&Problem) -> bool| report.problems.iter().any(wanted);
+    if !has(|problem| *problem == Problem::InvalidSuperblock) {
+        dm.encryption = report
+            .superblock
+            .as_ref()
+            .and_then(Encryption::from_superblock);
+    }
+
+    if has(|problem| matches!(problem, Problem::PartialPage { .. })) {
+        dm.storage
+            .set_len((report.file_pages + 1) * PAGE_SIZE as u64)?;
+        actions.push(RepairAction::PaddedPartialPage);
+    }
+    let file_pages = dm.storage.len()? / dm.frame_size() as u64;
+
+    let rebuild_checksums = match &report.superblock {
+        Some(superblock) if !has(|problem| *problem == Problem::InvalidSuperblock) => {
+            dm.next_free = superblock.next_free;
+            dm.changes = ChangeTracker::starting_at(superblock.backup_epoch);
+            dm.free_list = report.free_list.iter().copied().collect();
+            if has(|problem| {
+                matches!(
+                    problem,
+                    Problem::FreeListOutOfRange(_)
+                        | Problem::FreeListDuplicate(_)
+                        | Problem::FreeListUnreadable(_)
+                )
+            }) {
+                actions.push(RepairAction::TruncatedFreeList {
+                    kept: dm.free_list.len(),
+                });
+            }
+            match dm.read_checksum_table(superblock.checksum_pages, superblock.checksum_crc) {
+                Ok(checksums) => {
+                    dm.checksums = checksums;
+                    false
+                }
+                Err(_) => true,
+            }
+        }
+        _ => {
+            dm.next_free = PageID(file_pages.max(1) as usize);
+            actions.push(RepairAction::RebuiltSuperblock {
+                next_free: dm.next_free,
+            });
+            true
+        }
+    };
+
+    if rebuild_checksums {
+        rebuild_checksum_table(&mut dm, file_pages)?;
+        actions.push(RepairAction::RebuiltChecksums);
+    }
+
+    dm.sync_metadata()?;
+    drop(dm);
+    Ok(RepairReport {
+        actions,
+        report: check(path)?,
+    })
+}
+
+/// Recomputes the checksums of all pages in use that lie within the first `file_pages` pages.
+///
+/// Pages beyond the end of the file are treated as never written.
+fn rebuild_checksum_table(dm: &mut DiskManager, file_pages: u64) -> Result<(), DiskManagerError> {
+    let free: HashSet<PageID> = dm.free_list.iter().copied().collect();
+    let in_file = (file_pages as usize).min(dm.next_free.0);
+    dm.checksums = vec![None; dm.next_free.0];
+    let mut frame = vec![0u8; dm.frame_size()];
+    for page_id in (1..in_file).map(PageID) {
+        if !free.contains(&page_id) {
+            dm.read_at(dm.offset(page_id), &mut frame)?;
+            dm.set_checksum(page_id, Some(page_checksum(&frame)));
+        }
+    }
+    Ok(())
+}
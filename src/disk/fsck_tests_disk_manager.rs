@@ -0,0 +1,162 @@
+// Tests for the consistency check and repair of database files
+
+#[cfg(test)]
+mod fsck {
+    use crate::disk::fsck::*;
+    use crate::disk::shadow::ShadowDiskManager;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::FileExt;
+
+    /// Creates a file with pages 1..=10 written, 3 and 6 freed and page 11 allocated but never
+    /// written.
+    fn create(filename: &str) -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::new(filename)?;
+        for i in 1..=10u8 {
+            let pid = dm.allocate()?;
+            dm.write(pid, &page(i))?;
+        }
+        dm.free(PageID(3))?;
+        dm.free(PageID(6))?;
+        dm.free_list.clear();
+        dm.allocate()?;
+        dm.free_list.extend([PageID(3), PageID(6)]);
+        dm.sync_metadata()
+    }
+
+    fn overwrite(filename: &str, offset: u64, bytes: &[u8]) -> Result<(), DiskManagerError> {
+        let file = OpenOptions::new().write(true).open(filename)?;
+        file.write_all_at(bytes, offset)?;
+        Ok(())
+    }
+
+    #[test]
+    fn clean_file() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_fsck_clean.dmdb";
+        create(filename)?;
+
+        let report = check(filename)?;
+        assert!(report.is_clean(), "{report}");
+        assert_eq!(report.free_list, vec![PageID(3), PageID(6)]);
+        assert_eq!(report.checked_pages, 8);
+        assert_eq!(report.never_written, vec![PageID(11)]);
+
+        let repaired = repair(filename)?;
+        assert!(repaired.actions.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn detects_damaged_page() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_fsck_damaged_page.dmdb";
+        create(filename)?;
+        overwrite(filename, (4 * PAGE_SIZE + 100) as u64, &[0xFF])?;
+
+        let report = check(filename)?;
+        assert_eq!(report.problems, vec![Problem::ChecksumMismatch(PageID(4))]);
+
+        // Page contents are never repaired.
+        let repaired = repair(filename)?;
+        assert!(repaired.actions.is_empty());
+        assert!(!repaired.report.is_clean());
+
+        Ok(())
+    }
+
+    #[test]
+    fn repairs_broken_free_list() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_fsck_free_list.dmdb";
+        create(filename)?;
+        overwrite(filename, (3 * PAGE_SIZE) as u64, &500u64.to_le_bytes())?;
+
+        let report = check(filename)?;
+        assert_eq!(
+            report.problems,
+            vec![Problem::FreeListOutOfRange(PageID(500))]
+        );
+        assert_eq!(report.free_list, vec![PageID(3)]);
+
+        let repaired = repair(filename)?;
+        assert_eq!(
+            repaired.actions,
+            vec![RepairAction::TruncatedFreeList { kept: 1 }]
+        );
+        assert!(repaired.report.is_clean(), "{}", repaired.report);
+
+        let dm = DiskManager::open(filename)?;
+        assert_eq!(dm.free_list(), &[PageID(3)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn detects_free_list_cycle() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_fsck_free_cycle.dmdb";
+        create(filename)?;
+        overwrite(filename, (6 * PAGE_SIZE) as u64, &3u64.to_le_bytes())?;
+
+        let report = check(filename)?;
+        assert_eq!(report.problems, vec![Problem::FreeListDuplicate(PageID(3))]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn repairs_lost_superblock() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_fsck_superblock.dmdb";
+        create(filename)?;
+        overwrite(filename, 0, &page(0))?;
+
+        let report = check(filename)?;
+        assert_eq!(report.problems, vec![Problem::InvalidSuperblock]);
+
+        let repaired = repair(filename)?;
+        assert!(matches!(
+            repaired.actions[..],
+            [
+                RepairAction::RebuiltSuperblock { .. },
+                RepairAction::RebuiltChecksums
+            ]
+        ));
+        assert!(repaired.report.is_clean(), "{}", repaired.report);
+
+        let mut dm = DiskManager::open(filename)?;
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(PageID(7), &mut buf)?;
+        assert_eq!(buf, page(7));
+
+        Ok(())
+    }
+
+    #[test]
+    fn repairs_damaged_checksum_table() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_fsck_checksums.dmdb";
+        create(filename)?;
+        overwrite(filename, (12 * PAGE_SIZE) as u64, &[0xAB; 16])?;
+
+        let report = check(filename)?;
+        assert_eq!(report.problems, vec![Problem::ChecksumTableDamaged]);
+        assert!(DiskManager::open(filename).is_err());
+
+        let repaired = repair(filename)?;
+        assert_eq!(repaired.actions, vec![RepairAction::RebuiltChecksums]);
+        assert!(repaired.report.is_clean(), "{}", repaired.report);
+        DiskManager::open(filename)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn shadow_files_are_not_repaired() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_fsck_shadow.dmdb";
+        ShadowDiskManager::create(filename)?;
+
+        let report = check(filename)?;
+        assert!(matches!(report.problems[..], [Problem::UnsupportedMode(_)]));
+        assert!(repair(filename).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+//! Incremental backups
+//!
+//! The [`DiskManager`] divides time into backup epochs. Every backup, full or incremental, closes
+//! the current epoch and starts a new one. [`DiskManager::write`] records the written page in a
+//! [`PageBitmap`] of the current epoch, so [`DiskManager::incremental_backup`] can copy exactly
+//! the pages changed since a previous backup.
+//!
+//! An incremental backup file starts with a header holding the epochs it covers and the allocator
+//! metadata, followed by one record per changed page:
+//!
+//! | field        | size        |
+//! |--------------|-------------|
+//! | `PageID`     | 8           |
+//! | checksum     | 4           |
+//! | page data    | frame size  |
+//!
+//! The page data is stored as in the database file: `PAGE_SIZE` bytes, or the encrypted frame of
+//! an encrypted database.
+//!
+//! [`restore`] applies a full backup followed by a chain of incremental backups.
+
+use crate::PageID;
+use crate::disk::backup::BackupManifest;
+use crate::disk::checksum::page_checksum;
+use crate::disk::disk_manager::invalid_data;
+use crate::disk::superblock::Superblock;
+use crate::disk::{DiskManager, DiskManagerError};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+/// Identifies an incremental backup file.
+pub const MAGIC: [u8; 8] = *b"SDMSINC\x01";
+
+/// Set of pages with one bit per [`PageID`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageBitmap {
+    words: Vec<u64>,
+}
+
+impl PageBitmap {
+    /// Adds `page_id` to the set.
+    pub fn insert(&mut self, page_id: PageID) {
+        let (word, bit) = (page_id.0 / 64, page_id.0 % 64);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << bit;
+    }
+
+    /// Returns true if `page_id` is in the set.
+    pub fn contains(&self, page_id: PageID) -> bool {
+        let (word, bit) = (page_id.0 / 64, page_id.0 % 64);
+        self.words.get(word).is_some_and(|w| w & (1 << bit) != 0)
+    }
+
+    /// Adds all pages of `other` to the set.
+    pub fn union_with(&mut self, other: &PageBitmap) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    /// Iterates over the pages in the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = PageID> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| PageID(i * 64 + bit))
+        })
+    }
+}
+
+/// Records the pages written in each backup epoch.
+#[derive(Debug)]
+pub(crate) struct ChangeTracker {
+    /// The current epoch.
+    epoch: u64,
+    /// First epoch whose changes were recorded completely.
+    first_epoch: u64,
+    /// Pages written in each epoch from `first_epoch` up to and including `epoch`.
+    changed: Vec<PageBitmap>,
+}
+
+impl ChangeTracker {
+    /// Starts recording at `epoch`.
+    pub(crate) fn starting_at(epoch: u64) -> Self {
+        ChangeTracker {
+            epoch,
+            first_epoch: epoch,
+            changed: vec![PageBitmap::default()],
+        }
+    }
+
+    /// The current epoch.
+    pub(crate) fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Records a write of `page_id` in the current epoch.
+    pub(crate) fn record(&mut self, page_id: PageID) {
+        if let Some(current) = self.changed.last_mut() {
+            current.insert(page_id);
+        }
+    }
+
+    /// Closes the current epoch and returns the new one.
+    pub(crate) fn close_epoch(&mut self) -> u64 {
+        self.epoch += 1;
+        self.changed.push(PageBitmap::default());
+        self.epoch
+    }
+
+    /// Pages written since the start of `epoch`. `None` if the changes of `epoch` were not
+    /// recorded completely or `epoch` lies in the future.
+    pub(crate) fn changed_since(&self, epoch: u64) -> Option<PageBitmap> {
+        if epoch < self.first_epoch || epoch > self.epoch {
+            return None;
+        }
+        let mut pages = PageBitmap::default();
+        for changed in &self.changed[(epoch - self.first_epoch) as usize..] {
+            pages.union_with(changed);
+        }
+        Some(pages)
+    }
+}
+
+/// Header of an incremental backup file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    /// Epoch of the backup the changes are relative to.
+    base_epoch: u64,
+    /// Epoch started by this backup.
+    epoch: u64,
+    next_free: PageID,
+    free_list: Vec<PageID>,
+    /// Number of page records following the header.
+    page_count: u64,
+}
+
+impl Header {
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        for word in [
+            self.base_epoch,
+            self.epoch,
+            self.next_free.0 as u64,
+            self.free_list.len() as u64,
+            self.page_count,
+        ] {
+            bytes.extend(word.to_le_bytes());
+        }
+        for page_id in &self.free_list {
+            bytes.extend((page_id.0 as u64).to_le_bytes());
+        }
+        bytes.extend(crc32fast::hash(&bytes).to_le_bytes());
+        out.write_all(&bytes)
+    }
+
+    fn read(input: &mut impl Read) -> Result<Self, DiskManagerError> {
+        let mut bytes = vec![0u8; MAGIC.len() + 5 * 8];
+        input.read_exact(&mut bytes)?;
+        if bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not an incremental backup"));
+        }
+        let word = |bytes: &[u8], i: usize| {
+            let at = MAGIC.len() + i * 8;
+            u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+        };
+        let free_count = word(&bytes, 3) as usize;
+        if free_count as u64 >= word(&bytes, 2) {
+            return Err(invalid_data("damaged incremental backup header"));
+        }
+
+        let fixed = bytes.len();
+        bytes.resize(fixed + free_count * 8, 0);
+        input.read_exact(&mut bytes[fixed..])?;
+        let mut checksum = [0u8; 4];
+        input.read_exact(&mut checksum)?;
+        if crc32fast::hash(&bytes) != u32::from_le_bytes(checksum) {
+            return Err(invalid_data("damaged incremental backup header"));
+        }
+
+        Ok(Header {
+            base_epoch: word(&bytes, 0),
+            epoch: word(&bytes, 1),
+            next_free: PageID(word(&bytes, 2) as usize),
+            free_list: (0..free_count)
+                .map(|i| PageID(word(&bytes, 5 + i) as usize))
+                .collect(),
+            page_count: word(&bytes, 4),
+        })
+    }
+}
+
+impl DiskManager {
+    /// The current backup epoch. Changes made now are part of the next incremental backup.
+    pub fn backup_epoch(&self) -> u64 {
+        self.changes.epoch()
+    }
+
+    /// Writes all pages changed since the backup that started `since_epoch` to `out`, together
+    /// with the current allocator metadata. Closes the current epoch.
+    ///
+    /// `since_epoch` is the [`BackupManifest::epoch`] of an earlier full or incremental backup.
+    ///
+    /// # Errors
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::InvalidInput`] if the changes since
+    ///   `since_epoch` are not known, e.g. because the file was reopened since.
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::ResourceBusy`] if a full backup is in
+    ///   progress.
+    /// - Return [`DiskManagerError::IOError`] if file operations return an [`io::Error`].
+    pub fn incremental_backup(
+        &mut self,
+        since_epoch: u64,
+        out: &str,
+    ) -> Result<BackupManifest, DiskManagerError> {
+        if self.backup.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                "a backup is already in progress",
+            )
+            .into());
+        }
+        let changed = self.changes.changed_since(since_epoch).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("changes since backup epoch {since_epoch} are not known"),
+            )
+        })?;
+
+        let free: HashSet<PageID> = self.free_list.iter().copied().collect();
+        let pages: Vec<PageID> = changed
+            .iter()
+            .filter(|page_id| *page_id < self.next_free && !free.contains(page_id))
+            .collect();
+        let mut header = Header {
+            base_epoch: since_epoch,
+            epoch: self.changes.epoch() + 1,
+            next_free: self.next_free,
+            free_list: self.free_list.iter().copied().collect(),
+            page_count: pages.len() as u64,
+        };
+
+        let file = File::create(out)?;
+        let mut writer = BufWriter::new(file);
+        header.write(&mut writer)?;
+        let mut checksums = vec![None; self.next_free.0];
+        let mut page = vec![0u8; self.frame_size()];
+        for &page_id in &pages {
+            self.read_at(self.offset(page_id), &mut page)?;
+            let checksum = page_checksum(&page);
+            writer.write_all(&(page_id.0 as u64).to_le_bytes())?;
+            writer.write_all(&checksum.to_le_bytes())?;
+            writer.write_all(&page)?;
+            checksums[page_id.0] = Some(checksum);
+        }
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+
+        header.epoch = self.changes.close_epoch();
+        Ok(BackupManifest {
+            epoch: header.epoch,
+            base_epoch: Some(since_epoch),
+            next_free: header.next_free,
+            free_list: header.free_list,
+            checksums,
+        })
+    }
+}
+
+/// Restores a database into `target` from a full backup and a chain of incremental backups.
+///
+/// Each incremental backup must be based on an epoch not later than the state restored so far
+/// and must start a later epoch. The restored file can be opened with [`DiskManager::open`], or
+/// [`DiskManager::open_encrypted`] if the backups are encrypted. The returned disk manager has no
+/// key, so pages of an encrypted database can only be accessed after reopening it.
+///
+/// # Errors
+/// - Returns an [`io::Error`] of kind [`io::ErrorKind::InvalidData`] if a backup is damaged or
+///   an incremental backup does not apply to the restored state.
+/// - Return [`DiskManagerError::IOError`] if file operations return an [`io::Error`].
+pub fn restore(
+    target: &str,
+    full_backup: &str,
+    incrementals: &[&str],
+) -> Result<DiskManager, DiskManagerError> {
+    fs::copy(full_backup, target)?;
+    let mut dm = DiskManager::open_stored(target)?;
+    let mut epoch = Superblock::load(&mut dm)?.backup_epoch;
+
+    let mut page = vec![0u8; dm.frame_size()];
+    for incremental in incrementals {
+        let mut reader = BufReader::new(File::open(incremental)?);
+        let header = Header::read(&mut reader)?;
+        if header.base_epoch > epoch || header.epoch <= epoch {
+            return Err(invalid_data(&format!(
+                "{incremental} covers epochs {} to {} and does not apply to epoch {epoch}",
+                header.base_epoch, header.epoch
+            )));
+        }
+
+        for _ in 0..header.page_count {
+            let page_id = read_record(&mut reader, &mut page)?;
+            if page_id.0 == 0 || page_id >= header.next_free {
+                return Err(invalid_data("page of incremental backup out of range"));
+            }
+            dm.write_at(dm.offset(page_id), &page)?;
+            dm.set_checksum(page_id, Some(page_checksum(&page)));
+        }
+        dm.next_free = header.next_free;
+        // Pages behind the full backup hold its checksum table no more.
+        dm.checksum_table = 0..0;
+        dm.free_list = header.free_list.into();
+        for i in 0..dm.free_list.len() {
+            dm.set_checksum(dm.free_list[i], None);
+        }
+        epoch = header.epoch;
+    }
+
+    dm.storage.set_len(dm.offset(dm.next_free))?;
+    dm.changes = ChangeTracker::starting_at(epoch);
+    dm.sync_metadata()?;
+    Ok(dm)
+}
+
+/// Reads one page record into `page` and checks its checksum.
+fn read_record(input: &mut impl Read, page: &mut [u8]) -> Result<PageID, DiskManagerError> {
+    let mut page_id = [0u8; 8];
+    let mut checksum = [0u8; 4];
+    input.read_exact(&mut page_id)?;
+    input.read_exact(&mut checksum)?;
+    input.read_exact(page)?;
+
+    let page_id = PageID(u64::from_le_bytes(page_id) as usize);
+    if page_checksum(page) != u32::from_le_bytes(checksum) {
+        return Err(invalid_data(&format!(
+            "checksum mismatch of page {page_id} in incremental backup"
+        )));
+    }
+    Ok(page_id)
+}
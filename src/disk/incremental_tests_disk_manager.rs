@@ -0,0 +1,175 @@
+// Tests for incremental backups and restore
+
+#[cfg(test)]
+mod incremental {
+    use crate::disk::incremental::*;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+
+    fn assert_same_pages(
+        expected: &mut DiskManager,
+        actual: &mut DiskManager,
+    ) -> Result<(), DiskManagerError> {
+        assert_eq!(actual.next_free(), expected.next_free());
+        assert_eq!(actual.free_list(), expected.free_list());
+        let mut expected_page = [0u8; PAGE_SIZE];
+        let mut actual_page = [0u8; PAGE_SIZE];
+        for i in 1..expected.next_free().0 {
+            let pid = PageID(i);
+            if expected.free_list().contains(&pid) {
+                continue;
+            }
+            expected.read(pid, &mut expected_page)?;
+            actual.read(pid, &mut actual_page)?;
+            assert_eq!(
+                actual_page, expected_page,
+                "PID {pid} differs after restore"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn page_bitmap() {
+        let mut bitmap = PageBitmap::default();
+        for i in [1, 63, 64, 200] {
+            bitmap.insert(PageID(i));
+        }
+        assert!(bitmap.contains(PageID(64)));
+        assert!(!bitmap.contains(PageID(65)));
+        assert!(!bitmap.contains(PageID(100_000)));
+
+        let mut other = PageBitmap::default();
+        other.insert(PageID(2));
+        other.insert(PageID(64));
+        bitmap.union_with(&other);
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            [1, 2, 63, 64, 200].map(PageID)
+        );
+    }
+
+    #[test]
+    fn incremental_contains_only_changed_pages() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::new("/tmp/database_incremental_changed.dmdb")?;
+        for i in 1..=20u8 {
+            let pid = dm.allocate()?;
+            dm.write(pid, &page(i))?;
+        }
+        let full = dm.backup_to("/tmp/database_incremental_changed.bak")?;
+        assert_eq!(dm.backup_epoch(), full.epoch);
+
+        dm.write(PageID(3), &page(103))?;
+        dm.write(PageID(7), &page(107))?;
+        dm.write(PageID(3), &page(203))?;
+        dm.free(PageID(7))?;
+        let incremental =
+            dm.incremental_backup(full.epoch, "/tmp/database_incremental_changed.inc")?;
+
+        assert_eq!(incremental.base_epoch, Some(full.epoch));
+        assert_eq!(incremental.epoch, full.epoch + 1);
+        assert_eq!(incremental.free_list, vec![PageID(7)]);
+        let included: Vec<usize> = (0..incremental.checksums.len())
+            .filter(|&i| incremental.checksums[i].is_some())
+            .collect();
+        assert_eq!(included, vec![3], "Freed pages must not be included");
+
+        Ok(())
+    }
+
+    #[test]
+    fn restore_full_and_incrementals() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::new("/tmp/database_incremental_restore.dmdb")?;
+        for i in 1..=30u8 {
+            let pid = dm.allocate()?;
+            dm.write(pid, &page(i))?;
+        }
+        dm.free(PageID(10))?;
+        let full = dm.backup_to("/tmp/database_incremental_restore.bak")?;
+
+        dm.write(PageID(1), &page(101))?;
+        let reused = dm.allocate()?;
+        dm.write(reused, &page(110))?;
+        dm.free(PageID(20))?;
+        let first = dm.incremental_backup(full.epoch, "/tmp/database_incremental_restore_1.inc")?;
+
+        for i in 31..=40u8 {
+            let pid = dm.allocate()?;
+            dm.write(pid, &page(i))?;
+        }
+        dm.write(PageID(2), &page(102))?;
+        let second =
+            dm.incremental_backup(first.epoch, "/tmp/database_incremental_restore_2.inc")?;
+
+        let mut restored = restore(
+            "/tmp/database_incremental_restored.dmdb",
+            "/tmp/database_incremental_restore.bak",
+            &[
+                "/tmp/database_incremental_restore_1.inc",
+                "/tmp/database_incremental_restore_2.inc",
+            ],
+        )?;
+        assert_same_pages(&mut dm, &mut restored)?;
+        drop(restored);
+
+        // The restored file can be reopened and continues after the last epoch.
+        let mut reopened = DiskManager::open("/tmp/database_incremental_restored.dmdb")?;
+        assert!(reopened.backup_epoch() > second.epoch);
+        assert_same_pages(&mut dm, &mut reopened)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn restore_rejects_broken_chain() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::new("/tmp/database_incremental_chain.dmdb")?;
+        let pid = dm.allocate()?;
+        dm.write(pid, &page(1))?;
+        let full = dm.backup_to("/tmp/database_incremental_chain.bak")?;
+
+        dm.write(pid, &page(2))?;
+        let first = dm.incremental_backup(full.epoch, "/tmp/database_incremental_chain_1.inc")?;
+        dm.write(pid, &page(3))?;
+        dm.incremental_backup(first.epoch, "/tmp/database_incremental_chain_2.inc")?;
+
+        // The second incremental backup misses the changes of the first one.
+        let result = restore(
+            "/tmp/database_incremental_chain_restored.dmdb",
+            "/tmp/database_incremental_chain.bak",
+            &["/tmp/database_incremental_chain_2.inc"],
+        );
+        assert!(matches!(
+            result,
+            Err(DiskManagerError::IOError(err)) if err.kind() == std::io::ErrorKind::InvalidData
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_epoch_is_rejected() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_incremental_unknown.dmdb";
+        let epoch = {
+            let mut dm = DiskManager::new(filename)?;
+            let pid = dm.allocate()?;
+            dm.write(pid, &page(1))?;
+            let full = dm.backup_to("/tmp/database_incremental_unknown.bak")?;
+            assert!(
+                dm.incremental_backup(full.epoch + 1, "/tmp/database_incremental_unknown.inc")
+                    .is_err()
+            );
+            dm.sync_metadata()?;
+            full.epoch
+        };
+
+        let mut dm = DiskManager::open(filename)?;
+        assert!(
+            dm.incremental_backup(epoch, "/tmp/database_incremental_unknown.inc")
+                .is_err()
+        );
+        let since_open = dm.backup_epoch();
+        dm.incremental_backup(since_open, "/tmp/database_incremental_unknown.inc")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Read-only inspection of database files
+//!
+//! An [`Inspector`] opens a database file without write access and gives access to the raw
+//! contents of every page, including page 0 and free pages, which [`DiskManager::read`] refuses
+//! to return. It is the library side of the `sdms-inspect` binary.
+
+use crate::buffer::DATA_SIZE;
+use crate::disk::checksum::page_checksum;
+use crate::disk::superblock::Superblock;
+use crate::disk::{DiskManager, DiskManagerError, RawPage};
+use crate::{PAGE_SIZE, PageID};
+use std::io;
+
+/// What a page of the file is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageState {
+    /// Page 0, holding the [`Superblock`].
+    Superblock,
+    /// Allocated and written.
+    Written,
+    /// Allocated but never written.
+    Unwritten,
+    /// On the free list.
+    Free,
+    /// At or beyond `next_free`, e.g. part of the checksum table.
+    Unallocated,
+}
+
+/// Header of a [`MaterializedPage`](crate::buffer::MaterializedPage) stored in a page.
+///
+/// A materialized page is stored as its [`PageID`] in the first `size_of::<PageID>()` bytes
+/// (little endian), followed by [`DATA_SIZE`] bytes of data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageHeader {
+    /// The page that was read.
+    pub page_id: PageID,
+    /// Use of the page according to the allocator metadata.
+    pub state: PageState,
+    /// [`PageID`] stored in the header. Differs from `page_id` if the page does not hold a
+    /// materialized page or was written to the wrong place.
+    pub stored_page_id: PageID,
+    /// Number of data bytes that are not zero.
+    pub used_bytes: usize,
+    /// Checksum recorded for the page, `None` if the page was not written.
+    pub checksum: Option<u32>,
+    /// Whether the page contents match `checksum`, `None` if there is no checksum.
+    pub checksum_matches: Option<bool>,
+}
+
+/// A run of bytes that differ between two pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    /// Offset of the first differing byte within the page.
+    pub offset: usize,
+    /// Number of consecutive differing bytes.
+    pub len: usize,
+}
+
+/// Decodes the [`PageID`] stored in the header of a materialized page.
+pub fn decode_header(page: &RawPage) -> PageID {
+    let header = &page[..PAGE_SIZE - DATA_SIZE];
+    PageID(u64::from_le_bytes(header.try_into().unwrap()) as usize)
+}
+
+/// Returns the runs of bytes that differ between `a` and `b`, in ascending order.
+pub fn diff_pages(a: &RawPage, b: &RawPage) -> Vec<ByteRange> {
+    let mut ranges: Vec<ByteRange> = Vec::new();
+    for offset in (0..PAGE_SIZE).filter(|&i| a[i] != b[i]) {
+        match ranges.last_mut() {
+            Some(range) if range.offset + range.len == offset => range.len += 1,
+            _ => ranges.push(ByteRange { offset, len: 1 }),
+        }
+    }
+    ranges
+}
+
+/// Read-only view of a database file.
+#[derive(Debug)]
+pub struct Inspector {
+    dm: DiskManager,
+    superblock: Superblock,
+    file_pages: u64,
+}
+
+impl Inspector {
+    /// Opens the database file at `path` without write access.
+    ///
+    /// # Errors
+    /// See [`DiskManager::open_read_only`].
+    pub fn open(path: &str) -> Result<Self, DiskManagerError> {
+        let mut dm = DiskManager::open_read_only(path)?;
+        let superblock = Superblock::load(&mut dm)?;
+        let file_pages = dm.storage.len()? / PAGE_SIZE as u64;
+        Ok(Inspector {
+            dm,
+            superblock,
+            file_pages,
+        })
+    }
+
+    /// The current superblock.
+    pub fn superblock(&self) -> &Superblock {
+        &self.superblock
+    }
+
+    /// Highest allocated [`PageID`] + 1.
+    pub fn next_free(&self) -> PageID {
+        self.dm.next_free()
+    }
+
+    /// Free list in the order the pages will be reused.
+    pub fn free_list(&self) -> Vec<PageID> {
+        self.dm.free_list().iter().copied().collect()
+    }
+
+    /// Number of whole pages in the file.
+    pub fn file_pages(&self) -> u64 {
+        self.file_pages
+    }
+
+    /// Use of `page_id` according to the allocator metadata.
+    pub fn state(&self, page_id: PageID) -> PageState {
+        if page_id.0 == 0 {
+            PageState::Superblock
+        } else if page_id >= self.dm.next_free() {
+            PageState::Unallocated
+        } else if self.dm.free_list().contains(&page_id) {
+            PageState::Free
+        } else if self.dm.checksum(page_id).is_some() {
+            PageState::Written
+        } else {
+            PageState::Unwritten
+        }
+    }
+
+    /// Reads the raw contents of `page_id`, whatever its state.
+    ///
+    /// Allocated pages beyond the end of the file are read as zeros.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::OutOfRange`] if `page_id` is neither allocated nor part of
+    ///   the file.
+    /// - Return [`DiskManagerError::IOError`] if file operations return an [`io::Error`].
+    pub fn page(&mut self, page_id: PageID) -> Result<RawPage, DiskManagerError> {
+        let mut page = [0u8; PAGE_SIZE];
+        match self.dm.read_at(self.dm.offset(page_id), &mut page) {
+            Ok(()) => Ok(page),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                if page_id < self.dm.next_free() {
+                    Ok([0u8; PAGE_SIZE])
+                } else {
+                    Err(DiskManagerError::OutOfRange {
+                        page_id,
+                        next_free: self.dm.next_free(),
+                    })
+                }
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Decodes the materialized page header of `page_id`.
+    ///
+    /// # Errors
+    /// See [`Inspector::page`].
+    pub fn header(&mut self, page_id: PageID) -> Result<PageHeader, DiskManagerError> {
+        let page = self.page(page_id)?;
+        let checksum = self.dm.checksum(page_id);
+        Ok(PageHeader {
+            page_id,
+            state: self.state(page_id),
+            stored_page_id: decode_header(&page),
+            used_bytes: page[PAGE_SIZE - DATA_SIZE..]
+                .iter()
+                .filter(|&&byte| byte != 0)
+                .count(),
+            checksum,
+            checksum_matches: checksum.map(|checksum| page_checksum(&page) == checksum),
+        })
+    }
+
+    /// Returns the runs of bytes that differ between pages `a` and `b`.
+    ///
+    /// # Errors
+    /// See [`Inspector::page`].
+    pub fn diff(&mut self, a: PageID, b: PageID) -> Result<Vec<ByteRange>, DiskManagerError> {
+        let a = self.page(a)?;
+        let b = self.page(b)?;
+        Ok(diff_pages(&a, &b))
+    }
+}
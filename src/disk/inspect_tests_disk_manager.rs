@@ -0,0 +1,129 @@
+// Tests for read-only page inspection
+
+#[cfg(test)]
+mod inspect {
+    use crate::disk::inspect::*;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+
+    /// Page holding a materialized page header for `page_id` followed by `value`s.
+    fn materialized(page_id: PageID, value: u8) -> RawPage {
+        let mut page = [value; PAGE_SIZE];
+        page[..8].copy_from_slice(&(page_id.0 as u64).to_le_bytes());
+        page
+    }
+
+    fn create(filename: &str) -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::new(filename)?;
+        for i in 1..=4 {
+            let pid = dm.allocate()?;
+            dm.write(pid, &materialized(pid, i as u8))?;
+        }
+        dm.free(PageID(2))?;
+        dm.allocate()?;
+        dm.allocate()?;
+        dm.sync_metadata()
+    }
+
+    #[test]
+    fn diff_finds_ranges() {
+        let a = [0u8; PAGE_SIZE];
+        let mut b = a;
+        b[3] = 1;
+        b[4] = 1;
+        b[100] = 7;
+        b[PAGE_SIZE - 1] = 9;
+        assert_eq!(
+            diff_pages(&a, &b),
+            vec![
+                ByteRange { offset: 3, len: 2 },
+                ByteRange {
+                    offset: 100,
+                    len: 1
+                },
+                ByteRange {
+                    offset: PAGE_SIZE - 1,
+                    len: 1
+                },
+            ]
+        );
+        assert!(diff_pages(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn decodes_headers_and_states() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_inspect_headers.dmdb";
+        create(filename)?;
+        let mut inspector = Inspector::open(filename)?;
+
+        assert_eq!(inspector.next_free(), PageID(6));
+        assert_eq!(inspector.free_list(), vec![]);
+        assert_eq!(inspector.state(PageID(0)), PageState::Superblock);
+        assert_eq!(inspector.state(PageID(2)), PageState::Unwritten);
+        assert_eq!(inspector.state(PageID(5)), PageState::Unwritten);
+        assert_eq!(inspector.state(PageID(6)), PageState::Unallocated);
+
+        let header = inspector.header(PageID(3))?;
+        assert_eq!(header.state, PageState::Written);
+        assert_eq!(header.stored_page_id, PageID(3));
+        assert_eq!(header.used_bytes, PAGE_SIZE - 8);
+        assert_eq!(header.checksum_matches, Some(true));
+
+        assert!(matches!(
+            inspector.page(PageID(1000)),
+            Err(DiskManagerError::OutOfRange {
+                page_id: PageID(1000),
+                next_free: PageID(6)
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn lists_free_pages_and_diffs() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_inspect_free.dmdb";
+        {
+            let mut dm = DiskManager::new(filename)?;
+            for i in 1..=4 {
+                let pid = dm.allocate()?;
+                dm.write(pid, &materialized(pid, i as u8))?;
+            }
+            dm.free(PageID(3))?;
+            dm.sync_metadata()?;
+        }
+        let mut inspector = Inspector::open(filename)?;
+        assert_eq!(inspector.free_list(), vec![PageID(3)]);
+        assert_eq!(inspector.state(PageID(3)), PageState::Free);
+
+        let ranges = inspector.diff(PageID(1), PageID(4))?;
+        assert_eq!(ranges[0], ByteRange { offset: 0, len: 1 });
+        assert_eq!(
+            ranges[1],
+            ByteRange {
+                offset: 8,
+                len: PAGE_SIZE - 8
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_only_rejects_writes() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_inspect_read_only.dmdb";
+        create(filename)?;
+
+        let mut dm = DiskManager::open_read_only(filename)?;
+        assert!(matches!(
+            dm.write(PageID(1), &[0u8; PAGE_SIZE]),
+            Err(DiskManagerError::Io {
+                op: IoOp::Write,
+                page_id: PageID(1),
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+}
@@ -8,25 +8,80 @@
 
 use crate::{PAGE_SIZE, PageID};
 use std::collections::VecDeque;
-use std::fs::File;
+use std::fmt;
 use std::io;
 use thiserror::Error;
 
 /// Type for byte arrays representing raw pages.
 pub type RawPage = [u8; PAGE_SIZE];
 
+/// Page filled with `value`, for tests.
+#[cfg(test)]
+fn page(value: u8) -> RawPage {
+    [value; PAGE_SIZE]
+}
+
 /// DiskManager-related Errors
 ///
 /// Errors that can happen during interaction with the disk.
 /// We use a specific error enum here to make disk-related error explicit.
 #[derive(Error, Debug)]
 pub enum DiskManagerError {
+    /// Page 0, which holds the superblock and is never handed out.
     #[error("invalid page ID: {0}!")]
     InvalidPageID(PageID),
+    /// The page is on the free list.
+    #[error("page {0} is freed!")]
+    PageFreed(PageID),
+    /// The page was never allocated.
+    #[error("page {page_id} is out of range, next free page is {next_free}!")]
+    OutOfRange { page_id: PageID, next_free: PageID },
+    /// The page was written, but the file ends before its last byte.
+    #[error("page {0} is cut off by the end of the file!")]
+    ShortRead(PageID),
+    /// The stored page of an encrypted database fails authentication.
+    #[error("page {0} is corrupted!")]
+    Corrupted(PageID),
+    /// Reading, writing or freeing a page failed.
+    #[error("{op} of page {page_id} failed!")]
+    Io {
+        op: IoOp,
+        page_id: PageID,
+        #[source]
+        source: io::Error,
+    },
+    /// The cipher given to [`DiskManager::open_encrypted`] does not match the one the file was
+    /// encrypted with: its overhead or key check value differs.
+    #[error("wrong encryption key!")]
+    WrongKey,
+    /// Allocating a new page would exceed the quota, see [`DiskManager::set_quota`].
+    #[error("quota of {0} exceeded!")]
+    QuotaExceeded(quota::Quota),
+    /// I/O errors that do not concern a single page, e.g. opening the file.
     #[error(transparent)]
     IOError(#[from] io::Error),
 }
 
+/// Page operation that hit an I/O error, see [`DiskManagerError::Io`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoOp {
+    Read,
+    Write,
+    Free,
+    Allocate,
+}
+
+impl fmt::Display for IoOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IoOp::Read => "read",
+            IoOp::Write => "write",
+            IoOp::Free => "free",
+            IoOp::Allocate => "allocation",
+        })
+    }
+}
+
 /// The DiskManager is used to store and retrieve persisted database data
 ///
 /// Data is stored in fix-sized blocks of bytes, called pages.
@@ -34,19 +89,70 @@ pub enum DiskManagerError {
 /// The DiskManager keeps track of used and unused pages.
 #[derive(Debug)]
 pub struct DiskManager {
-    /// Handle to a database file on disk
-    file: File,
+    /// Storage holding the database file, see [`storage`]
+    storage: Box<dyn storage::StorageBackend>,
     /// Highest allocated [`PageID`] + 1. Never decreases.
     next_free: PageID,
     /// Used to keep track of pages that are not in use anymore
     ///
     /// Add freed pages to `free_list`
     free_list: VecDeque<PageID>,
+    /// Backup in progress, see [`DiskManager::start_backup`].
+    backup: Option<Box<backup::BackupState>>,
+    /// Pages written per backup epoch, see [`DiskManager::incremental_backup`].
+    changes: incremental::ChangeTracker,
+    /// Checksum of every written page, see [`DiskManager::checksum`].
+    checksums: Vec<Option<u32>>,
+    /// Pages holding the checksum table of the last [`DiskManager::sync_metadata`], see
+    /// [`checksum`].
+    checksum_table: std::ops::Range<usize>,
+    /// Encryption settings, `None` for plain databases. See [`DiskManager::new_encrypted`].
+    encryption: Option<cipher::Encryption>,
+    /// What [`DiskManager::free`] does with freed pages, see [`DiskManager::set_secure_delete`].
+    secure_delete: secure_delete::SecureDelete,
+    /// Limit on growing the database, see [`DiskManager::set_quota`].
+    quota: quota::Quota,
+    /// Whether new pages get their disk space reserved, see [`DiskManager::set_reserve_space`].
+    reserve_space: bool,
+    /// Bytes to grow the file by at once, see [`DiskManager::set_growth_chunk`].
+    growth_chunk: u64,
+    /// End of the space allocated on disk, see [`DiskManager::allocated_on_disk`].
+    allocated_on_disk: u64,
+    /// Detects sequential reads to issue read-ahead, see [`advise`].
+    access: advise::SequentialDetector,
 }
 
 // The tests
 mod advanced_tests_disk_manager;
+mod advise_tests_disk_manager;
+mod backup_tests_disk_manager;
 mod basic_tests_disk_manager;
+mod cipher_tests_disk_manager;
+mod compressed_tests_disk_manager;
+mod fsck_tests_disk_manager;
+mod incremental_tests_disk_manager;
+mod inspect_tests_disk_manager;
+mod preallocate_tests_disk_manager;
+mod quota_tests_disk_manager;
+mod secure_delete_tests_disk_manager;
+mod shadow_tests_disk_manager;
+mod sparse_tests_disk_manager;
+mod storage_tests_disk_manager;
+mod temp_tests_disk_manager;
 
 // The implementations
+pub mod advise;
+pub mod backup;
+pub mod checksum;
+pub mod cipher;
+pub mod compressed;
 pub mod disk_manager;
+pub mod fsck;
+pub mod incremental;
+pub mod inspect;
+pub mod preallocate;
+pub mod quota;
+pub mod secure_delete;
+pub mod shadow;
+pub mod storage;
+pub mod superblock;
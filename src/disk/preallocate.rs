@@ -0,0 +1,60 @@
+//! Preallocation of file space
+//!
+//! Growing the file one page at a time fragments it and changes the file length, and with it the
+//! file system metadata, on every write to a new page. With a growth chunk set,
+//! [`DiskManager::allocate`] extends the file by a whole chunk with `fallocate` whenever a new
+//! page does not fit into the space allocated so far. The chunk is cut short at the quota.
+//!
+//! The space allocated on disk is tracked separately from `next_free`. Pages between
+//! `next_free` and the end of the allocated space read as zeros once they are allocated.
+
+use crate::PageID;
+use crate::disk::disk_manager::io_error;
+use crate::disk::{DiskManager, DiskManagerError, IoOp};
+
+impl DiskManager {
+    /// Sets the number of bytes to grow the file by when it runs out of allocated space, e.g.
+    /// 1 MiB. The chunk is rounded down to whole pages. 0, the default, grows the file page by
+    /// page.
+    pub fn set_growth_chunk(&mut self, bytes: u64) {
+        self.growth_chunk = bytes;
+    }
+
+    /// The growth chunk in bytes, see [`DiskManager::set_growth_chunk`].
+    pub fn growth_chunk(&self) -> u64 {
+        self.growth_chunk
+    }
+
+    /// Bytes of the file that are known to be allocated on disk, by preallocation, reservation or
+    /// writes. At least the file length at the time it was opened.
+    pub fn allocated_on_disk(&self) -> u64 {
+        self.allocated_on_disk
+    }
+
+    /// Makes sure disk space for `page_id` is allocated before it is handed out.
+    ///
+    /// Preallocates the next chunk if a growth chunk is set and the page lies beyond the
+    /// allocated space, or else reserves the page, see [`DiskManager::set_reserve_space`].
+    pub(crate) fn make_room(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        let frame_size = self.frame_size() as u64;
+        if self.offset(page_id) + frame_size <= self.allocated_on_disk {
+            return Ok(());
+        }
+        let chunk_pages = (self.growth_chunk / frame_size) as usize;
+        if chunk_pages == 0 {
+            return self.reserve(page_id);
+        }
+
+        let mut end = (page_id.0 / chunk_pages + 1) * chunk_pages;
+        if let Some(quota) = self.quota_pages() {
+            end = end.min(quota);
+        }
+        let start = self.allocated_on_disk.max(self.offset(page_id));
+        let end = self.offset(PageID(end));
+        self.storage
+            .preallocate(start, end - start)
+            .map_err(io_error(IoOp::Allocate, page_id))?;
+        self.allocated_on_disk = end;
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+// Tests for preallocation in growth chunks
+
+#[cfg(test)]
+mod preallocate {
+    use crate::disk::quota::*;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+
+    const CHUNK_PAGES: usize = 16;
+    const CHUNK: u64 = (CHUNK_PAGES * PAGE_SIZE) as u64;
+
+    #[test]
+    fn file_grows_in_chunks() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_preallocate_chunks.dmdb";
+        let mut dm = DiskManager::new(filename)?;
+        dm.set_growth_chunk(CHUNK);
+        assert_eq!(dm.growth_chunk(), CHUNK);
+
+        assert_eq!(dm.allocate()?, PageID(1));
+        assert_eq!(std::fs::metadata(filename)?.len(), CHUNK);
+        assert_eq!(dm.allocated_on_disk(), CHUNK);
+
+        for _ in 2..CHUNK_PAGES {
+            dm.allocate()?;
+        }
+        assert_eq!(dm.next_free(), PageID(CHUNK_PAGES));
+        assert_eq!(dm.allocated_on_disk(), CHUNK);
+
+        dm.allocate()?;
+        assert_eq!(std::fs::metadata(filename)?.len(), 2 * CHUNK);
+        assert_eq!(dm.allocated_on_disk(), 2 * CHUNK);
+        assert_eq!(dm.next_free(), PageID(CHUNK_PAGES + 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn preallocated_pages_read_as_zeros_after_reopen() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_preallocate_reopen.dmdb";
+        let mut dm = DiskManager::new(filename)?;
+        dm.set_growth_chunk(CHUNK);
+        let written = dm.allocate()?;
+        dm.write(written, &[7u8; PAGE_SIZE])?;
+        let empty = dm.allocate()?;
+        dm.sync_metadata()?;
+        drop(dm);
+
+        let mut dm = DiskManager::open(filename)?;
+        assert_eq!(dm.allocated_on_disk(), CHUNK);
+        let mut buf = [1u8; PAGE_SIZE];
+        dm.read(written, &mut buf)?;
+        assert_eq!(buf, [7u8; PAGE_SIZE]);
+        dm.read(empty, &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_stops_at_quota() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::in_memory();
+        dm.set_growth_chunk(CHUNK);
+        dm.set_quota(Quota::Pages(5));
+        dm.allocate()?;
+        assert_eq!(dm.allocated_on_disk(), (6 * PAGE_SIZE) as u64);
+
+        for _ in 2..=5 {
+            dm.allocate()?;
+        }
+        assert!(matches!(
+            dm.allocate(),
+            Err(DiskManagerError::QuotaExceeded(_))
+        ));
+        assert_eq!(dm.allocated_on_disk(), (6 * PAGE_SIZE) as u64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_smaller_than_page_grows_page_by_page() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::in_memory();
+        dm.set_growth_chunk(PAGE_SIZE as u64 / 2);
+        dm.set_reserve_space(true);
+        dm.allocate()?;
+        assert_eq!(dm.allocated_on_disk(), (2 * PAGE_SIZE) as u64);
+        dm.allocate()?;
+        assert_eq!(dm.allocated_on_disk(), (3 * PAGE_SIZE) as u64);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+//! Quotas and space reservation
+//!
+//! A [`Quota`] limits how far [`DiskManager::allocate`] may grow the database. Pages taken
+//! from the free list do not count against it, as they do not grow the file.
+//!
+//! With space reservation enabled, every page gets its disk space reserved with `fallocate` when
+//! it is allocated, including pages reused from the free list. A full disk is then reported by
+//! [`DiskManager::allocate`], not by a later [`DiskManager::write`], e.g. while the buffer
+//! manager writes back an evicted page. Pages of zeros are written instead of punching holes, so
+//! their space stays reserved. See [`crate::disk::preallocate`] for reserving space in larger
+//! chunks.
+
+use crate::PageID;
+use crate::disk::disk_manager::io_error;
+use crate::disk::{DiskManager, DiskManagerError, IoOp};
+use std::{fmt, io};
+
+/// Limit on the size of a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quota {
+    /// No limit.
+    #[default]
+    Unlimited,
+    /// At most this many pages besides page 0.
+    Pages(usize),
+    /// At most this many bytes of pages, including page 0. Metadata stored behind the pages,
+    /// e.g. the checksum table, does not count.
+    Bytes(u64),
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quota::Unlimited => write!(f, "unlimited"),
+            Quota::Pages(pages) => write!(f, "{pages} pages"),
+            Quota::Bytes(bytes) => write!(f, "{bytes} bytes"),
+        }
+    }
+}
+
+impl DiskManager {
+    /// Limits how far [`DiskManager::allocate`] may grow the database.
+    ///
+    /// Pages allocated before are kept, even if they exceed the new quota.
+    pub fn set_quota(&mut self, quota: Quota) {
+        self.quota = quota;
+    }
+
+    /// The current quota, see [`DiskManager::set_quota`].
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// Reserve disk space for new pages when they are allocated, see [`crate::disk::quota`].
+    pub fn set_reserve_space(&mut self, reserve: bool) {
+        self.reserve_space = reserve;
+    }
+
+    /// Number of pages including page 0 the quota allows, `None` if unlimited.
+    pub(crate) fn quota_pages(&self) -> Option<usize> {
+        match self.quota {
+            Quota::Unlimited => None,
+            Quota::Pages(pages) => Some(pages + 1),
+            Quota::Bytes(bytes) => Some((bytes / self.frame_size() as u64) as usize),
+        }
+    }
+
+    /// Checks that growing the database to hold `page_id` stays within the quota.
+    pub(crate) fn check_quota(&self, page_id: PageID) -> Result<(), DiskManagerError> {
+        match self.quota_pages() {
+            Some(pages) if page_id.0 >= pages => Err(DiskManagerError::QuotaExceeded(self.quota)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reserves the disk space of `page_id` if space reservation is enabled.
+    ///
+    /// Storage that cannot reserve space is used without reservation.
+    pub(crate) fn reserve(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        if !self.reserve_space {
+            return Ok(());
+        }
+        let (offset, len) = (self.offset(page_id), self.frame_size() as u64);
+        match self.storage.reserve(offset, len) {
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => Ok(()),
+            Err(err) => Err(io_error(IoOp::Allocate, page_id)(err)),
+            Ok(()) => {
+                self.allocated_on_disk = self.allocated_on_disk.max(offset + len);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// Tests for quotas and space reservation
+
+#[cfg(test)]
+mod quota {
+    use crate::disk::quota::*;
+    use crate::disk::secure_delete::SecureDelete;
+    use crate::disk::storage::*;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use std::io;
+    use std::os::unix::fs::MetadataExt;
+    use std::sync::{Arc, Mutex};
+
+    type Offsets = Arc<Mutex<Vec<u64>>>;
+
+    /// In-memory storage that has room for `capacity` bytes. Records the offsets of reserved
+    /// ranges and punched holes.
+    #[derive(Debug)]
+    struct SmallDisk {
+        inner: MemoryBackend,
+        capacity: u64,
+        reserved: Offsets,
+        punched: Offsets,
+    }
+
+    impl SmallDisk {
+        fn new(capacity: u64) -> Self {
+            SmallDisk {
+                inner: MemoryBackend::new(),
+                capacity,
+                reserved: Offsets::default(),
+                punched: Offsets::default(),
+            }
+        }
+    }
+
+    impl StorageBackend for SmallDisk {
+        fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            self.inner.read_at(offset, buf)
+        }
+
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+            self.inner.write_at(offset, buf)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.inner.sync()
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            self.inner.len()
+        }
+
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.inner.set_len(len)
+        }
+
+        fn reserve(&mut self, offset: u64, len: u64) -> io::Result<()> {
+            if offset + len > self.capacity {
+                return Err(io::ErrorKind::StorageFull.into());
+            }
+            self.reserved.lock().unwrap().push(offset);
+            Ok(())
+        }
+
+        fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+            self.punched.lock().unwrap().push(offset);
+            self.inner.write_at(offset, &vec![0u8; len as usize])
+        }
+    }
+
+    #[test]
+    fn page_quota() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::in_memory();
+        dm.set_quota(Quota::Pages(3));
+        for i in 1..=3 {
+            assert_eq!(dm.allocate()?, PageID(i));
+        }
+        assert!(matches!(
+            dm.allocate(),
+            Err(DiskManagerError::QuotaExceeded(Quota::Pages(3)))
+        ));
+        assert_eq!(dm.next_free(), PageID(4));
+
+        // Reusing freed pages does not grow the database.
+        dm.free(PageID(2))?;
+        assert_eq!(dm.allocate()?, PageID(2));
+
+        dm.set_quota(Quota::Unlimited);
+        assert_eq!(dm.allocate()?, PageID(4));
+        Ok(())
+    }
+
+    #[test]
+    fn byte_quota() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::in_memory();
+        dm.set_quota(Quota::Bytes(4 * PAGE_SIZE as u64 + 100));
+        for _ in 0..3 {
+            dm.allocate()?;
+        }
+        let err = dm.allocate().unwrap_err();
+        assert!(matches!(err, DiskManagerError::QuotaExceeded(_)));
+        assert_eq!(
+            err.to_string(),
+            format!("quota of {} bytes exceeded!", 4 * PAGE_SIZE + 100)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn full_disk_is_reported_at_allocation() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::with_backend(Box::new(SmallDisk::new(3 * PAGE_SIZE as u64)))?;
+        dm.set_reserve_space(true);
+        assert_eq!(dm.allocate()?, PageID(1));
+        assert_eq!(dm.allocate()?, PageID(2));
+        match dm.allocate() {
+            Err(DiskManagerError::Io {
+                op: IoOp::Allocate,
+                page_id: PageID(3),
+                source,
+            }) => assert_eq!(source.kind(), io::ErrorKind::StorageFull),
+            other => panic!("Expected a full disk, got {other:?}"),
+        }
+        assert_eq!(dm.next_free(), PageID(3));
+
+        // Without reservation, the allocation succeeds.
+        dm.set_reserve_space(false);
+        assert_eq!(dm.allocate()?, PageID(3));
+        Ok(())
+    }
+
+    #[test]
+    fn reservation_allocates_disk_space() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_quota_reserve.dmdb";
+        let mut dm = DiskManager::new(filename)?;
+        dm.set_reserve_space(true);
+        for _ in 0..16 {
+            dm.allocate()?;
+        }
+
+        let metadata = std::fs::metadata(filename)?;
+        assert_eq!(metadata.len(), 0, "reservation must not grow the file");
+        assert!(
+            metadata.blocks() * 512 >= 16 * PAGE_SIZE as u64,
+            "only {} bytes reserved",
+            metadata.blocks() * 512
+        );
+
+        dm.write(PageID(16), &[1u8; PAGE_SIZE])?;
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(PageID(3), &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+        Ok(())
+    }
+
+    #[test]
+    fn reused_pages_are_reserved_again() -> Result<(), DiskManagerError> {
+        let disk = SmallDisk::new(3 * PAGE_SIZE as u64);
+        let (reserved, punched) = (disk.reserved.clone(), disk.punched.clone());
+        let mut dm = DiskManager::with_backend(Box::new(disk))?;
+        dm.set_reserve_space(true);
+        dm.set_secure_delete(SecureDelete::Discard);
+        let page = dm.allocate()?;
+        dm.write(page, &[1u8; PAGE_SIZE])?;
+        dm.write(page, &[0u8; PAGE_SIZE])?;
+        dm.free(page)?;
+        // Holes would release the reserved space.
+        assert!(punched.lock().unwrap().is_empty());
+
+        reserved.lock().unwrap().clear();
+        assert_eq!(dm.allocate()?, page);
+        assert_eq!(*reserved.lock().unwrap(), vec![dm.offset(page)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+//! Secure deletion of freed pages
+//!
+//! By default [`DiskManager::free`] only puts a page on the free list, and its old contents stay
+//! in the file until the page is reused. With [`SecureDelete::Overwrite`] or
+//! [`SecureDelete::Discard`], `free` removes the contents before the page is put on the free list.
+//!
+//! The mode is stored in the superblock by [`DiskManager::sync_metadata`], so it stays in effect
+//! when the file is reopened. Copies the file system or the disk keep on their own, e.g. in a
+//! journal or in remapped sectors, are not affected.
+
+use crate::PageID;
+use crate::disk::disk_manager::io_error;
+use crate::disk::{DiskManager, DiskManagerError, IoOp};
+
+/// What [`DiskManager::free`] does with the contents of a freed page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SecureDelete {
+    /// Leave the contents in place until the page is reused.
+    #[default]
+    Off,
+    /// Overwrite the page with zeros.
+    Overwrite,
+    /// Deallocate the page with `fallocate`, so it reads as zeros. Falls back to overwriting if
+    /// the file system does not support holes or space reservation is enabled.
+    Discard,
+}
+
+impl SecureDelete {
+    pub(crate) fn to_u32(self) -> u32 {
+        match self {
+            SecureDelete::Off => 0,
+            SecureDelete::Overwrite => 1,
+            SecureDelete::Discard => 2,
+        }
+    }
+
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(SecureDelete::Off),
+            1 => Some(SecureDelete::Overwrite),
+            2 => Some(SecureDelete::Discard),
+            _ => None,
+        }
+    }
+}
+
+impl DiskManager {
+    /// Sets what [`DiskManager::free`] does with the contents of freed pages.
+    ///
+    /// Only affects pages freed from now on.
+    pub fn set_secure_delete(&mut self, mode: SecureDelete) {
+        self.secure_delete = mode;
+    }
+
+    /// What [`DiskManager::free`] does with the contents of freed pages.
+    pub fn secure_delete(&self) -> SecureDelete {
+        self.secure_delete
+    }
+
+    /// Removes the contents of `page_id` according to the secure delete mode and calls fsync.
+    pub(crate) fn wipe(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        let offset = self.offset(page_id);
+        let len = self.frame_size();
+        if self.secure_delete == SecureDelete::Off {
+            return Ok(());
+        }
+        match self.secure_delete {
+            SecureDelete::Overwrite => self.write_at(offset, &vec![0u8; len]),
+            _ => self.write_zeros_at(offset, len as u64),
+        }
+        .and_then(|()| self.sync())
+        .map_err(io_error(IoOp::Free, page_id))
+    }
+}
@@ -0,0 +1,92 @@
+// Tests for secure deletion of freed pages
+
+#[cfg(test)]
+mod secure_delete {
+    use crate::disk::secure_delete::*;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+
+    const SECRET: &[u8] = b"top secret record";
+
+    /// Page with `SECRET` repeated all over it.
+    fn secret_page() -> RawPage {
+        let mut page = [0u8; PAGE_SIZE];
+        for chunk in page.chunks_mut(SECRET.len()) {
+            chunk.copy_from_slice(&SECRET[..chunk.len()]);
+        }
+        page
+    }
+
+    fn file_contains_secret(filename: &str) -> bool {
+        std::fs::read(filename)
+            .unwrap()
+            .windows(SECRET.len())
+            .any(|window| window == SECRET)
+    }
+
+    /// Writes the secret to page 2 of a new file, frees it and returns the manager.
+    fn free_secret(filename: &str, mode: SecureDelete) -> Result<DiskManager, DiskManagerError> {
+        let mut dm = DiskManager::new(filename)?;
+        dm.set_secure_delete(mode);
+        for _ in 0..3 {
+            let pid = dm.allocate()?;
+            dm.write(pid, &[1u8; PAGE_SIZE])?;
+        }
+        dm.write(PageID(2), &secret_page())?;
+        assert!(file_contains_secret(filename));
+        dm.free(PageID(2))?;
+        Ok(dm)
+    }
+
+    #[test]
+    fn off_keeps_contents() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_secure_delete_off.dmdb";
+        free_secret(filename, SecureDelete::Off)?;
+        assert!(file_contains_secret(filename));
+        Ok(())
+    }
+
+    #[test]
+    fn overwrite_removes_contents() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_secure_delete_overwrite.dmdb";
+        free_secret(filename, SecureDelete::Overwrite)?;
+        assert!(!file_contains_secret(filename));
+        Ok(())
+    }
+
+    #[test]
+    fn discard_removes_contents() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_secure_delete_discard.dmdb";
+        let mut dm = free_secret(filename, SecureDelete::Discard)?;
+        assert!(!file_contains_secret(filename));
+        assert_eq!(
+            std::fs::metadata(filename)?.len(),
+            4 * PAGE_SIZE as u64,
+            "discarding must not shrink the file"
+        );
+
+        // Reused pages read as zeros until written.
+        let pid = dm.allocate()?;
+        assert_eq!(pid, PageID(2));
+        let mut buf = [1u8; PAGE_SIZE];
+        dm.read(pid, &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+        Ok(())
+    }
+
+    #[test]
+    fn mode_survives_reopen() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_secure_delete_reopen.dmdb";
+        {
+            let mut dm = free_secret(filename, SecureDelete::Overwrite)?;
+            dm.sync_metadata()?;
+        }
+
+        let mut dm = DiskManager::open(filename)?;
+        assert_eq!(dm.secure_delete(), SecureDelete::Overwrite);
+        dm.write(PageID(3), &secret_page())?;
+        dm.free(PageID(3))?;
+        assert!(!file_contains_secret(filename));
+        Ok(())
+    }
+}
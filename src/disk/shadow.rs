@@ -0,0 +1,562 @@
+//! Shadow paging (copy-on-write) storage mode
+//!
+//! A [`ShadowDiskManager`] never overwrites a page in place. Every write of a logical [`PageID`]
+//! goes to a freshly allocated physical page of the underlying [`DiskManager`], and a page table
+//! maps logical to physical pages. [`ShadowDiskManager::commit`] writes the changed parts of the
+//! page table to fresh pages as well and then swaps the root in the [`Superblock`]. Until that
+//! superblock write, the previously committed state is untouched on disk, so a crash in the middle
+//! of a transaction discards all of its writes.
+//!
+//! Physical pages replaced by a transaction go back to the free list of the underlying
+//! [`DiskManager`] only after the commit, and only once no [`Snapshot`] of an older state is alive.
+//!
+//! # File layout
+//!
+//! - Physical page 0 holds the [`Superblock`] with mode [`StorageMode::Shadow`]. `root` points to
+//!   the root page, `next_free` is the `next_free` of the physical pages.
+//! - The root page holds the logical `next_free`, the number of table pages and the physical page
+//!   ids of the table pages (u64 each).
+//! - Each table page holds [`ENTRIES_PER_PAGE`] entries of the page table (u64 each). Entry `i` of
+//!   table page `t` maps logical page `t * ENTRIES_PER_PAGE + i`.
+//!
+//! The physical free list is not stored. [`ShadowDiskManager::open`] rebuilds it from the pages
+//! that are not reachable from the root, which also reclaims pages written by a transaction that
+//! never committed.
+
+use crate::disk::disk_manager::{invalid_data, io_error, unallocated};
+use crate::disk::superblock::{StorageMode, Superblock};
+use crate::disk::{DiskManager, DiskManagerError, IoOp, RawPage};
+use crate::{PAGE_SIZE, PageID};
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fs::OpenOptions;
+use std::io;
+use std::rc::Rc;
+
+/// Number of page table entries stored in one table page.
+pub const ENTRIES_PER_PAGE: usize = PAGE_SIZE / size_of::<u64>();
+
+/// Maximum number of table pages the root page can reference.
+const MAX_TABLE_PAGES: usize = ENTRIES_PER_PAGE - 2;
+
+/// Maximum number of logical pages (including the unused `PageID(0)`).
+pub const MAX_PAGES: usize = MAX_TABLE_PAGES * ENTRIES_PER_PAGE;
+
+/// Page table entry of a logical page that is not allocated.
+const FREE: u64 = 0;
+
+/// Page table entry of a logical page that is allocated but was never written.
+const UNWRITTEN: u64 = u64::MAX;
+
+/// Maps logical pages to physical pages. Its length is the logical `next_free`.
+#[derive(Debug, Clone)]
+struct PageTable {
+    entries: Vec<u64>,
+}
+
+impl PageTable {
+    /// Entry of `page_id`, `FREE` if the page is out of range.
+    fn get(&self, page_id: PageID) -> u64 {
+        match page_id.0 {
+            0 => FREE,
+            i => self.entries.get(i).copied().unwrap_or(FREE),
+        }
+    }
+
+    /// Logical page ids that are not allocated, in ascending order.
+    fn free_pages(&self) -> VecDeque<PageID> {
+        (1..self.entries.len())
+            .filter(|&i| self.entries[i] == FREE)
+            .map(PageID)
+            .collect()
+    }
+}
+
+/// Read-only view of a committed state of a [`ShadowDiskManager`].
+///
+/// The physical pages of the state stay untouched for as long as the snapshot (or a clone of it)
+/// is alive, even after later commits.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    epoch: u64,
+    table: Rc<PageTable>,
+}
+
+impl Snapshot {
+    /// Epoch of the commit this snapshot was taken from.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+/// Stores pages copy-on-write and makes multi-page updates atomic.
+///
+/// Writes, allocations and frees form a transaction that becomes durable with
+/// [`ShadowDiskManager::commit`] or is discarded with [`ShadowDiskManager::rollback`].
+#[derive(Debug)]
+pub struct ShadowDiskManager {
+    /// Storage for physical pages.
+    disk: DiskManager,
+    /// Epoch of the last commit.
+    epoch: u64,
+    /// Page table of the last commit. Shared with [`Snapshot`]s.
+    committed: Rc<PageTable>,
+    /// Physical page of the committed root, `PageID(0)` before the first commit.
+    root: PageID,
+    /// Physical pages of the committed table pages.
+    table_pages: Vec<PageID>,
+    /// Page table including the changes of the current transaction.
+    working: PageTable,
+    /// Logical page ids that can be reused by [`ShadowDiskManager::allocate`].
+    free_list: VecDeque<PageID>,
+    /// Indices of table pages changed in the current transaction.
+    dirty_tables: BTreeSet<usize>,
+    /// Physical pages written in the current transaction.
+    fresh: HashSet<PageID>,
+    /// Committed physical pages replaced in the current transaction.
+    superseded: Vec<PageID>,
+    /// Physical pages replaced by earlier commits, together with the state they belonged to.
+    /// Released in order once no snapshot of the state is alive anymore.
+    retired: VecDeque<(Rc<PageTable>, Vec<PageID>)>,
+}
+
+impl ShadowDiskManager {
+    /// Creates an empty shadow paging database in `filename`.
+    ///
+    /// Empties the file if it already exists and commits an empty page table.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::IOError`] if creating or writing the file fails.
+    pub fn create(filename: &str) -> Result<Self, DiskManagerError> {
+        ShadowDiskManager::create_on(DiskManager::new(filename)?)
+    }
+
+    /// Creates an empty shadow paging database in the empty database `disk`, like
+    /// [`ShadowDiskManager::create`].
+    pub(crate) fn create_on(disk: DiskManager) -> Result<Self, DiskManagerError> {
+        let table = PageTable {
+            entries: vec![FREE],
+        };
+        let mut shadow = ShadowDiskManager {
+            disk,
+            epoch: 0,
+            committed: Rc::new(table.clone()),
+            root: PageID(0),
+            table_pages: Vec::new(),
+            working: table,
+            free_list: VecDeque::new(),
+            dirty_tables: BTreeSet::new(),
+            fresh: HashSet::new(),
+            superseded: Vec::new(),
+            retired: VecDeque::new(),
+        };
+        shadow.commit()?;
+        Ok(shadow)
+    }
+
+    /// Opens an existing shadow paging database in the state of its last commit.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::IOError`] if reading the file fails.
+    /// - Returns an [`io::Error`] of kind [`io::ErrorKind::InvalidData`] if the file is not a
+    ///   shadow paging database or its page table is damaged.
+    pub fn open(filename: &str) -> Result<Self, DiskManagerError> {
+        let file = OpenOptions::new().read(true).write(true).open(filename)?;
+        let mut disk = DiskManager::from_parts(file, PageID(1), VecDeque::new());
+
+        let superblock = Superblock::load(&mut disk)?;
+        if superblock.mode != StorageMode::Shadow {
+            return Err(invalid_data("not a shadow paging database"));
+        }
+        disk.next_free = superblock.next_free;
+
+        let (table, table_pages) = Self::load_table(&mut disk, superblock.root)?;
+
+        let mut reachable: HashSet<PageID> = table_pages.iter().copied().collect();
+        reachable.insert(superblock.root);
+        for &entry in &table.entries {
+            if entry != FREE && entry != UNWRITTEN {
+                if entry as usize >= disk.next_free.0 {
+                    return Err(invalid_data("page table entry beyond physical next_free"));
+                }
+                reachable.insert(PageID(entry as usize));
+            }
+        }
+        disk.free_list = (1..disk.next_free.0)
+            .map(PageID)
+            .filter(|page| !reachable.contains(page))
+            .collect();
+
+        Ok(ShadowDiskManager {
+            disk,
+            epoch: superblock.epoch,
+            committed: Rc::new(table.clone()),
+            root: superblock.root,
+            table_pages,
+            free_list: table.free_pages(),
+            working: table,
+            dirty_tables: BTreeSet::new(),
+            fresh: HashSet::new(),
+            superseded: Vec::new(),
+            retired: VecDeque::new(),
+        })
+    }
+
+    /// Epoch of the last commit.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Highest allocated logical [`PageID`] + 1, including the current transaction.
+    pub fn next_free(&self) -> PageID {
+        PageID(self.working.entries.len())
+    }
+
+    /// Physical page currently holding `page_id`, `None` if the page is not allocated or was
+    /// never written.
+    pub fn physical_page(&self, page_id: PageID) -> Option<PageID> {
+        match self.working.get(page_id) {
+            FREE | UNWRITTEN => None,
+            entry => Some(PageID(entry as usize)),
+        }
+    }
+
+    /// Returns a read-only view of the last committed state.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            epoch: self.epoch,
+            table: self.committed.clone(),
+        }
+    }
+
+    /// Get a logical PageID for a new page, either from the free list or using `next_free`.
+    ///
+    /// # Errors
+    /// Returns an [`io::Error`] of kind [`io::ErrorKind::StorageFull`] if the page table already
+    /// holds [`MAX_PAGES`] pages.
+    pub fn allocate(&mut self) -> Result<PageID, DiskManagerError> {
+        let page_id = match self.free_list.pop_front() {
+            Some(page_id) => page_id,
+            None if self.working.entries.len() < MAX_PAGES => {
+                self.working.entries.push(FREE);
+                PageID(self.working.entries.len() - 1)
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::StorageFull,
+                    "shadow page table is full",
+                )
+                .into());
+            }
+        };
+        self.set_entry(page_id, UNWRITTEN);
+        Ok(page_id)
+    }
+
+    /// Marks the logical page `page_id` as free.
+    ///
+    /// Its physical page is released after the next commit.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::PageFreed`] or [`DiskManagerError::OutOfRange`] if `page_id` is
+    /// not allocated.
+    pub fn free(&mut self, page_id: PageID) -> Result<(), DiskManagerError> {
+        let entry = self.allocated_entry(page_id)?;
+        self.release(entry)?;
+        self.set_entry(page_id, FREE);
+        self.free_list.push_back(page_id);
+        Ok(())
+    }
+
+    /// Reads the logical page `page_id` as seen by the current transaction.
+    ///
+    /// Pages that were allocated but never written read as zeros.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::PageFreed`] or [`DiskManagerError::OutOfRange`] if `page_id` is
+    ///   not allocated.
+    /// - Returns [`DiskManagerError::Io`] if reading the physical page fails.
+    pub fn read(&mut self, page_id: PageID, buf: &mut RawPage) -> Result<(), DiskManagerError> {
+        let entry = self.working.get(page_id);
+        let next_free = self.next_free();
+        Self::read_entry(&mut self.disk, page_id, entry, next_free, buf)
+    }
+
+    /// Reads the logical page `page_id` as seen by `snapshot`.
+    ///
+    /// # Errors
+    /// See [`ShadowDiskManager::read`].
+    pub fn read_snapshot(
+        &mut self,
+        snapshot: &Snapshot,
+        page_id: PageID,
+        buf: &mut RawPage,
+    ) -> Result<(), DiskManagerError> {
+        let entry = snapshot.table.get(page_id);
+        let next_free = PageID(snapshot.table.entries.len());
+        Self::read_entry(&mut self.disk, page_id, entry, next_free, buf)
+    }
+
+    /// Writes the logical page `page_id` to a fresh physical page.
+    ///
+    /// The write becomes visible to [`ShadowDiskManager::open`] and new snapshots with the next
+    /// commit.
+    ///
+    /// # Errors
+    /// - Returns [`DiskManagerError::PageFreed`] or [`DiskManagerError::OutOfRange`] if `page_id` is
+    ///   not allocated.
+    /// - Returns [`DiskManagerError::Io`] if writing the physical page fails.
+    pub fn write(&mut self, page_id: PageID, buf: &RawPage) -> Result<(), DiskManagerError> {
+        let entry = self.allocated_entry(page_id)?;
+        self.reclaim()?;
+
+        let physical = Self::write_fresh(&mut self.disk, buf)?;
+        self.fresh.insert(physical);
+        self.release(entry)?;
+        self.set_entry(page_id, physical.0 as u64);
+        Ok(())
+    }
+
+    /// Makes all changes since the last commit durable.
+    ///
+    /// Writes the changed table pages and a new root to fresh physical pages and then swaps the
+    /// root in the superblock.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::IOError`] if a write fails. If the superblock was not written,
+    /// the pages written for the new page table are released and the transaction stays open, so
+    /// it can be committed again or rolled back.
+    pub fn commit(&mut self) -> Result<(), DiskManagerError> {
+        let mut written = Vec::new();
+        let stored = self
+            .write_table(&mut written)
+            .and_then(|(root, table_pages)| {
+                let superblock = Superblock {
+                    mode: StorageMode::Shadow,
+                    epoch: self.epoch + 1,
+                    next_free: self.disk.next_free,
+                    root,
+                    ..Default::default()
+                };
+                superblock.store(&mut self.disk)?;
+                Ok((superblock.epoch, root, table_pages))
+            });
+        let (epoch, root, table_pages) = match stored {
+            Ok(stored) => stored,
+            Err(err) => {
+                // The new root is not reachable, so its table pages can be reused.
+                for page in written {
+                    self.disk.free(page)?;
+                }
+                return Err(err);
+            }
+        };
+
+        let mut retired = std::mem::take(&mut self.superseded);
+        retired.extend(
+            self.table_pages
+                .iter()
+                .enumerate()
+                .filter(|(t, _)| self.dirty_tables.contains(t))
+                .map(|(_, &page)| page),
+        );
+        if self.root != PageID(0) {
+            retired.push(self.root);
+        }
+        let previous = std::mem::replace(&mut self.committed, Rc::new(self.working.clone()));
+        self.retired.push_back((previous, retired));
+
+        self.epoch = epoch;
+        self.root = root;
+        self.table_pages = table_pages;
+        self.dirty_tables.clear();
+        self.fresh.clear();
+        self.reclaim()
+    }
+
+    /// Discards all changes since the last commit.
+    ///
+    /// # Errors
+    /// Returns [`DiskManagerError::PageFreed`] if a physical page written by the transaction
+    /// cannot be released, which indicates a bug.
+    pub fn rollback(&mut self) -> Result<(), DiskManagerError> {
+        for page in std::mem::take(&mut self.fresh) {
+            self.disk.free(page)?;
+        }
+        self.superseded.clear();
+        self.dirty_tables.clear();
+        self.working = (*self.committed).clone();
+        self.free_list = self.working.free_pages();
+        Ok(())
+    }
+
+    /// Reads the root and table pages reachable from `root`.
+    fn load_table(
+        disk: &mut DiskManager,
+        root: PageID,
+    ) -> Result<(PageTable, Vec<PageID>), DiskManagerError> {
+        let mut page = [0u8; PAGE_SIZE];
+        Self::read_physical(disk, root, &mut page)?;
+
+        let words = decode_words(&page);
+        let next_free = words[0] as usize;
+        let table_count = words[1] as usize;
+        if table_count > MAX_TABLE_PAGES || next_free > table_count * ENTRIES_PER_PAGE {
+            return Err(invalid_data("damaged shadow page table root"));
+        }
+
+        let table_pages: Vec<PageID> = words[2..2 + table_count]
+            .iter()
+            .map(|&page| PageID(page as usize))
+            .collect();
+        let mut entries = Vec::with_capacity(table_count * ENTRIES_PER_PAGE);
+        for &table_page in &table_pages {
+            Self::read_physical(disk, table_page, &mut page)?;
+            entries.extend(decode_words(&page));
+        }
+        entries.truncate(next_free.max(1));
+
+        Ok((PageTable { entries }, table_pages))
+    }
+
+    /// Writes the changed table pages and a new root to fresh physical pages.
+    ///
+    /// Every page written is pushed to `written`. Returns the new root and table pages.
+    fn write_table(
+        &mut self,
+        written: &mut Vec<PageID>,
+    ) -> Result<(PageID, Vec<PageID>), DiskManagerError> {
+        let table_count = self.working.entries.len().div_ceil(ENTRIES_PER_PAGE);
+        let mut table_pages = Vec::with_capacity(table_count);
+        for (t, chunk) in self.working.entries.chunks(ENTRIES_PER_PAGE).enumerate() {
+            match self.table_pages.get(t) {
+                Some(&page) if !self.dirty_tables.contains(&t) => table_pages.push(page),
+                _ => {
+                    let page = Self::write_fresh(&mut self.disk, &encode_words(chunk))?;
+                    written.push(page);
+                    table_pages.push(page);
+                }
+            }
+        }
+
+        let mut words = vec![self.working.entries.len() as u64, table_count as u64];
+        words.extend(table_pages.iter().map(|page| page.0 as u64));
+        let root = Self::write_fresh(&mut self.disk, &encode_words(&words))?;
+        written.push(root);
+
+        Ok((root, table_pages))
+    }
+
+    /// Writes `buf` to a newly allocated physical page.
+    fn write_fresh(disk: &mut DiskManager, buf: &RawPage) -> Result<PageID, DiskManagerError> {
+        let physical = disk.allocate()?;
+        if let Err(err) = disk.write(physical, buf) {
+            disk.free(physical)?;
+            return Err(err);
+        }
+        Ok(physical)
+    }
+
+    /// Releases the physical page of a page table entry that is being replaced.
+    ///
+    /// Pages written in the current transaction are not visible to anyone else and are freed
+    /// immediately. Committed pages are released after the next commit.
+    fn release(&mut self, entry: u64) -> Result<(), DiskManagerError> {
+        if entry == FREE || entry == UNWRITTEN {
+            return Ok(());
+        }
+
+        let physical = PageID(entry as usize);
+        if self.fresh.remove(&physical) {
+            self.disk.free(physical)?;
+        } else {
+            self.superseded.push(physical);
+        }
+        Ok(())
+    }
+
+    /// Frees physical pages retired by earlier commits whose states are no longer visible.
+    ///
+    /// A page retired by a commit may also be part of any older state, so pages are released
+    /// strictly in commit order and only if no snapshot of the state (or an older one) is alive.
+    fn reclaim(&mut self) -> Result<(), DiskManagerError> {
+        while self
+            .retired
+            .front()
+            .is_some_and(|(state, _)| Rc::strong_count(state) == 1)
+        {
+            if let Some((_, pages)) = self.retired.pop_front() {
+                for page in pages {
+                    self.disk.free(page)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Entry of `page_id` in the working page table if the page is allocated.
+    fn allocated_entry(&self, page_id: PageID) -> Result<u64, DiskManagerError> {
+        match self.working.get(page_id) {
+            FREE => Err(unallocated(page_id, self.next_free())),
+            entry => Ok(entry),
+        }
+    }
+
+    /// Updates the working page table and marks the affected table page as dirty.
+    fn set_entry(&mut self, page_id: PageID, entry: u64) {
+        self.working.entries[page_id.0] = entry;
+        self.dirty_tables.insert(page_id.0 / ENTRIES_PER_PAGE);
+    }
+
+    /// Reads the physical page referenced by `entry` into `buf`.
+    ///
+    /// `next_free` is the size of the page table that `entry` was taken from.
+    fn read_entry(
+        disk: &mut DiskManager,
+        page_id: PageID,
+        entry: u64,
+        next_free: PageID,
+        buf: &mut RawPage,
+    ) -> Result<(), DiskManagerError> {
+        match entry {
+            FREE => Err(unallocated(page_id, next_free)),
+            UNWRITTEN => {
+                buf.fill(0);
+                Ok(())
+            }
+            physical => Self::read_physical(disk, PageID(physical as usize), buf),
+        }
+    }
+
+    /// Reads physical page `physical`.
+    ///
+    /// Bypasses [`DiskManager::read`], which reads pages without a checksum as zeros. The
+    /// checksum table of the underlying disk manager is not kept, as the page table knows which
+    /// physical pages hold data.
+    fn read_physical(
+        disk: &mut DiskManager,
+        physical: PageID,
+        buf: &mut RawPage,
+    ) -> Result<(), DiskManagerError> {
+        if physical.0 == 0 || physical >= disk.next_free {
+            return Err(unallocated(physical, disk.next_free));
+        }
+        disk.read_at_or_zeros(disk.offset(physical), buf)
+            .map_err(io_error(IoOp::Read, physical))
+    }
+}
+
+/// Encodes up to [`ENTRIES_PER_PAGE`] words into a page, padding with zeros.
+fn encode_words(words: &[u64]) -> RawPage {
+    let mut page = [0u8; PAGE_SIZE];
+    for (chunk, word) in page.chunks_exact_mut(size_of::<u64>()).zip(words) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    page
+}
+
+/// Decodes all [`ENTRIES_PER_PAGE`] words of a page.
+fn decode_words(page: &RawPage) -> Vec<u64> {
+    page.chunks_exact(size_of::<u64>())
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
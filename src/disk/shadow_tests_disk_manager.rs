@@ -0,0 +1,274 @@
+// Tests for the shadow paging storage mode
+
+#[cfg(test)]
+mod shadow {
+    use crate::disk::shadow::*;
+    use crate::disk::storage::*;
+    use crate::disk::superblock::{SLOT_SIZE, Superblock};
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::os::unix::fs::FileExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory storage whose superblock writes fail while `fail_superblock` is set.
+    #[derive(Debug, Default, Clone)]
+    struct FlakySuperblock {
+        inner: Arc<Mutex<MemoryBackend>>,
+        fail_superblock: Arc<AtomicBool>,
+    }
+
+    impl StorageBackend for FlakySuperblock {
+        fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            self.inner.lock().unwrap().read_at(offset, buf)
+        }
+
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+            if offset < PAGE_SIZE as u64 && self.fail_superblock.load(Ordering::Relaxed) {
+                return Err(io::Error::other("superblock write failed"));
+            }
+            self.inner.lock().unwrap().write_at(offset, buf)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            self.inner.lock().unwrap().len()
+        }
+
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.inner.lock().unwrap().set_len(len)
+        }
+    }
+
+    #[test]
+    fn commit_and_reopen() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_shadow_reopen.dmdb";
+        {
+            let mut shadow = ShadowDiskManager::create(filename)?;
+            for i in 1..=10u8 {
+                let pid = shadow.allocate()?;
+                assert_eq!(pid, PageID(i as usize));
+                shadow.write(pid, &page(i))?;
+            }
+            shadow.free(PageID(4))?;
+            shadow.commit()?;
+        }
+
+        let mut shadow = ShadowDiskManager::open(filename)?;
+        assert_eq!(shadow.next_free(), PageID(11));
+        let mut buf = [0u8; PAGE_SIZE];
+        for i in (1..=10u8).filter(|&i| i != 4) {
+            shadow.read(PageID(i as usize), &mut buf)?;
+            assert_eq!(buf, page(i), "PID {i} differs after reopen");
+        }
+        assert!(matches!(
+            shadow.read(PageID(4), &mut buf),
+            Err(DiskManagerError::PageFreed(PageID(4)))
+        ));
+        assert_eq!(shadow.allocate()?, PageID(4));
+
+        Ok(())
+    }
+
+    #[test]
+    fn uncommitted_writes_are_discarded() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_shadow_uncommitted.dmdb";
+        {
+            let mut shadow = ShadowDiskManager::create(filename)?;
+            let pid = shadow.allocate()?;
+            shadow.write(pid, &page(1))?;
+            shadow.commit()?;
+
+            shadow.write(pid, &page(2))?;
+            let other = shadow.allocate()?;
+            shadow.write(other, &page(3))?;
+            // dropped without commit, like a crash
+        }
+
+        let mut shadow = ShadowDiskManager::open(filename)?;
+        let mut buf = [0u8; PAGE_SIZE];
+        shadow.read(PageID(1), &mut buf)?;
+        assert_eq!(buf, page(1));
+        assert_eq!(shadow.next_free(), PageID(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rollback_restores_committed_state() -> Result<(), DiskManagerError> {
+        let mut shadow = ShadowDiskManager::create("/tmp/database_shadow_rollback.dmdb")?;
+        let a = shadow.allocate()?;
+        shadow.write(a, &page(1))?;
+        shadow.commit()?;
+        let committed = shadow.physical_page(a);
+
+        shadow.write(a, &page(2))?;
+        let b = shadow.allocate()?;
+        shadow.write(b, &page(3))?;
+        shadow.free(a)?;
+        shadow.rollback()?;
+
+        let mut buf = [0u8; PAGE_SIZE];
+        shadow.read(a, &mut buf)?;
+        assert_eq!(buf, page(1));
+        assert_eq!(shadow.physical_page(a), committed);
+        assert!(shadow.read(b, &mut buf).is_err());
+        assert_eq!(shadow.next_free(), PageID(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes_go_to_fresh_physical_pages() -> Result<(), DiskManagerError> {
+        let mut shadow = ShadowDiskManager::create("/tmp/database_shadow_fresh.dmdb")?;
+        let pid = shadow.allocate()?;
+        assert_eq!(shadow.physical_page(pid), None);
+
+        let mut buf = page(7);
+        shadow.read(pid, &mut buf)?;
+        assert_eq!(buf, page(0), "Unwritten pages read as zeros");
+
+        shadow.write(pid, &page(1))?;
+        shadow.commit()?;
+        let first = shadow.physical_page(pid).unwrap();
+
+        shadow.write(pid, &page(2))?;
+        let second = shadow.physical_page(pid).unwrap();
+        assert_ne!(first, second);
+
+        // Rewriting within the same transaction frees the uncommitted page right away.
+        shadow.write(pid, &page(3))?;
+        let other = shadow.allocate()?;
+        shadow.write(other, &page(4))?;
+        assert_eq!(shadow.physical_page(other), Some(second));
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_survives_commit() -> Result<(), DiskManagerError> {
+        let mut shadow = ShadowDiskManager::create("/tmp/database_shadow_snapshot.dmdb")?;
+        let a = shadow.allocate()?;
+        let b = shadow.allocate()?;
+        shadow.write(a, &page(1))?;
+        shadow.write(b, &page(2))?;
+        shadow.commit()?;
+
+        let snapshot = shadow.snapshot();
+        let old = shadow.physical_page(a).unwrap();
+        assert_eq!(snapshot.epoch(), shadow.epoch());
+
+        // Overwrite and free pages over several commits while the snapshot is alive.
+        for round in 0..5u8 {
+            shadow.write(a, &page(10 + round))?;
+            shadow.commit()?;
+            assert_ne!(
+                shadow.physical_page(a),
+                Some(old),
+                "Page of snapshot reused"
+            );
+        }
+        shadow.free(b)?;
+        shadow.commit()?;
+
+        let mut buf = [0u8; PAGE_SIZE];
+        shadow.read_snapshot(&snapshot, a, &mut buf)?;
+        assert_eq!(buf, page(1));
+        shadow.read_snapshot(&snapshot, b, &mut buf)?;
+        assert_eq!(buf, page(2));
+        shadow.read(a, &mut buf)?;
+        assert_eq!(buf, page(14));
+
+        // Once the snapshot is gone, its pages are released with the next commit.
+        drop(snapshot);
+        shadow.commit()?;
+        let c = shadow.allocate()?;
+        shadow.write(c, &page(3))?;
+        assert_eq!(shadow.physical_page(c), Some(old));
+
+        Ok(())
+    }
+
+    #[test]
+    fn torn_superblock_falls_back_to_previous_commit() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_shadow_torn.dmdb";
+        let epoch = {
+            let mut shadow = ShadowDiskManager::create(filename)?;
+            let pid = shadow.allocate()?;
+            shadow.write(pid, &page(1))?;
+            shadow.commit()?;
+            shadow.write(pid, &page(2))?;
+            shadow.commit()?;
+            shadow.epoch()
+        };
+
+        // Destroy the copy of the newest superblock.
+        let file = OpenOptions::new().read(true).write(true).open(filename)?;
+        let newest = Superblock {
+            epoch,
+            ..Default::default()
+        };
+        file.write_all_at(&[0xFF; 16], (newest.slot() * SLOT_SIZE) as u64)?;
+
+        let mut shadow = ShadowDiskManager::open(filename)?;
+        assert_eq!(shadow.epoch(), epoch - 1);
+        let mut buf = [0u8; PAGE_SIZE];
+        shadow.read(PageID(1), &mut buf)?;
+        assert_eq!(buf, page(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_rejects_plain_files() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_shadow_plain.dmdb";
+        let mut dm = DiskManager::new(filename)?;
+        let pid = dm.allocate()?;
+        dm.write(pid, &page(1))?;
+
+        let result = ShadowDiskManager::open(filename);
+        match result {
+            Err(DiskManagerError::IOError(err)) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::InvalidData)
+            }
+            _ => panic!("Expected InvalidData error"),
+        }
+
+        Ok(())
+    }
+
+    /// Writes a page in a transaction and commits it, failing the first commit if
+    /// `fail_first_commit` is set. Returns the size of the storage afterwards.
+    fn commit_once(fail_first_commit: bool) -> Result<u64, DiskManagerError> {
+        let storage = FlakySuperblock::default();
+        let disk = DiskManager::with_backend(Box::new(storage.clone()))?;
+        let mut shadow = ShadowDiskManager::create_on(disk)?;
+        let pid = shadow.allocate()?;
+        shadow.write(pid, &page(1))?;
+
+        if fail_first_commit {
+            storage.fail_superblock.store(true, Ordering::Relaxed);
+            assert!(shadow.commit().is_err());
+            storage.fail_superblock.store(false, Ordering::Relaxed);
+        }
+        shadow.commit()?;
+
+        let mut buf = [0u8; PAGE_SIZE];
+        shadow.read(pid, &mut buf)?;
+        assert_eq!(buf, page(1));
+        Ok(storage.len()?)
+    }
+
+    #[test]
+    fn failed_commit_releases_table_pages() -> Result<(), DiskManagerError> {
+        // The retry reuses the pages written by the failed commit, so the file does not grow.
+        assert_eq!(commit_once(true)?, commit_once(false)?);
+
+        Ok(())
+    }
+}
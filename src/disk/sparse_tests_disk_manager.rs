@@ -0,0 +1,124 @@
+// Tests for zero-page elimination
+
+#[cfg(test)]
+mod sparse {
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use rand::Rng;
+    use std::os::unix::fs::MetadataExt;
+
+    fn random_page() -> RawPage {
+        let mut page = [0u8; PAGE_SIZE];
+        rand::rng().fill(&mut page[..]);
+        page
+    }
+
+    /// Bytes of disk space used by `filename`.
+    fn disk_usage(filename: &str) -> u64 {
+        std::fs::metadata(filename).unwrap().blocks() * 512
+    }
+
+    #[test]
+    fn unwritten_pages_read_as_zeros() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::temp()?;
+        let first = dm.allocate()?;
+        let second = dm.allocate()?;
+        let third = dm.allocate()?;
+        dm.write(second, &random_page())?;
+
+        let mut buf = [1u8; PAGE_SIZE];
+        dm.read(first, &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+        // Beyond the end of the file
+        buf.fill(1);
+        dm.read(third, &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_allocated_after_reopen_reads_as_zeros() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_sparse_reopen.dmdb";
+        {
+            let mut dm = DiskManager::new(filename)?;
+            for _ in 0..3 {
+                let pid = dm.allocate()?;
+                dm.write(pid, &random_page())?;
+            }
+            // Writes the checksum table to page 4.
+            dm.sync_metadata()?;
+        }
+
+        let mut dm = DiskManager::open(filename)?;
+        let pid = dm.allocate()?;
+        assert_eq!(pid, PageID(5));
+        let mut buf = [1u8; PAGE_SIZE];
+        dm.read(pid, &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+        assert!(matches!(
+            dm.read(PageID(4), &mut buf),
+            Err(DiskManagerError::PageFreed(PageID(4)))
+        ));
+
+        // The table page is reused once the new table is written.
+        dm.sync_metadata()?;
+        assert_eq!(dm.allocate()?, PageID(4));
+        dm.read(PageID(4), &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn zero_pages_release_disk_space() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_sparse_zeros.dmdb";
+        let mut dm = DiskManager::new(filename)?;
+        let pages = (0..16)
+            .map(|_| dm.allocate())
+            .collect::<Result<Vec<_>, _>>()?;
+        for &pid in &pages {
+            dm.write(pid, &random_page())?;
+        }
+        let written = disk_usage(filename);
+
+        for &pid in &pages[..8] {
+            dm.write(pid, &[0u8; PAGE_SIZE])?;
+        }
+        assert!(
+            disk_usage(filename) <= written - 8 * PAGE_SIZE as u64,
+            "disk usage {} was {written}",
+            disk_usage(filename)
+        );
+
+        let mut buf = [1u8; PAGE_SIZE];
+        dm.read(pages[3], &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+        assert_eq!(dm.checksum(pages[3]), Some(checksum::page_checksum(&buf)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn trailing_zero_page_keeps_file_length() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_sparse_trailing.dmdb";
+        {
+            let mut dm = DiskManager::new(filename)?;
+            let first = dm.allocate()?;
+            let last = dm.allocate()?;
+            dm.write(first, &random_page())?;
+            dm.write(last, &[0u8; PAGE_SIZE])?;
+            assert_eq!(std::fs::metadata(filename)?.len(), 3 * PAGE_SIZE as u64);
+            dm.sync_metadata()?;
+        }
+
+        let report = fsck::check(filename)?;
+        assert!(report.is_clean(), "{report}");
+        let mut dm = DiskManager::open(filename)?;
+        let mut buf = [1u8; PAGE_SIZE];
+        dm.read(PageID(2), &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+
+        Ok(())
+    }
+}
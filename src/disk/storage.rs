@@ -0,0 +1,397 @@
+//! Storage backends
+//!
+//! A [`DiskManager`](crate::disk::DiskManager) keeps its pages in a [`StorageBackend`], a
+//! growable array of bytes. Allocation, bounds checks, checksums and encryption live in the
+//! `DiskManager` and work the same on every backend.
+//!
+//! - [`FileBackend`] reads and writes a file with positioned system calls.
+//! - [`MemoryBackend`] keeps the bytes in RAM. Nothing survives the process.
+//! - [`MmapBackend`] maps a file into memory and copies pages from and to the mapping.
+
+use crate::disk::advise::Advice;
+use memmap2::MmapMut;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+/// A growable array of bytes that pages are stored in.
+pub trait StorageBackend: fmt::Debug + Send {
+    /// Reads exactly `buf.len()` bytes starting at byte `offset`.
+    ///
+    /// # Errors
+    /// Returns an [`io::Error`] of kind [`io::ErrorKind::UnexpectedEof`] if the range ends beyond
+    /// [`StorageBackend::len`].
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Writes all of `buf` starting at byte `offset`, growing the storage if needed.
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()>;
+
+    /// Makes all writes so far durable.
+    fn sync(&mut self) -> io::Result<()>;
+
+    /// Current size in bytes.
+    fn len(&self) -> io::Result<u64>;
+
+    /// Returns true if the storage holds no bytes.
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Truncates or extends the storage to `len` bytes. New bytes read as zeros.
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+
+    /// Allocates the space of `len` bytes starting at byte `offset` without changing
+    /// [`StorageBackend::len`], so later writes to the range cannot run out of space.
+    ///
+    /// # Errors
+    /// Returns an [`io::Error`] of kind [`io::ErrorKind::StorageFull`] if there is not enough
+    /// space. The default does nothing.
+    fn reserve(&mut self, _offset: u64, _len: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Allocates the space of `len` bytes starting at byte `offset` and extends the storage to
+    /// include them. Bytes that are already stored keep their contents.
+    ///
+    /// The default extends the storage with [`StorageBackend::set_len`] without allocating.
+    fn preallocate(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        if self.len()? < offset + len {
+            self.set_len(offset + len)?;
+        }
+        Ok(())
+    }
+
+    /// Hints how `len` bytes starting at byte `offset` are going to be accessed, see
+    /// [`crate::disk::advise`]. The default ignores the hint.
+    fn advise(&mut self, _offset: u64, _len: u64, _advice: Advice) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Makes `len` bytes starting at byte `offset` read as zeros and releases the space they take
+    /// up, without changing [`StorageBackend::len`].
+    ///
+    /// # Errors
+    /// Returns an [`io::Error`] of kind [`io::ErrorKind::Unsupported`] if the backend cannot
+    /// release space. This is the default.
+    fn punch_hole(&mut self, _offset: u64, _len: u64) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Stores bytes in a file.
+#[derive(Debug)]
+pub struct FileBackend {
+    file: File,
+}
+
+impl FileBackend {
+    /// Uses the opened `file`. Writes fail if it was opened without write access.
+    pub fn new(file: File) -> Self {
+        FileBackend { file }
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.file.read_exact_at(buf, offset)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        self.file.write_all_at(buf, offset)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+
+    fn reserve(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        fallocate(&self.file, FALLOC_KEEP_SIZE, offset, len)
+    }
+
+    fn preallocate(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        match fallocate(&self.file, FALLOC_EXTEND, offset, len) {
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                if self.len()? < offset + len {
+                    self.set_len(offset + len)?;
+                }
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+        fadvise(&self.file, offset, len, advice)
+    }
+
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        fallocate(&self.file, FALLOC_PUNCH_HOLE, offset, len)
+    }
+}
+
+/// Stores bytes in RAM.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryBackend {
+    bytes: Vec<u8>,
+}
+
+impl MemoryBackend {
+    /// Creates an empty backend.
+    pub fn new() -> Self {
+        MemoryBackend::default()
+    }
+
+    /// The stored bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let range = offset as usize..offset as usize + buf.len();
+        let bytes = self
+            .bytes
+            .get(range)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let end = offset as usize + buf.len();
+        if self.bytes.len() < end {
+            self.bytes.resize(end, 0);
+        }
+        self.bytes[offset as usize..end].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.bytes.len() as u64)
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.bytes.resize(len as usize, 0);
+        self.bytes.shrink_to_fit();
+        Ok(())
+    }
+
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        let start = (offset as usize).min(self.bytes.len());
+        let end = (offset as usize + len as usize).min(self.bytes.len());
+        self.bytes[start..end].fill(0);
+        Ok(())
+    }
+}
+
+/// Stores bytes in a file that is mapped into memory.
+///
+/// The mapping is replaced whenever the file changes its length. Other processes must not
+/// truncate the file while it is mapped.
+pub struct MmapBackend {
+    file: File,
+    /// `None` while the file is empty, as empty files cannot be mapped.
+    map: Option<MmapMut>,
+}
+
+impl MmapBackend {
+    /// Maps the opened `file`, which needs read and write access.
+    ///
+    /// # Errors
+    /// Returns an [`io::Error`] if mapping the file fails.
+    pub fn new(file: File) -> io::Result<Self> {
+        let mut backend = MmapBackend { file, map: None };
+        backend.remap()?;
+        Ok(backend)
+    }
+
+    /// Maps the file again after its length changed.
+    fn remap(&mut self) -> io::Result<()> {
+        self.map = None;
+        if self.file.metadata()?.len() > 0 {
+            // SAFETY: the file is only resized through this backend, which drops the old mapping
+            // first. Concurrent modification by other processes is documented as unsupported.
+            self.map = Some(unsafe { MmapMut::map_mut(&self.file)? });
+        }
+        Ok(())
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for MmapBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmapBackend")
+            .field("file", &self.file)
+            .field("len", &self.bytes().len())
+            .finish()
+    }
+}
+
+impl StorageBackend for MmapBackend {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let range = offset as usize..offset as usize + buf.len();
+        let bytes = self
+            .bytes()
+            .get(range)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let end = offset + buf.len() as u64;
+        if self.len()? < end {
+            self.set_len(end)?;
+        }
+        let map = self.map.as_mut().expect("a non-empty file is mapped");
+        map[offset as usize..end as usize].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        if let Some(map) = &self.map {
+            map.flush()?;
+        }
+        self.file.sync_all()
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.bytes().len() as u64)
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.map = None;
+        self.file.set_len(len)?;
+        self.remap()
+    }
+
+    fn reserve(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        fallocate(&self.file, FALLOC_KEEP_SIZE, offset, len)
+    }
+
+    fn preallocate(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        if self.len()? >= offset + len {
+            // Like `FileBackend`, storage without fallocate already holds the range.
+            return match fallocate(&self.file, FALLOC_KEEP_SIZE, offset, len) {
+                Err(err) if err.kind() == io::ErrorKind::Unsupported => Ok(()),
+                result => result,
+            };
+        }
+        self.map = None;
+        let result = match fallocate(&self.file, FALLOC_EXTEND, offset, len) {
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => self.file.set_len(offset + len),
+            result => result,
+        };
+        self.remap()?;
+        result
+    }
+
+    fn advise(&mut self, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+        fadvise(&self.file, offset, len, advice)
+    }
+
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        fallocate(&self.file, FALLOC_PUNCH_HOLE, offset, len)
+    }
+}
+
+/// Flags of [`fallocate`] that allocate space without changing the file length.
+#[cfg(target_os = "linux")]
+const FALLOC_KEEP_SIZE: i32 = libc::FALLOC_FL_KEEP_SIZE;
+/// Flags of [`fallocate`] that deallocate space without changing the file length.
+#[cfg(target_os = "linux")]
+const FALLOC_PUNCH_HOLE: i32 = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+/// Flags of [`fallocate`] that allocate space and extend the file if needed.
+const FALLOC_EXTEND: i32 = 0;
+#[cfg(not(target_os = "linux"))]
+const FALLOC_KEEP_SIZE: i32 = 0;
+#[cfg(not(target_os = "linux"))]
+const FALLOC_PUNCH_HOLE: i32 = 0;
+
+/// Calls `fallocate` with `mode` on `len` bytes starting at byte `offset` of `file`.
+///
+/// Returns an [`io::Error`] of kind [`io::ErrorKind::Unsupported`] if the file system does not
+/// support `mode`.
+#[cfg(target_os = "linux")]
+fn fallocate(file: &File, mode: i32, offset: u64, len: u64) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    // SAFETY: fallocate only accesses the open file descriptor.
+    let result = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            mode,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EOPNOTSUPP) => Err(io::ErrorKind::Unsupported.into()),
+        _ => Err(err),
+    }
+}
+
+/// Calls `fallocate` with `mode` on `len` bytes starting at byte `offset` of `file`.
+///
+/// Not available outside of Linux, always returns an [`io::Error`] of kind
+/// [`io::ErrorKind::Unsupported`].
+#[cfg(not(target_os = "linux"))]
+fn fallocate(_file: &File, _mode: i32, _offset: u64, _len: u64) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Calls `posix_fadvise` with the flag matching `advice` on `len` bytes starting at byte `offset`
+/// of `file`.
+#[cfg(target_os = "linux")]
+fn fadvise(file: &File, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let advice = match advice {
+        Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        Advice::Random => libc::POSIX_FADV_RANDOM,
+        Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+        Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+    };
+    // SAFETY: posix_fadvise only accesses the open file descriptor.
+    let result = unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            advice,
+        )
+    };
+    // posix_fadvise returns the error number instead of setting errno.
+    match result {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// Hints are not passed on outside of Linux.
+#[cfg(not(target_os = "linux"))]
+fn fadvise(_file: &File, _offset: u64, _len: u64, _advice: Advice) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,154 @@
+// Tests for the storage backends
+
+#[cfg(test)]
+mod storage {
+    use crate::disk::storage::*;
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+    use std::fs::{File, OpenOptions};
+    use std::io;
+
+    fn create_file(filename: &str) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(filename)
+    }
+
+    /// Runs the same allocate, write, read and free sequence on `dm`.
+    fn exercise(mut dm: DiskManager) -> Result<DiskManager, DiskManagerError> {
+        let pages = (0..10)
+            .map(|_| dm.allocate())
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, &pid) in pages.iter().enumerate() {
+            dm.write(pid, &[i as u8 + 1; PAGE_SIZE])?;
+        }
+        let mut buf = [0u8; PAGE_SIZE];
+        for (i, &pid) in pages.iter().enumerate() {
+            dm.read(pid, &mut buf)?;
+            assert_eq!(buf, [i as u8 + 1; PAGE_SIZE], "PID {pid} differs");
+        }
+
+        dm.free(pages[4])?;
+        assert!(matches!(
+            dm.read(pages[4], &mut buf),
+            Err(DiskManagerError::PageFreed(pid)) if pid == pages[4]
+        ));
+        assert!(matches!(
+            dm.free(pages[4]),
+            Err(DiskManagerError::PageFreed(_))
+        ));
+        assert!(matches!(
+            dm.write(PageID(100), &buf),
+            Err(DiskManagerError::OutOfRange {
+                page_id: PageID(100),
+                next_free: PageID(11)
+            })
+        ));
+        assert!(matches!(
+            dm.write(PageID(0), &buf),
+            Err(DiskManagerError::InvalidPageID(_))
+        ));
+        assert_eq!(dm.allocate()?, pages[4]);
+        dm.read(pages[4], &mut buf)?;
+
+        // Allocated beyond the end of the storage
+        let last = dm.allocate()?;
+        dm.read(last, &mut buf)?;
+        assert_eq!(buf, [0u8; PAGE_SIZE]);
+
+        dm.sync_metadata()?;
+        Ok(dm)
+    }
+
+    #[test]
+    fn memory_backend() -> Result<(), DiskManagerError> {
+        let dm = exercise(DiskManager::in_memory())?;
+        assert_eq!(dm.next_free(), PageID(12));
+        Ok(())
+    }
+
+    #[test]
+    fn file_backend_reopens() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_storage_file.dmdb";
+        let backend = FileBackend::new(create_file(filename)?);
+        exercise(DiskManager::with_backend(Box::new(backend))?)?;
+
+        let mut dm = DiskManager::open(filename)?;
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(PageID(3), &mut buf)?;
+        assert_eq!(buf, [3u8; PAGE_SIZE]);
+        Ok(())
+    }
+
+    #[test]
+    fn mmap_backend_reopens() -> Result<(), DiskManagerError> {
+        let filename = "/tmp/database_storage_mmap.dmdb";
+        let backend = MmapBackend::new(create_file(filename)?)?;
+        exercise(DiskManager::with_backend(Box::new(backend))?)?;
+
+        // Written through the mapping, read through the file and vice versa
+        let mut dm = DiskManager::open(filename)?;
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(PageID(7), &mut buf)?;
+        assert_eq!(buf, [7u8; PAGE_SIZE]);
+        dm.write(PageID(7), &[70u8; PAGE_SIZE])?;
+        dm.sync_metadata()?;
+        drop(dm);
+
+        let file = OpenOptions::new().read(true).write(true).open(filename)?;
+        let mut dm = DiskManager::open_backend(Box::new(MmapBackend::new(file)?))?;
+        dm.read(PageID(7), &mut buf)?;
+        assert_eq!(buf, [70u8; PAGE_SIZE]);
+        Ok(())
+    }
+
+    #[test]
+    fn backends_agree_on_bounds() -> io::Result<()> {
+        let mmap = MmapBackend::new(create_file("/tmp/database_storage_bounds.dmdb")?)?;
+        let backends: Vec<Box<dyn StorageBackend>> =
+            vec![Box::new(MemoryBackend::new()), Box::new(mmap)];
+        for mut backend in backends {
+            assert_eq!(backend.len()?, 0);
+            backend.write_at(10, b"hello")?;
+            assert_eq!(backend.len()?, 15);
+
+            let mut buf = [1u8; 15];
+            backend.read_at(0, &mut buf)?;
+            assert_eq!(&buf, b"\0\0\0\0\0\0\0\0\0\0hello");
+            let err = backend.read_at(12, &mut [0u8; 4]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+            backend.set_len(12)?;
+            assert_eq!(backend.len()?, 12);
+            backend.set_len(20)?;
+            let mut buf = [1u8; 10];
+            backend.read_at(10, &mut buf)?;
+            assert_eq!(&buf, b"he\0\0\0\0\0\0\0\0");
+            backend.sync()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reads_beyond_the_end_are_zeros() -> Result<(), DiskManagerError> {
+        let mmap = MmapBackend::new(create_file("/tmp/database_storage_beyond.dmdb")?)?;
+        let backends: Vec<Box<dyn StorageBackend>> =
+            vec![Box::new(MemoryBackend::new()), Box::new(mmap)];
+        for backend in backends {
+            let mut dm = DiskManager::with_backend(backend)?;
+            // Pages that end within the storage and pages that start beyond its end
+            for page_id in [PageID(0), PageID(5)] {
+                let mut buf = [1u8; PAGE_SIZE];
+                dm.read_at_or_zeros(dm.offset(page_id) + 10, &mut buf)?;
+                assert_eq!(buf[PAGE_SIZE - 10..], [0u8; 10]);
+            }
+            let mut buf = [1u8; PAGE_SIZE];
+            dm.read_at_or_zeros(dm.offset(PageID(5)), &mut buf)?;
+            assert_eq!(buf, [0u8; PAGE_SIZE]);
+        }
+        Ok(())
+    }
+}
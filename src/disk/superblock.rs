@@ -0,0 +1,170 @@
+//! Superblock of a database file
+//!
+//! Page 0 of a database file is never handed out by [`DiskManager::allocate`]. It holds the
+//! superblock, a small record describing the layout of the rest of the file.
+//!
+//! The page is split into two slots of [`SLOT_SIZE`] bytes. Every update writes the slot that
+//! does not hold the newest copy, so a torn write can only damage the copy that is being
+//! replaced. On load, the valid copy with the highest epoch wins.
+
+use crate::disk::cipher::KEY_CHECK_SIZE;
+use crate::disk::disk_manager::invalid_data;
+use crate::disk::secure_delete::SecureDelete;
+use crate::disk::{DiskManager, DiskManagerError};
+use crate::{PAGE_SIZE, PageID};
+
+/// Identifies a page 0 written by this crate.
+pub const MAGIC: [u8; 8] = *b"SDMSDB\0\x01";
+
+/// Size of one superblock copy in bytes.
+pub const SLOT_SIZE: usize = PAGE_SIZE / 2;
+
+/// Number of bytes of an encoded superblock, including its trailing checksum.
+const ENCODED_SIZE: usize = 84;
+
+/// Layout of the pages following the superblock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageMode {
+    /// Pages are stored in place at `PageID * PAGE_SIZE`.
+    #[default]
+    Plain,
+    /// Pages are mapped to physical pages through a page table, see
+    /// [`ShadowDiskManager`](crate::disk::shadow::ShadowDiskManager).
+    Shadow,
+    /// Pages are stored compressed in variable-length blobs, see
+    /// [`CompressedDiskManager`](crate::disk::compressed::CompressedDiskManager).
+    Compressed,
+}
+
+impl StorageMode {
+    fn to_u32(self) -> u32 {
+        match self {
+            StorageMode::Plain => 0,
+            StorageMode::Shadow => 1,
+            StorageMode::Compressed => 2,
+        }
+    }
+
+    fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(StorageMode::Plain),
+            1 => Some(StorageMode::Shadow),
+            2 => Some(StorageMode::Compressed),
+            _ => None,
+        }
+    }
+}
+
+/// In-memory representation of the superblock.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Superblock {
+    /// Layout of the file.
+    pub mode: StorageMode,
+    /// Incremented on every update. Decides which of the two copies is current.
+    pub epoch: u64,
+    /// `next_free` of the [`DiskManager`] that wrote the superblock.
+    pub next_free: PageID,
+    /// Entry point of mode specific metadata, `PageID(0)` if there is none.
+    pub root: PageID,
+    /// Backup epoch of the file, see [`DiskManager::incremental_backup`].
+    pub backup_epoch: u64,
+    /// Number of pages of the checksum table stored behind page `next_free - 1`, see
+    /// [`crate::disk::checksum`].
+    pub checksum_pages: u64,
+    /// Checksum of the checksum table.
+    pub checksum_crc: u32,
+    /// Bytes stored per page in addition to `PAGE_SIZE` by the cipher, 0 if the file is not
+    /// encrypted. See [`crate::disk::cipher`].
+    pub cipher_overhead: u32,
+    /// Key check value of the cipher, all zeros if the file is not encrypted.
+    pub key_check: [u8; KEY_CHECK_SIZE],
+    /// What freeing a page does with its contents, see [`crate::disk::secure_delete`].
+    pub secure_delete: SecureDelete,
+}
+
+impl Superblock {
+    /// Serializes the superblock into the beginning of `slot`.
+    pub fn encode(&self, slot: &mut [u8]) {
+        slot[0..8].copy_from_slice(&MAGIC);
+        slot[8..12].copy_from_slice(&self.mode.to_u32().to_le_bytes());
+        slot[12..20].copy_from_slice(&self.epoch.to_le_bytes());
+        slot[20..28].copy_from_slice(&(self.next_free.0 as u64).to_le_bytes());
+        slot[28..36].copy_from_slice(&(self.root.0 as u64).to_le_bytes());
+        slot[36..44].copy_from_slice(&self.backup_epoch.to_le_bytes());
+        slot[44..52].copy_from_slice(&self.checksum_pages.to_le_bytes());
+        slot[52..56].copy_from_slice(&self.checksum_crc.to_le_bytes());
+        slot[56..60].copy_from_slice(&self.cipher_overhead.to_le_bytes());
+        slot[60..76].copy_from_slice(&self.key_check);
+        slot[76..80].copy_from_slice(&self.secure_delete.to_u32().to_le_bytes());
+        let checksum = crc32fast::hash(&slot[0..ENCODED_SIZE - 4]);
+        slot[ENCODED_SIZE - 4..ENCODED_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    /// Deserializes a superblock from `slot`.
+    ///
+    /// Returns `None` if the slot does not contain a superblock or its checksum does not match.
+    pub fn decode(slot: &[u8]) -> Option<Self> {
+        if slot.len() < ENCODED_SIZE || slot[0..8] != MAGIC {
+            return None;
+        }
+        let checksum = u32::from_le_bytes(slot[ENCODED_SIZE - 4..ENCODED_SIZE].try_into().ok()?);
+        if crc32fast::hash(&slot[0..ENCODED_SIZE - 4]) != checksum {
+            return None;
+        }
+
+        let u64_at = |at: usize| u64::from_le_bytes(slot[at..at + 8].try_into().unwrap());
+        Some(Superblock {
+            mode: StorageMode::from_u32(u32::from_le_bytes(slot[8..12].try_into().ok()?))?,
+            epoch: u64_at(12),
+            next_free: PageID(u64_at(20) as usize),
+            root: PageID(u64_at(28) as usize),
+            backup_epoch: u64_at(36),
+            checksum_pages: u64_at(44),
+            checksum_crc: u32::from_le_bytes(slot[52..56].try_into().ok()?),
+            cipher_overhead: u32::from_le_bytes(slot[56..60].try_into().ok()?),
+            key_check: slot[60..76].try_into().ok()?,
+            secure_delete: SecureDelete::from_u32(u32::from_le_bytes(
+                slot[76..80].try_into().ok()?,
+            ))?,
+        })
+    }
+
+    /// Picks the current superblock out of both slots of a page 0.
+    ///
+    /// Returns `None` if neither slot holds a valid superblock.
+    pub fn from_page(page: &[u8; PAGE_SIZE]) -> Option<Self> {
+        let first = Superblock::decode(&page[..SLOT_SIZE]);
+        let second = Superblock::decode(&page[SLOT_SIZE..]);
+        match (first, second) {
+            (Some(a), Some(b)) => Some(if a.epoch >= b.epoch { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Index of the slot that this superblock is written to.
+    pub fn slot(&self) -> usize {
+        (self.epoch % 2) as usize
+    }
+
+    /// Reads the current superblock from page 0 of `disk`.
+    ///
+    /// # Errors
+    /// Returns an [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`] if page 0 holds no
+    /// valid superblock.
+    pub(crate) fn load(disk: &mut DiskManager) -> Result<Self, DiskManagerError> {
+        let mut page = [0u8; PAGE_SIZE];
+        disk.read_at(0, &mut page)?;
+        Superblock::from_page(&page).ok_or_else(|| invalid_data("no valid superblock in page 0"))
+    }
+
+    /// Writes the superblock to its slot in page 0 of `disk` and calls fsync.
+    ///
+    /// Only the slot of this superblock's epoch is written, the other copy stays intact.
+    pub(crate) fn store(&self, disk: &mut DiskManager) -> Result<(), DiskManagerError> {
+        let mut slot = [0u8; SLOT_SIZE];
+        self.encode(&mut slot);
+        disk.write_at((self.slot() * SLOT_SIZE) as u64, &slot)?;
+        disk.sync()?;
+        Ok(())
+    }
+}
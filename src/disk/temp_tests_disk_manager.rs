@@ -0,0 +1,48 @@
+// Tests for temporary databases
+
+#[cfg(test)]
+mod temp {
+    use crate::disk::*;
+    use crate::{PAGE_SIZE, PageID};
+
+    /// Temporary database files that are open in this process but no longer have a name.
+    fn unlinked_temp_files() -> usize {
+        std::fs::read_dir("/proc/self/fd")
+            .unwrap()
+            .filter_map(|entry| std::fs::read_link(entry.ok()?.path()).ok())
+            .filter(|target| {
+                let target = target.to_string_lossy();
+                target.contains("sdms-") && target.ends_with(" (deleted)")
+            })
+            .count()
+    }
+
+    #[test]
+    fn temp_database_has_no_name() -> Result<(), DiskManagerError> {
+        let mut dm = DiskManager::temp()?;
+        assert!(unlinked_temp_files() >= 1);
+
+        let pid = dm.allocate()?;
+        assert_eq!(pid, PageID(1));
+        dm.write(pid, &[7u8; PAGE_SIZE])?;
+        let mut buf = [0u8; PAGE_SIZE];
+        dm.read(pid, &mut buf)?;
+        assert_eq!(buf, [7u8; PAGE_SIZE]);
+        dm.sync_metadata()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn temp_databases_are_independent() -> Result<(), DiskManagerError> {
+        let mut a = DiskManager::temp()?;
+        let mut b = DiskManager::temp()?;
+        let pid = a.allocate()?;
+        a.write(pid, &[1u8; PAGE_SIZE])?;
+        assert!(matches!(
+            b.read(pid, &mut [0u8; PAGE_SIZE]),
+            Err(DiskManagerError::OutOfRange { .. })
+        ));
+        Ok(())
+    }
+}